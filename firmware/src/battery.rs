@@ -1,12 +1,37 @@
+use core::fmt::Write;
+
 use stm32f4xx_hal::stm32 as stm32f405;
 
-const DEAD_VOLTAGE: u16 = 2000;
-const DEAD_TIME: u32 = 5000;
+use ignore_result::Ignore;
+
+use crate::time::Duration;
+use crate::time::Instant;
+use crate::uart::Command;
+use crate::uart::Output;
+use crate::uart::Subcommand;
+
+/// The battery is measured through a 200k over 100k divider, R3 and R4
+const DIVIDER_RATIO: f64 = 3.0;
+
+/// The ADC reads 0 to 4095 over 0 to 3.3V
+const ADC_VOLTS: f64 = 3.3 / 4095.0;
+
+/// Below this the battery is dead, or not plugged in
+const DEAD_VOLTAGE: f64 = 4.8;
+const DEAD_TIME: Duration = Duration::from_millis(5000);
+
+/// How often to take a reading
+const SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+
+/// How much of the filtered voltage is kept at each reading, so the motors
+/// pulling it down for a moment don't show up
+const SMOOTHING: f64 = 0.95;
 
 pub struct Battery {
     adc: stm32f405::ADC1,
-    last_alive: Option<u32>,
-    last_update: Option<u32>,
+    voltage: Option<f64>,
+    last_alive: Option<Instant>,
+    last_update: Option<Instant>,
 }
 
 impl Battery {
@@ -28,6 +53,7 @@ impl Battery {
 
         Battery {
             adc,
+            voltage: None,
             last_alive: None,
             last_update: None,
         }
@@ -39,14 +65,38 @@ impl Battery {
         raw
     }
 
-    pub fn update(&mut self, now: u32) {
-        if self.raw() > DEAD_VOLTAGE {
+    /// The voltage of the pack from a raw reading
+    fn to_voltage(raw: u16) -> f64 {
+        raw as f64 * ADC_VOLTS * DIVIDER_RATIO
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        if let Some(last_update) = self.last_update {
+            if now - last_update < SAMPLE_PERIOD {
+                return;
+            }
+        }
+
+        let reading = Battery::to_voltage(self.raw());
+
+        let voltage = match self.voltage {
+            Some(voltage) => SMOOTHING * voltage + (1.0 - SMOOTHING) * reading,
+            None => reading,
+        };
+
+        if voltage > DEAD_VOLTAGE {
             self.last_alive = Some(now);
         }
 
+        self.voltage = Some(voltage);
         self.last_update = Some(now);
     }
 
+    /// The filtered voltage of the pack, once it has been measured
+    pub fn voltage(&self) -> Option<f64> {
+        self.voltage
+    }
+
     pub fn is_dead(&self) -> bool {
         match (self.last_alive, self.last_update) {
             (Some(alive), Some(update)) => update - alive > DEAD_TIME,
@@ -54,3 +104,42 @@ impl Battery {
         }
     }
 }
+
+const BATTERY_SUBCOMMANDS: &[Subcommand] = &[Subcommand {
+    name: "voltage",
+    args: "",
+    help: "show the filtered and raw battery voltage",
+}];
+
+impl Command for Battery {
+    fn keyword_command(&self) -> &str {
+        "battery"
+    }
+
+    fn subcommands(&self) -> &[Subcommand] {
+        BATTERY_SUBCOMMANDS
+    }
+
+    fn handle_command<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        out: &mut Output,
+        mut args: I,
+    ) {
+        match args.next() {
+            Some("voltage") => match self.voltage {
+                Some(voltage) => writeln!(
+                    out,
+                    "battery: {:.2} V raw: {:.2} V",
+                    voltage,
+                    Battery::to_voltage(self.raw())
+                )
+                .ignore(),
+                None => writeln!(out, "battery: not measured yet").ignore(),
+            },
+            Some(c) => {
+                writeln!(out, "battery: unknown command: {}", c).ignore()
+            }
+            None => writeln!(out, "battery: no command").ignore(),
+        }
+    }
+}
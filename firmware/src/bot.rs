@@ -1,9 +1,5 @@
 use core::fmt::Write;
 
-use stm32f4xx_hal::gpio::{gpioa, gpiob, gpioc, Alternate, AF4};
-use stm32f4xx_hal::i2c::I2c;
-use stm32f4xx_hal::stm32 as stm32f405;
-
 use ignore_result::Ignore;
 
 use pid_control::Controller;
@@ -14,36 +10,44 @@ use crate::motors::left::LeftMotor;
 use crate::motors::right::RightEncoder;
 use crate::motors::right::RightMotor;
 
+use crate::motors::Decay;
 use crate::motors::Encoder;
 use crate::motors::Motor;
+use crate::motors::NOMINAL_VOLTAGE;
 
-use crate::vl6180x::VL6180x;
+use crate::characterize::Characterize;
+use crate::characterize::MotorModel;
+use crate::filter::DistanceFilter;
+use crate::filter::EdgeDetector;
+use crate::filter::WallEdge;
+use crate::sensors::Reading;
+use crate::sensors::Readings;
 
+use crate::uart;
 use crate::uart::Command;
-use crate::uart::Uart;
+use crate::uart::Output;
+use crate::uart::Subcommand;
 
 use crate::config::BotConfig;
 
-type FrontDistance = VL6180x<
-    I2c<
-        stm32f405::I2C1,
-        (gpiob::PB8<Alternate<AF4>>, gpiob::PB9<Alternate<AF4>>),
-    >,
->;
-
-type LeftDistance = VL6180x<
-    I2c<
-        stm32f405::I2C2,
-        (gpiob::PB10<Alternate<AF4>>, gpiob::PB11<Alternate<AF4>>),
-    >,
->;
-
-type RightDistance = VL6180x<
-    I2c<
-        stm32f405::I2C3,
-        (gpioa::PA8<Alternate<AF4>>, gpioc::PC9<Alternate<AF4>>),
-    >,
->;
+use crate::time;
+use crate::time::Instant;
+
+/// How many updates back the wheel velocities are measured from
+///
+/// At 1 kHz a single update only sees a few encoder ticks, so the velocity
+/// is taken over the last 10 ms to keep it from being too coarse.
+const VELOCITY_WINDOW: usize = 10;
+
+/// The wheel positions and target velocities at an update
+#[derive(Copy, Clone)]
+struct Sample {
+    left_pos: f64,
+    right_pos: f64,
+    left_target: f64,
+    right_target: f64,
+    time: Instant,
+}
 
 pub struct Bot {
     left_pid: PIDController,
@@ -51,20 +55,36 @@ pub struct Bot {
     left_encoder: LeftEncoder,
     left_velocity: f64,
     left_power: f64,
-    last_left_pos: f64,
 
     right_pid: PIDController,
     right_motor: RightMotor,
     right_encoder: RightEncoder,
     right_velocity: f64,
     right_power: f64,
-    last_right_pos: f64,
 
-    front_distance: FrontDistance,
-    left_distance: LeftDistance,
-    right_distance: RightDistance,
+    // The clock the motor timers count, and the PWM setup they were given
+    timer_clock: u32,
+    pwm_frequency: u32,
+    brake: bool,
+
+    samples: [Sample; VELOCITY_WINDOW],
+    next_sample: usize,
+
+    front_filter: DistanceFilter,
+    left_filter: DistanceFilter,
+    right_filter: DistanceFilter,
+    left_edges: EdgeDetector,
+    right_edges: EdgeDetector,
+
+    linear_offset: f64,
+
+    battery_voltage: Option<f64>,
+    power_limit: f64,
 
-    last_update: u32,
+    characterize: Option<Characterize>,
+    calibrate_sensors: bool,
+
+    last_update: Instant,
 
     pub config: BotConfig,
 }
@@ -75,9 +95,7 @@ impl Bot {
         left_encoder: LeftEncoder,
         right_motor: RightMotor,
         right_encoder: RightEncoder,
-        mut front_distance: FrontDistance,
-        mut left_distance: LeftDistance,
-        mut right_distance: RightDistance,
+        timer_clock: u32,
         config: BotConfig,
     ) -> Bot {
         let mut left_pid =
@@ -88,9 +106,13 @@ impl Bot {
             PIDController::new(config.right_p, config.right_i, config.right_d);
         right_pid.set_limits(-5000.0, 5000.0);
 
-        front_distance.start_ranging();
-        left_distance.start_ranging();
-        right_distance.start_ranging();
+        let sample = Sample {
+            left_pos: 0.0,
+            right_pos: 0.0,
+            left_target: 0.0,
+            right_target: 0.0,
+            time: Instant::START,
+        };
 
         Bot {
             left_pid,
@@ -98,17 +120,27 @@ impl Bot {
             left_encoder,
             left_velocity: 0.0,
             left_power: 0.0,
-            last_left_pos: 0.0,
             right_pid,
             right_motor,
             right_encoder,
             right_velocity: 0.0,
             right_power: 0.0,
-            front_distance,
-            left_distance,
-            right_distance,
-            last_right_pos: 0.0,
-            last_update: 0,
+            timer_clock,
+            pwm_frequency: 0,
+            brake: false,
+            samples: [sample; VELOCITY_WINDOW],
+            next_sample: 0,
+            front_filter: DistanceFilter::new(),
+            left_filter: DistanceFilter::new(),
+            right_filter: DistanceFilter::new(),
+            left_edges: EdgeDetector::new(),
+            right_edges: EdgeDetector::new(),
+            linear_offset: 0.0,
+            battery_voltage: None,
+            power_limit: 1.0,
+            characterize: None,
+            calibrate_sensors: false,
+            last_update: Instant::START,
             config,
         }
     }
@@ -129,64 +161,313 @@ impl Bot {
         }
     }
 
-    pub fn update(&mut self, now: u32) {
-        let delta_time = now - self.last_update;
+    /**
+     *  Update the wheel velocity controllers
+     *
+     *  This is the fast loop, run from the scheduler's interrupt every
+     *  millisecond, so it only touches the encoders and motors.
+     */
+    pub fn update(&mut self, now: Instant) {
+        let delta_time = (now - self.last_update).as_millis_f64();
+
+        self.update_pwm();
+
+        let left_pos = self.left_pos();
+        let right_pos = self.right_pos();
+        let left_target = self.left_target();
+        let right_target = self.right_target();
+
+        // The oldest sample is the one about to be replaced
+        let oldest = self.samples[self.next_sample];
+        self.samples[self.next_sample] = Sample {
+            left_pos,
+            right_pos,
+            left_target,
+            right_target,
+            time: now,
+        };
+        self.next_sample = (self.next_sample + 1) % VELOCITY_WINDOW;
+
+        let window = (now - oldest.time).as_millis_f64();
+
+        let (left_accel, right_accel) = if window > 0.0 {
+            self.left_velocity = (left_pos - oldest.left_pos) / window;
+            self.right_velocity = (right_pos - oldest.right_pos) / window;
+
+            (
+                (left_target - oldest.left_target) / window,
+                (right_target - oldest.right_target) / window,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        if self.update_characterize(now) {
+            self.last_update = now;
+            return;
+        }
 
-        self.front_distance.update();
-        self.left_distance.update();
-        self.right_distance.update();
+        self.left_pid.p_gain = self.config.left_p;
+        self.left_pid.i_gain = self.config.left_i;
+        self.left_pid.d_gain = self.config.left_d;
 
-        if delta_time > 10 {
-            self.left_pid.p_gain = self.config.left_p;
-            self.left_pid.i_gain = self.config.left_i;
-            self.left_pid.d_gain = self.config.left_d;
+        self.left_power = self.left_pid.update(self.left_velocity, delta_time)
+            + self.left_model().feedforward(left_target, left_accel);
 
-            let left_pos = self.left_pos();
+        self.left_motor
+            .change_power((self.left_power * self.power_limit) as i32);
 
-            self.left_velocity =
-                (left_pos - self.last_left_pos) / delta_time as f64;
+        self.right_pid.p_gain = self.config.right_p;
+        self.right_pid.i_gain = self.config.right_i;
+        self.right_pid.d_gain = self.config.right_d;
 
-            self.left_power =
-                self.left_pid.update(self.left_velocity, delta_time as f64);
+        self.right_power =
+            self.right_pid.update(self.right_velocity, delta_time)
+                + self.right_model().feedforward(right_target, right_accel);
 
-            self.left_motor.change_power(self.left_power as i32);
+        self.right_motor
+            .change_power((self.right_power * self.power_limit) as i32);
 
-            self.last_left_pos = left_pos;
+        self.last_update = now;
+    }
 
-            self.right_pid.p_gain = self.config.right_p;
-            self.right_pid.i_gain = self.config.right_i;
-            self.right_pid.d_gain = self.config.right_d;
+    /// Follow changes to the PWM setup in the config
+    fn update_pwm(&mut self) {
+        if self.config.pwm_frequency != self.pwm_frequency {
+            let frequency = self.config.pwm_frequency;
+            self.left_motor.set_frequency(self.timer_clock, frequency);
+            self.right_motor.set_frequency(self.timer_clock, frequency);
+            self.pwm_frequency = frequency;
+        }
 
-            let right_pos = self.right_pos();
+        if self.config.brake != self.brake {
+            let decay = if self.config.brake {
+                Decay::Slow
+            } else {
+                Decay::Fast
+            };
+            self.left_motor.set_decay(decay);
+            self.right_motor.set_decay(decay);
+            self.brake = self.config.brake;
+        }
+    }
 
-            self.right_velocity =
-                (right_pos - self.last_right_pos) / delta_time as f64;
+    /**
+     *  Drive the motors for the characterization, if it is running
+     *
+     *  Returns true if it is, in which case the velocity controllers are
+     *  left out. Once it finishes, the fitted models go into the config,
+     *  which only lasts until a reset. `bot model defaults` prints them to
+     *  be pasted into the defaults in main.
+     */
+    fn update_characterize(&mut self, now: Instant) -> bool {
+        let powers = match self.characterize.as_mut() {
+            Some(characterize) if characterize.is_running() => characterize
+                .update(now, self.left_velocity, self.right_velocity),
+            _ => return false,
+        };
+
+        match powers {
+            Some((left_power, right_power)) => {
+                self.left_power = left_power;
+                self.right_power = right_power;
+            }
+            None => {
+                if let Some((left, right)) =
+                    self.characterize.as_ref().and_then(|c| c.models())
+                {
+                    self.set_models(left, right);
+                }
 
-            self.right_power = self
-                .right_pid
-                .update(self.right_velocity, delta_time as f64);
+                self.left_power = 0.0;
+                self.right_power = 0.0;
+                self.change_velocity(0.0, 0.0);
+            }
+        }
 
-            self.right_motor.change_power(self.right_power as i32);
+        self.left_motor
+            .change_power((self.left_power * self.power_limit) as i32);
+        self.right_motor
+            .change_power((self.right_power * self.power_limit) as i32);
 
-            self.last_right_pos = right_pos;
+        true
+    }
 
-            self.last_update = now;
+    /**
+     *  Start working out models of the motors, which takes a few seconds
+     *
+     *  The wheels should be off the ground, since they are driven forward
+     *  and backward at a range of powers.
+     */
+    pub fn characterize(&mut self, now: Instant) {
+        self.change_velocity(0.0, 0.0);
+        self.characterize = Some(Characterize::new(now));
+    }
+
+    pub fn is_characterizing(&self) -> bool {
+        self.characterize.as_ref().map_or(false, |c| c.is_running())
+    }
+
+    /**
+     *  Ask for the distance sensors to be calibrated
+     *
+     *  They are run from the main loop rather than by the bot, so the
+     *  calibration is done there, once it sees `take_calibrate_sensors`.
+     */
+    pub fn calibrate_sensors(&mut self) {
+        self.calibrate_sensors = true;
+    }
+
+    /// Whether the sensors have been asked to be calibrated since this was
+    /// last called
+    pub fn take_calibrate_sensors(&mut self) -> bool {
+        let calibrate = self.calibrate_sensors;
+        self.calibrate_sensors = false;
+        calibrate
+    }
+
+    /// Write the models, or as fields of the `BotConfig` if `defaults`
+    fn write_models(&self, out: &mut Output, defaults: bool) {
+        match self.characterize {
+            Some(ref c) if c.is_running() => {
+                let (step, steps) = c.progress();
+                writeln!(
+                    out,
+                    "bot: characterizing: step {} of {}",
+                    step, steps
+                )
+                .ignore();
+            }
+            Some(ref c) if c.has_failed() => {
+                writeln!(out, "bot: characterizing failed, wheels stuck?")
+                    .ignore();
+            }
+            _ if defaults => {
+                write_model_defaults(out, "left", &self.left_model());
+                write_model_defaults(out, "right", &self.right_model());
+            }
+            _ => {
+                write_model(out, "left", &self.left_model());
+                write_model(out, "right", &self.right_model());
+            }
         }
     }
 
+    fn left_model(&self) -> MotorModel {
+        MotorModel {
+            kv: self.config.left_kv,
+            forward_deadband: self.config.left_forward_deadband,
+            backward_deadband: self.config.left_backward_deadband,
+            tau: self.config.left_tau,
+        }
+    }
+
+    fn right_model(&self) -> MotorModel {
+        MotorModel {
+            kv: self.config.right_kv,
+            forward_deadband: self.config.right_forward_deadband,
+            backward_deadband: self.config.right_backward_deadband,
+            tau: self.config.right_tau,
+        }
+    }
+
+    fn set_models(&mut self, left: MotorModel, right: MotorModel) {
+        self.config.left_kv = left.kv;
+        self.config.left_forward_deadband = left.forward_deadband;
+        self.config.left_backward_deadband = left.backward_deadband;
+        self.config.left_tau = left.tau;
+
+        self.config.right_kv = right.kv;
+        self.config.right_forward_deadband = right.forward_deadband;
+        self.config.right_backward_deadband = right.backward_deadband;
+        self.config.right_tau = right.tau;
+    }
+
+    /// Feed new measurements from the distance sensors through the filters,
+    /// and look for the ends of side walls in them
+    pub fn add_readings(&mut self, readings: &Readings) {
+        add_reading(&mut self.front_filter, readings.front, &self.config);
+        add_reading(&mut self.left_filter, readings.left, &self.config);
+        add_reading(&mut self.right_filter, readings.right, &self.config);
+
+        let linear_pos = self.linear_pos();
+
+        if let Some(reading) = readings.left {
+            self.left_edges.add(reading.range, linear_pos, &self.config);
+        }
+
+        if let Some(reading) = readings.right {
+            self.right_edges
+                .add(reading.range, linear_pos, &self.config);
+        }
+    }
+
+    /// Where the wall on the left last started or ended, if anywhere
+    /// since this was last called
+    pub fn take_left_edge(&mut self) -> Option<WallEdge> {
+        self.left_edges.take()
+    }
+
+    /// Where the wall on the right last started or ended, if anywhere
+    /// since this was last called
+    pub fn take_right_edge(&mut self) -> Option<WallEdge> {
+        self.right_edges.take()
+    }
+
+    /**
+     *  Compensate the motor power for the battery voltage
+     *
+     *  None leaves the power as it is, for when there is no battery to go
+     *  by, like a board running off USB.
+     */
+    pub fn set_battery_voltage(&mut self, voltage: Option<f64>) {
+        let motor_voltage = voltage.unwrap_or(NOMINAL_VOLTAGE);
+        self.left_motor.set_voltage(motor_voltage);
+        self.right_motor.set_voltage(motor_voltage);
+        self.battery_voltage = voltage;
+    }
+
+    /**
+     *  Scale the motor power down, from 1 for full power to 0 for off
+     *
+     *  This is for shutting the motors down gently, so it applies on top
+     *  of the velocity controllers rather than through them.
+     */
+    pub fn set_power_limit(&mut self, limit: f64) {
+        self.power_limit = limit.max(0.0).min(1.0);
+    }
+
     pub fn reset(&mut self) {
-        self.last_left_pos = 0.0;
         self.left_encoder.reset();
-
-        self.last_right_pos = 0.0;
         self.right_encoder.reset();
 
+        for sample in self.samples.iter_mut() {
+            sample.left_pos = 0.0;
+            sample.right_pos = 0.0;
+        }
+
         self.left_pid.reset();
         self.right_pid.reset();
+
+        self.left_edges.reset();
+        self.right_edges.reset();
+
+        self.linear_offset = 0.0;
+    }
+
+    /**
+     *  Correct the linear position by `ticks`
+     *
+     *  This is used when the position is known from the maze, such as
+     *  when passing a wall post. It does not change the encoder counts,
+     *  so the wheel velocities are not disturbed.
+     */
+    pub fn shift_linear(&mut self, ticks: f64) {
+        self.linear_offset += ticks;
     }
 
     pub fn linear_pos(&self) -> f64 {
-        (self.left_pos() + self.right_pos()) / 2.0
+        (self.left_pos() + self.right_pos()) / 2.0 + self.linear_offset
     }
 
     pub fn spin_pos(&self) -> f64 {
@@ -233,33 +514,135 @@ impl Bot {
         self.right_power
     }
 
-    pub fn front_distance(&self) -> f64 {
-        self.front_distance.range() as f64
+    /// The last battery voltage the motors were told about
+    pub fn battery_voltage(&self) -> Option<f64> {
+        self.battery_voltage
     }
 
-    pub fn left_distance(&self) -> f64 {
-        self.left_distance.range() as f64
+    /// The filtered front distance in mm, or None if nothing is in range
+    pub fn front_distance(&self) -> Option<f64> {
+        self.front_filter.value()
     }
 
-    pub fn right_distance(&self) -> f64 {
-        self.right_distance.range() as f64
+    /// The filtered left distance in mm, or None if nothing is in range
+    pub fn left_distance(&self) -> Option<f64> {
+        self.left_filter.value()
+    }
+
+    /// The filtered right distance in mm, or None if nothing is in range
+    pub fn right_distance(&self) -> Option<f64> {
+        self.right_filter.value()
+    }
+}
+
+/// Feed a new measurement, valid or not, through a distance filter
+fn add_reading(
+    filter: &mut DistanceFilter,
+    reading: Option<Reading>,
+    config: &BotConfig,
+) {
+    if let Some(reading) = reading {
+        filter.add(reading.range, reading.time, config);
     }
 }
 
+fn write_model(out: &mut Output, name: &str, model: &MotorModel) {
+    writeln!(
+        out,
+        "{}: kv: {} deadband: {} {} tau: {}",
+        name,
+        model.kv,
+        model.forward_deadband,
+        model.backward_deadband,
+        model.tau
+    )
+    .ignore();
+}
+
+/// Write a model the way the `BotConfig` in main sets it, as Rust
+fn write_model_defaults(out: &mut Output, name: &str, model: &MotorModel) {
+    // Debug keeps the decimal point, so whole numbers are still floats
+    writeln!(out, "        {}_kv: {:?},", name, model.kv).ignore();
+    writeln!(
+        out,
+        "        {}_forward_deadband: {:?},",
+        name, model.forward_deadband
+    )
+    .ignore();
+    writeln!(
+        out,
+        "        {}_backward_deadband: {:?},",
+        name, model.backward_deadband
+    )
+    .ignore();
+    writeln!(out, "        {}_tau: {:?},", name, model.tau).ignore();
+}
+
+const BOT_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "config",
+        args: "...",
+        help: "show all the settings, or set or show one",
+    },
+    Subcommand {
+        name: "spin",
+        args: "<velocity>",
+        help: "spin at a velocity, in ticks/ms",
+    },
+    Subcommand {
+        name: "linear",
+        args: "<velocity>",
+        help: "move straight at a velocity, in ticks/ms",
+    },
+    Subcommand {
+        name: "characterize",
+        args: "",
+        help: "fit models of the motors, with the wheels off the ground",
+    },
+    Subcommand {
+        name: "model",
+        args: "[defaults]",
+        help: "show the motor models or the fit, or the models as Rust",
+    },
+    Subcommand {
+        name: "calibrate",
+        args: "sensors",
+        help: "calibrate the distance sensors, like sensors calibrate",
+    },
+];
+
 impl Command for Bot {
     fn keyword_command(&self) -> &str {
         "bot"
     }
 
+    fn subcommands(&self) -> &[Subcommand] {
+        BOT_SUBCOMMANDS
+    }
+
+    fn help_command<'a, I: Iterator<Item = &'a str>>(
+        &self,
+        out: &mut Output,
+        mut path: I,
+    ) {
+        let command = path.next();
+
+        if command == Some(self.config.keyword_command()) {
+            self.config.help_command(out, path);
+        } else {
+            uart::write_help(out, "bot", BOT_SUBCOMMANDS, command);
+        }
+    }
+
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         mut args: I,
     ) {
         let command = args.next();
 
         if command == Some(self.config.keyword_command()) {
-            self.config.handle_command(uart, args);
+            self.config.handle_command(out, args);
         } else {
             match command {
                 Some("spin") => {
@@ -268,7 +651,7 @@ impl Command for Bot {
                     {
                         self.change_velocity(0.0, spin_vel);
                     } else {
-                        writeln!(uart, "bot: value needed").ignore();
+                        writeln!(out, "bot: value needed").ignore();
                     }
                 }
                 Some("linear") => {
@@ -277,13 +660,29 @@ impl Command for Bot {
                     {
                         self.change_velocity(linear_vel, 0.0);
                     } else {
-                        writeln!(uart, "bot: value needed").ignore();
+                        writeln!(out, "bot: value needed").ignore();
                     }
                 }
+                Some("characterize") => {
+                    self.characterize(time::now());
+                    writeln!(out, "bot: characterizing").ignore();
+                }
+                Some("model") => match args.next() {
+                    Some("defaults") => self.write_models(out, true),
+                    Some(a) => {
+                        writeln!(out, "bot: model: unknown argument: {}", a)
+                            .ignore()
+                    }
+                    None => self.write_models(out, false),
+                },
+                Some("calibrate") => match args.next() {
+                    Some("sensors") => self.calibrate_sensors(),
+                    _ => writeln!(out, "bot: calibrate: only sensors").ignore(),
+                },
                 Some(c) => {
-                    writeln!(uart, "bot: unknown command: {}", c).ignore()
+                    writeln!(out, "bot: unknown command: {}", c).ignore()
                 }
-                None => writeln!(uart, "bot: no command").ignore(),
+                None => writeln!(out, "bot: no command").ignore(),
             }
         }
     }
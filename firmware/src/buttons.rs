@@ -0,0 +1,117 @@
+use stm32f4xx_hal::gpio::{gpioc, Input, PullUp};
+use stm32f4xx_hal::prelude::*;
+
+use crate::time::Duration;
+use crate::time::Instant;
+
+/// How long a button has to settle before a change counts
+const DEBOUNCE_TIME: Duration = Duration::from_millis(20);
+
+/// Held for this long, a press is a long press
+const LONG_PRESS_TIME: Duration = Duration::from_millis(600);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Press {
+    Short,
+    Long,
+}
+
+/// Debounces a button, and tells short presses from long ones
+pub struct Button {
+    pressed: bool,
+    last_reading: bool,
+    last_change: Instant,
+    press_start: Instant,
+    long_sent: bool,
+}
+
+impl Button {
+    pub fn new() -> Button {
+        Button {
+            pressed: false,
+            last_reading: false,
+            last_change: Instant::START,
+            press_start: Instant::START,
+            long_sent: false,
+        }
+    }
+
+    /**
+     *  Take the latest reading of the button, true if it is down
+     *
+     *  A short press is given when the button is let go, and a long press
+     *  as soon as it has been held long enough, so that the bot can react
+     *  without waiting. Letting go after a long press gives nothing more.
+     */
+    pub fn update(&mut self, now: Instant, reading: bool) -> Option<Press> {
+        if reading != self.last_reading {
+            self.last_reading = reading;
+            self.last_change = now;
+        }
+
+        if reading != self.pressed && now - self.last_change >= DEBOUNCE_TIME {
+            self.pressed = reading;
+
+            if reading {
+                self.press_start = now;
+                self.long_sent = false;
+            } else if !self.long_sent {
+                return Some(Press::Short);
+            }
+        }
+
+        if self.pressed
+            && !self.long_sent
+            && now - self.press_start >= LONG_PRESS_TIME
+        {
+            self.long_sent = true;
+            return Some(Press::Long);
+        }
+
+        None
+    }
+}
+
+/// The presses on each button since the last update
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Presses {
+    pub left: Option<Press>,
+    pub middle: Option<Press>,
+    pub right: Option<Press>,
+}
+
+/// The three buttons, which pull their pins low when pressed
+pub struct Buttons {
+    left_pin: gpioc::PC10<Input<PullUp>>,
+    middle_pin: gpioc::PC11<Input<PullUp>>,
+    right_pin: gpioc::PC12<Input<PullUp>>,
+    left: Button,
+    middle: Button,
+    right: Button,
+}
+
+impl Buttons {
+    pub fn new(
+        left_pin: gpioc::PC10<Input<PullUp>>,
+        middle_pin: gpioc::PC11<Input<PullUp>>,
+        right_pin: gpioc::PC12<Input<PullUp>>,
+    ) -> Buttons {
+        Buttons {
+            left_pin,
+            middle_pin,
+            right_pin,
+            left: Button::new(),
+            middle: Button::new(),
+            right: Button::new(),
+        }
+    }
+
+    /// Read the buttons, which should be done every few milliseconds
+    pub fn update(&mut self, now: Instant) -> Presses {
+        Presses {
+            left: self.left.update(now, self.left_pin.is_low()),
+            middle: self.middle.update(now, self.middle_pin.is_low()),
+            right: self.right.update(now, self.right_pin.is_low()),
+        }
+    }
+}
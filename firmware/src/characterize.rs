@@ -0,0 +1,343 @@
+use crate::time::Duration;
+use crate::time::Instant;
+
+/// The power added at each step of the sweep
+const STEP_POWER: f64 = 400.0;
+
+/// How many steps of the sweep go each way
+const STEPS: usize = 10;
+
+/// How long each step of the sweep is held
+const STEP_TIME: Duration = Duration::from_millis(400);
+
+/// How much of the end of each step the velocity is averaged over, by
+/// which time it should have settled
+const MEASURE_TIME: Duration = Duration::from_millis(200);
+
+/// How long to let the wheels stop before the step response
+const REST_TIME: Duration = Duration::from_millis(1000);
+
+/// The power of the step response
+const RESPONSE_POWER: f64 = STEP_POWER * (STEPS / 2) as f64;
+
+/// How long to wait for the step response to get most of the way there
+const RESPONSE_TIME: Duration = Duration::from_millis(1000);
+
+/// Slower than this in ticks/ms, a wheel is taken to be stuck
+const MIN_VELOCITY: f64 = 0.05;
+
+/// The velocities are measured over the last 10ms, which holds them back by
+/// about half of that
+const VELOCITY_LAG: f64 = 5.0;
+
+/**
+ *  A simple model of a motor, with `velocity = kv * (power - deadband)`
+ *  once it settles, and a first order response getting there
+ */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MotorModel {
+    /// The velocity in ticks/ms for each unit of power past the deadband
+    pub kv: f64,
+
+    /// The power the motor needs to start turning, each way
+    pub forward_deadband: f64,
+    pub backward_deadband: f64,
+
+    /// How long the motor takes to get 63% of the way to a new velocity,
+    /// in ms
+    pub tau: f64,
+}
+
+impl MotorModel {
+    /**
+     *  The power that should drive the motor at `velocity` while
+     *  accelerating at `acceleration`, in ticks/ms/ms
+     *
+     *  A model with no `kv` hasn't been fitted, and gives no power.
+     */
+    pub fn feedforward(&self, velocity: f64, acceleration: f64) -> f64 {
+        if self.kv <= 0.0 || velocity == 0.0 {
+            return 0.0;
+        }
+
+        let deadband = if velocity > 0.0 {
+            self.forward_deadband
+        } else {
+            -self.backward_deadband
+        };
+
+        deadband + (velocity + self.tau * acceleration) / self.kv
+    }
+
+    /// The velocity the model settles at with `power`
+    fn velocity(&self, power: f64) -> f64 {
+        if power > self.forward_deadband {
+            self.kv * (power - self.forward_deadband)
+        } else if power < -self.backward_deadband {
+            self.kv * (power + self.backward_deadband)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The power of a step of the sweep, forward then backward
+fn step_power(step: usize) -> f64 {
+    if step < STEPS {
+        (step + 1) as f64 * STEP_POWER
+    } else {
+        -((step - STEPS + 1) as f64) * STEP_POWER
+    }
+}
+
+/**
+ *  Fit a line through the points where the wheel turned, returning the
+ *  slope and the power where it crosses zero velocity
+ */
+fn fit<I: Iterator<Item = (f64, f64)>>(points: I) -> Option<(f64, f64)> {
+    let mut n = 0.0;
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    let mut sum_xx = 0.0;
+    let mut sum_xy = 0.0;
+
+    for (x, y) in points.filter(|&(_, y)| y.abs() > MIN_VELOCITY) {
+        n += 1.0;
+        sum_x += x;
+        sum_y += y;
+        sum_xx += x * x;
+        sum_xy += x * y;
+    }
+
+    let denominator = n * sum_xx - sum_x * sum_x;
+
+    if n < 2.0 || denominator == 0.0 {
+        return None;
+    }
+
+    let slope = (n * sum_xy - sum_x * sum_y) / denominator;
+    let intercept = (sum_y - slope * sum_x) / n;
+
+    if slope <= 0.0 {
+        return None;
+    }
+
+    Some((slope, -intercept / slope))
+}
+
+/// Fit a model to the steady velocities of each step of the sweep
+fn fit_model(velocities: &[f64; 2 * STEPS]) -> Option<MotorModel> {
+    let points = |range: core::ops::Range<usize>| {
+        range.map(move |step| (step_power(step), velocities[step]))
+    };
+
+    let (forward_kv, forward_zero) = fit(points(0..STEPS))?;
+    let (backward_kv, backward_zero) = fit(points(STEPS..2 * STEPS))?;
+
+    Some(MotorModel {
+        kv: (forward_kv + backward_kv) / 2.0,
+        forward_deadband: forward_zero.max(0.0),
+        backward_deadband: (-backward_zero).max(0.0),
+        tau: 0.0,
+    })
+}
+
+#[derive(Copy, Clone)]
+enum Phase {
+    Sweep {
+        step: usize,
+        start: Instant,
+        left_total: f64,
+        right_total: f64,
+        samples: u32,
+    },
+    Rest(Instant),
+    Response {
+        start: Instant,
+        left_done: bool,
+        right_done: bool,
+    },
+    Done,
+    Failed,
+}
+
+/**
+ *  Works out a model of each motor by driving them at fixed powers
+ *
+ *  The powers are swept up forward and then backward, and the velocity
+ *  each settles at is fitted to get the deadbands and velocity constant.
+ *  The time constant comes from timing a step from rest afterwards. This
+ *  is meant to be run with the wheels off the ground.
+ */
+pub struct Characterize {
+    phase: Phase,
+    left_velocities: [f64; 2 * STEPS],
+    right_velocities: [f64; 2 * STEPS],
+    left_model: MotorModel,
+    right_model: MotorModel,
+}
+
+impl Characterize {
+    pub fn new(now: Instant) -> Characterize {
+        Characterize {
+            phase: Phase::Sweep {
+                step: 0,
+                start: now,
+                left_total: 0.0,
+                right_total: 0.0,
+                samples: 0,
+            },
+            left_velocities: [0.0; 2 * STEPS],
+            right_velocities: [0.0; 2 * STEPS],
+            left_model: MotorModel::default(),
+            right_model: MotorModel::default(),
+        }
+    }
+
+    /**
+     *  Take the latest wheel velocities, returning the powers to drive the
+     *  wheels at, or None once it is finished
+     */
+    pub fn update(
+        &mut self,
+        now: Instant,
+        left_velocity: f64,
+        right_velocity: f64,
+    ) -> Option<(f64, f64)> {
+        match self.phase {
+            Phase::Sweep {
+                step,
+                start,
+                left_total,
+                right_total,
+                samples,
+            } => {
+                let elapsed = now - start;
+
+                if elapsed >= STEP_TIME {
+                    let samples = samples.max(1) as f64;
+                    self.left_velocities[step] = left_total / samples;
+                    self.right_velocities[step] = right_total / samples;
+
+                    if step + 1 < 2 * STEPS {
+                        self.phase = Phase::Sweep {
+                            step: step + 1,
+                            start: now,
+                            left_total: 0.0,
+                            right_total: 0.0,
+                            samples: 0,
+                        };
+                    } else {
+                        self.finish_sweep(now);
+                        return self.powers();
+                    }
+                } else if elapsed >= STEP_TIME - MEASURE_TIME {
+                    self.phase = Phase::Sweep {
+                        step,
+                        start,
+                        left_total: left_total + left_velocity,
+                        right_total: right_total + right_velocity,
+                        samples: samples + 1,
+                    };
+                }
+            }
+
+            Phase::Rest(start) => {
+                if now - start >= REST_TIME {
+                    self.phase = Phase::Response {
+                        start: now,
+                        left_done: false,
+                        right_done: false,
+                    };
+                }
+            }
+
+            Phase::Response {
+                start,
+                mut left_done,
+                mut right_done,
+            } => {
+                let elapsed = (now - start).as_millis_f64();
+                let tau = (elapsed - VELOCITY_LAG).max(0.0);
+
+                let left_target = self.left_model.velocity(RESPONSE_POWER);
+                if !left_done && left_velocity >= 0.63 * left_target {
+                    self.left_model.tau = tau;
+                    left_done = true;
+                }
+
+                let right_target = self.right_model.velocity(RESPONSE_POWER);
+                if !right_done && right_velocity >= 0.63 * right_target {
+                    self.right_model.tau = tau;
+                    right_done = true;
+                }
+
+                if (left_done && right_done) || now - start >= RESPONSE_TIME {
+                    self.phase = Phase::Done;
+                } else {
+                    self.phase = Phase::Response {
+                        start,
+                        left_done,
+                        right_done,
+                    };
+                }
+            }
+
+            Phase::Done | Phase::Failed => {}
+        }
+
+        self.powers()
+    }
+
+    fn finish_sweep(&mut self, now: Instant) {
+        match (
+            fit_model(&self.left_velocities),
+            fit_model(&self.right_velocities),
+        ) {
+            (Some(left_model), Some(right_model)) => {
+                self.left_model = left_model;
+                self.right_model = right_model;
+                self.phase = Phase::Rest(now);
+            }
+            _ => self.phase = Phase::Failed,
+        }
+    }
+
+    fn powers(&self) -> Option<(f64, f64)> {
+        match self.phase {
+            Phase::Sweep { step, .. } => {
+                Some((step_power(step), step_power(step)))
+            }
+            Phase::Rest(_) => Some((0.0, 0.0)),
+            Phase::Response { .. } => Some((RESPONSE_POWER, RESPONSE_POWER)),
+            Phase::Done | Phase::Failed => None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.powers().is_some()
+    }
+
+    /// The fitted models of the left and right motors, once finished
+    pub fn models(&self) -> Option<(MotorModel, MotorModel)> {
+        match self.phase {
+            Phase::Done => Some((self.left_model, self.right_model)),
+            _ => None,
+        }
+    }
+
+    /// The step of the sweep, and how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        match self.phase {
+            Phase::Sweep { step, .. } => (step + 1, 2 * STEPS),
+            _ => (2 * STEPS, 2 * STEPS),
+        }
+    }
+
+    pub fn has_failed(&self) -> bool {
+        match self.phase {
+            Phase::Failed => true,
+            _ => false,
+        }
+    }
+}
@@ -2,10 +2,13 @@ use core::fmt::Write;
 
 use ignore_result::Ignore;
 
+use crate::motors::MAX_PWM_FREQUENCY;
+use crate::motors::MIN_PWM_FREQUENCY;
 use crate::uart::Command;
-use crate::uart::Uart;
+use crate::uart::Output;
+use crate::uart::Subcommand;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BotConfig {
     pub left_p: f64,
     pub left_i: f64,
@@ -15,11 +18,25 @@ pub struct BotConfig {
     pub right_i: f64,
     pub right_d: f64,
 
+    pub left_kv: f64,
+    pub left_forward_deadband: f64,
+    pub left_backward_deadband: f64,
+    pub left_tau: f64,
+
+    pub right_kv: f64,
+    pub right_forward_deadband: f64,
+    pub right_backward_deadband: f64,
+    pub right_tau: f64,
+
+    pub pwm_frequency: u32,
+    pub brake: bool,
+
     pub spin_p: f64,
     pub spin_i: f64,
     pub spin_d: f64,
     pub spin_err: f64,
     pub spin_settle: u32,
+    pub spin_max_velocity: f64,
 
     pub linear_p: f64,
     pub linear_i: f64,
@@ -31,6 +48,10 @@ pub struct BotConfig {
     pub linear_err: f64,
     pub linear_front_err: f64,
     pub linear_settle: u32,
+    pub linear_max_velocity: f64,
+
+    pub curve_velocity: f64,
+    pub curve_spin_p: f64,
 
     pub ticks_per_spin: f64,
     pub ticks_per_cell: f64,
@@ -39,16 +60,378 @@ pub struct BotConfig {
     pub cell_offset: f64,
     pub wall_threshold: f64,
     pub front_wall_distance: f64,
+    pub side_sensor_offset: f64,
+    pub post_width: f64,
+    pub wall_hysteresis: f64,
+    pub wall_samples: u32,
+    pub snap_window: f64,
+
+    pub stall_power: f64,
+    pub stall_velocity: f64,
+    pub stall_time: u32,
+    pub collision_distance: f64,
+
+    pub pickup_range: f64,
+    pub pickup_load: f64,
+    pub pickup_time: u32,
+
+    pub wave_distance: f64,
+    pub wave_countdown: u32,
+
+    pub range_period: u32,
+    pub interleaved: bool,
+    pub front_scaling: u8,
+
+    pub filter_median: usize,
+    pub filter_smoothing: f64,
+    pub filter_outlier: f64,
+    pub filter_outlier_count: u32,
+    pub filter_max_rate: f64,
+}
+
+/// Sets of speeds that can be picked between without the uart
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl BotConfig {
+    /// Set the top speeds of the moves to one of the sets
+    pub fn set_speed(&mut self, speed: Speed) {
+        let (linear, spin, curve) = match speed {
+            Speed::Slow => (1.0, 1.0, 0.5),
+            Speed::Normal => (2.0, 2.0, 1.0),
+            Speed::Fast => (3.0, 3.0, 1.5),
+        };
+
+        self.linear_max_velocity = linear;
+        self.spin_max_velocity = spin;
+        self.curve_velocity = curve;
+    }
 }
 
+const CONFIG_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "left_p",
+        args: "[value]",
+        help: "left wheel velocity P gain",
+    },
+    Subcommand {
+        name: "left_i",
+        args: "[value]",
+        help: "left wheel velocity I gain",
+    },
+    Subcommand {
+        name: "left_d",
+        args: "[value]",
+        help: "left wheel velocity D gain",
+    },
+    Subcommand {
+        name: "right_p",
+        args: "[value]",
+        help: "right wheel velocity P gain",
+    },
+    Subcommand {
+        name: "right_i",
+        args: "[value]",
+        help: "right wheel velocity I gain",
+    },
+    Subcommand {
+        name: "right_d",
+        args: "[value]",
+        help: "right wheel velocity D gain",
+    },
+    Subcommand {
+        name: "left_kv",
+        args: "[value]",
+        help: "left motor ticks/ms per unit of power, 0 for no feedforward",
+    },
+    Subcommand {
+        name: "left_forward_deadband",
+        args: "[value]",
+        help: "power the left motor needs to start forward",
+    },
+    Subcommand {
+        name: "left_backward_deadband",
+        args: "[value]",
+        help: "power the left motor needs to start backward",
+    },
+    Subcommand {
+        name: "left_tau",
+        args: "[value]",
+        help: "left motor time constant, in ms",
+    },
+    Subcommand {
+        name: "right_kv",
+        args: "[value]",
+        help: "right motor ticks/ms per unit of power, 0 for no feedforward",
+    },
+    Subcommand {
+        name: "right_forward_deadband",
+        args: "[value]",
+        help: "power the right motor needs to start forward",
+    },
+    Subcommand {
+        name: "right_backward_deadband",
+        args: "[value]",
+        help: "power the right motor needs to start backward",
+    },
+    Subcommand {
+        name: "right_tau",
+        args: "[value]",
+        help: "right motor time constant, in ms",
+    },
+    Subcommand {
+        name: "pwm_frequency",
+        args: "[value]",
+        help: "motor PWM frequency, 1000 to 100000 Hz",
+    },
+    Subcommand {
+        name: "brake",
+        args: "[value]",
+        help: "brake between PWM pulses instead of coasting",
+    },
+    Subcommand {
+        name: "spin_p",
+        args: "[value]",
+        help: "spin move P gain",
+    },
+    Subcommand {
+        name: "spin_i",
+        args: "[value]",
+        help: "spin move I gain",
+    },
+    Subcommand {
+        name: "spin_d",
+        args: "[value]",
+        help: "spin move D gain",
+    },
+    Subcommand {
+        name: "spin_err",
+        args: "[value]",
+        help: "spin error counted as done, in ticks",
+    },
+    Subcommand {
+        name: "spin_settle",
+        args: "[value]",
+        help: "ms to stay within spin_err to finish a spin",
+    },
+    Subcommand {
+        name: "spin_max_velocity",
+        args: "[value]",
+        help: "fastest a spin turns, in ticks/ms",
+    },
+    Subcommand {
+        name: "linear_p",
+        args: "[value]",
+        help: "linear move P gain",
+    },
+    Subcommand {
+        name: "linear_i",
+        args: "[value]",
+        help: "linear move I gain",
+    },
+    Subcommand {
+        name: "linear_d",
+        args: "[value]",
+        help: "linear move D gain",
+    },
+    Subcommand {
+        name: "linear_spin_p",
+        args: "[value]",
+        help: "P gain for staying straight in linear moves",
+    },
+    Subcommand {
+        name: "linear_spin_i",
+        args: "[value]",
+        help: "I gain for staying straight in linear moves",
+    },
+    Subcommand {
+        name: "linear_spin_d",
+        args: "[value]",
+        help: "D gain for staying straight in linear moves",
+    },
+    Subcommand {
+        name: "linear_spin_pos_p",
+        args: "[value]",
+        help: "spin target per mm off the cell center",
+    },
+    Subcommand {
+        name: "linear_err",
+        args: "[value]",
+        help: "linear error counted as done, in ticks",
+    },
+    Subcommand {
+        name: "linear_front_err",
+        args: "[value]",
+        help: "error to the front wall counted as done, in mm",
+    },
+    Subcommand {
+        name: "linear_settle",
+        args: "[value]",
+        help: "ms to stay within linear_err to finish a move",
+    },
+    Subcommand {
+        name: "linear_max_velocity",
+        args: "[value]",
+        help: "fastest a linear move drives, in ticks/ms",
+    },
+    Subcommand {
+        name: "curve_velocity",
+        args: "[value]",
+        help: "linear velocity of curves, in ticks/ms",
+    },
+    Subcommand {
+        name: "curve_spin_p",
+        args: "[value]",
+        help: "P gain for the spin of curves",
+    },
+    Subcommand {
+        name: "cell_width",
+        args: "[value]",
+        help: "width of a cell, in mm",
+    },
+    Subcommand {
+        name: "cell_offset",
+        args: "[value]",
+        help: "side sensor to wall distance in a cell center, in mm",
+    },
+    Subcommand {
+        name: "wall_threshold",
+        args: "[value]",
+        help: "side distance under which there is a wall, in mm",
+    },
+    Subcommand {
+        name: "front_wall_distance",
+        args: "[value]",
+        help: "front distance to stop at from a wall, in mm",
+    },
+    Subcommand {
+        name: "post_width",
+        args: "[value]",
+        help: "width of a post between walls, in mm",
+    },
+    Subcommand {
+        name: "wall_hysteresis",
+        args: "[value]",
+        help: "distance past wall_threshold to find a wall's end, in mm",
+    },
+    Subcommand {
+        name: "wall_samples",
+        args: "[value]",
+        help: "readings in a row past a wall's end to believe it",
+    },
+    Subcommand {
+        name: "snap_window",
+        args: "[value]",
+        help: "furthest a wall's end can be off to snap to it, in mm",
+    },
+    Subcommand {
+        name: "stall_power",
+        args: "[value]",
+        help: "motor power above which a still wheel is stalled",
+    },
+    Subcommand {
+        name: "stall_velocity",
+        args: "[value]",
+        help: "wheel velocity below which a wheel is still, in ticks/ms",
+    },
+    Subcommand {
+        name: "stall_time",
+        args: "[value]",
+        help: "how long a wheel is stalled before the move aborts, in ms",
+    },
+    Subcommand {
+        name: "collision_distance",
+        args: "[value]",
+        help: "front distance that aborts a move as a collision, in mm",
+    },
+    Subcommand {
+        name: "pickup_range",
+        args: "[value]",
+        help: "distance at which a sensor sees nothing, in mm",
+    },
+    Subcommand {
+        name: "pickup_load",
+        args: "[value]",
+        help: "power per ticks/ms below which a wheel spins free",
+    },
+    Subcommand {
+        name: "pickup_time",
+        args: "[value]",
+        help: "how long the bot is held up before it is picked up, in ms",
+    },
+    Subcommand {
+        name: "wave_distance",
+        args: "[value]",
+        help: "front distance a hand has to come within to start, in mm",
+    },
+    Subcommand {
+        name: "wave_countdown",
+        args: "[value]",
+        help: "how long after a hand wave the run starts, in ms",
+    },
+    Subcommand {
+        name: "range_period",
+        args: "[value]",
+        help: "ms between continuous ranges, 0 for single shot",
+    },
+    Subcommand {
+        name: "interleaved",
+        args: "[value]",
+        help: "true to measure ambient light between ranges",
+    },
+    Subcommand {
+        name: "front_scaling",
+        args: "[value]",
+        help: "front sensor range scaling, 1, 2 or 3",
+    },
+    Subcommand {
+        name: "filter_median",
+        args: "[value]",
+        help: "readings to take the median of, up to 9",
+    },
+    Subcommand {
+        name: "filter_smoothing",
+        args: "[value]",
+        help: "smoothing factor, 1 for none",
+    },
+    Subcommand {
+        name: "filter_outlier",
+        args: "[value]",
+        help: "change in mm taken as an outlier, 0 for none",
+    },
+    Subcommand {
+        name: "filter_outlier_count",
+        args: "[value]",
+        help: "outliers in a row taken as a real change",
+    },
+    Subcommand {
+        name: "filter_max_rate",
+        args: "[value]",
+        help: "most change in mm/ms, 0 for no limit",
+    },
+    Subcommand {
+        name: "side_sensor_offset",
+        args: "[value]",
+        help: "side sensors ahead of the wheels, in mm",
+    },
+];
+
 impl Command for BotConfig {
     fn keyword_command(&self) -> &str {
         "config"
     }
 
+    fn subcommands(&self) -> &[Subcommand] {
+        CONFIG_SUBCOMMANDS
+    }
+
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         mut args: I,
     ) {
         match args.next() {
@@ -57,10 +440,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.left_p = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "left_p: {}", self.left_p).ignore();
+                    writeln!(out, "left_p: {}", self.left_p).ignore();
                 }
             }
             Some("left_i") => {
@@ -68,10 +451,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.left_i = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "left_i: {}", self.left_i).ignore();
+                    writeln!(out, "left_i: {}", self.left_i).ignore();
                 }
             }
             Some("left_d") => {
@@ -79,10 +462,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.left_d = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "left_d: {}", self.left_d).ignore();
+                    writeln!(out, "left_d: {}", self.left_d).ignore();
                 }
             }
             Some("right_p") => {
@@ -90,10 +473,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.right_p = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "right_p: {}", self.right_p).ignore();
+                    writeln!(out, "right_p: {}", self.right_p).ignore();
                 }
             }
             Some("right_i") => {
@@ -101,10 +484,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.right_i = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "right_i: {}", self.right_i).ignore();
+                    writeln!(out, "right_i: {}", self.right_i).ignore();
                 }
             }
             Some("right_d") => {
@@ -112,10 +495,145 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.right_d = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "right_d: {}", self.right_d).ignore();
+                }
+            }
+            Some("left_kv") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.left_kv = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "left_kv: {}", self.left_kv).ignore();
+                }
+            }
+            Some("left_forward_deadband") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.left_forward_deadband = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "left_forward_deadband: {}",
+                        self.left_forward_deadband
+                    )
+                    .ignore();
+                }
+            }
+            Some("left_backward_deadband") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.left_backward_deadband = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "left_backward_deadband: {}",
+                        self.left_backward_deadband
+                    )
+                    .ignore();
+                }
+            }
+            Some("left_tau") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.left_tau = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "left_tau: {}", self.left_tau).ignore();
+                }
+            }
+            Some("right_kv") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.right_kv = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "right_kv: {}", self.right_kv).ignore();
+                }
+            }
+            Some("right_forward_deadband") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.right_forward_deadband = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "right_forward_deadband: {}",
+                        self.right_forward_deadband
+                    )
+                    .ignore();
+                }
+            }
+            Some("right_backward_deadband") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.right_backward_deadband = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "right_backward_deadband: {}",
+                        self.right_backward_deadband
+                    )
+                    .ignore();
+                }
+            }
+            Some("right_tau") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.right_tau = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "right_tau: {}", self.right_tau).ignore();
+                }
+            }
+            Some("pwm_frequency") => {
+                if let Some(arg) = args.next() {
+                    match arg.parse() {
+                        Ok(v)
+                            if v >= MIN_PWM_FREQUENCY
+                                && v <= MAX_PWM_FREQUENCY =>
+                        {
+                            self.pwm_frequency = v
+                        }
+                        _ => writeln!(out, "invalid value").ignore(),
                     }
                 } else {
-                    writeln!(uart, "right_d: {}", self.right_d).ignore();
+                    writeln!(out, "pwm_frequency: {}", self.pwm_frequency)
+                        .ignore();
+                }
+            }
+            Some("brake") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.brake = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "brake: {}", self.brake).ignore();
                 }
             }
             Some("spin_p") => {
@@ -123,10 +641,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.spin_p = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "spin_p: {}", self.spin_p).ignore();
+                    writeln!(out, "spin_p: {}", self.spin_p).ignore();
                 }
             }
             Some("spin_i") => {
@@ -134,10 +652,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.spin_i = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "spin_i: {}", self.spin_i).ignore();
+                    writeln!(out, "spin_i: {}", self.spin_i).ignore();
                 }
             }
             Some("spin_d") => {
@@ -145,10 +663,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.spin_d = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "spin_d: {}", self.spin_d).ignore();
+                    writeln!(out, "spin_d: {}", self.spin_d).ignore();
                 }
             }
             Some("spin_err") => {
@@ -156,10 +674,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.spin_err = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "spin_err: {}", self.spin_err).ignore();
+                    writeln!(out, "spin_err: {}", self.spin_err).ignore();
                 }
             }
             Some("spin_settle") => {
@@ -167,11 +685,26 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.spin_settle = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "spin_settle: {}", self.spin_settle)
-                        .ignore();
+                    writeln!(out, "spin_settle: {}", self.spin_settle).ignore();
+                }
+            }
+            Some("spin_max_velocity") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.spin_max_velocity = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "spin_max_velocity: {}",
+                        self.spin_max_velocity
+                    )
+                    .ignore();
                 }
             }
             Some("linear_p") => {
@@ -179,10 +712,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_p = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_p: {}", self.linear_p).ignore();
+                    writeln!(out, "linear_p: {}", self.linear_p).ignore();
                 }
             }
             Some("linear_i") => {
@@ -190,10 +723,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_i = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_i: {}", self.linear_i).ignore();
+                    writeln!(out, "linear_i: {}", self.linear_i).ignore();
                 }
             }
             Some("linear_d") => {
@@ -201,10 +734,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_d = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_d: {}", self.linear_d).ignore();
+                    writeln!(out, "linear_d: {}", self.linear_d).ignore();
                 }
             }
             Some("linear_spin_p") => {
@@ -212,10 +745,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_spin_p = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_spin_p: {}", self.linear_spin_p)
+                    writeln!(out, "linear_spin_p: {}", self.linear_spin_p)
                         .ignore();
                 }
             }
@@ -224,10 +757,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_spin_i = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_spin_i: {}", self.linear_spin_i)
+                    writeln!(out, "linear_spin_i: {}", self.linear_spin_i)
                         .ignore();
                 }
             }
@@ -236,10 +769,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_spin_d = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_spin_d: {}", self.linear_spin_d)
+                    writeln!(out, "linear_spin_d: {}", self.linear_spin_d)
                         .ignore();
                 }
             }
@@ -248,11 +781,11 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_spin_pos_p = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
                     writeln!(
-                        uart,
+                        out,
                         "linear_spin_pos_p: {}",
                         self.linear_spin_pos_p
                     )
@@ -264,10 +797,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_err = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_err: {}", self.linear_err).ignore();
+                    writeln!(out, "linear_err: {}", self.linear_err).ignore();
                 }
             }
             Some("linear_front_err") => {
@@ -275,10 +808,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_front_err = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_front_err: {}", self.linear_err)
+                    writeln!(out, "linear_front_err: {}", self.linear_err)
                         .ignore();
                 }
             }
@@ -287,10 +820,50 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.linear_settle = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "linear_settle: {}", self.linear_settle)
+                        .ignore();
+                }
+            }
+            Some("linear_max_velocity") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.linear_max_velocity = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "linear_max_velocity: {}",
+                        self.linear_max_velocity
+                    )
+                    .ignore();
+                }
+            }
+            Some("curve_velocity") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.curve_velocity = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "curve_velocity: {}", self.curve_velocity)
+                        .ignore();
+                }
+            }
+            Some("curve_spin_p") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.curve_spin_p = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "linear_settle: {}", self.linear_settle)
+                    writeln!(out, "curve_spin_p: {}", self.curve_spin_p)
                         .ignore();
                 }
             }
@@ -299,10 +872,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.cell_width = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "cell_width:: {}", self.cell_width).ignore();
+                    writeln!(out, "cell_width:: {}", self.cell_width).ignore();
                 }
             }
             Some("cell_offset") => {
@@ -310,10 +883,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.cell_offset = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "cell_offset:: {}", self.cell_offset)
+                    writeln!(out, "cell_offset:: {}", self.cell_offset)
                         .ignore();
                 }
             }
@@ -322,10 +895,10 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.wall_threshold = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
-                    writeln!(uart, "wall_threshold:: {}", self.cell_offset)
+                    writeln!(out, "wall_threshold:: {}", self.cell_offset)
                         .ignore();
                 }
             }
@@ -334,20 +907,289 @@ impl Command for BotConfig {
                     if let Ok(v) = arg.parse() {
                         self.front_wall_distance = v;
                     } else {
-                        writeln!(uart, "invalid value").ignore();
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "front_wall_distance:: {}", self.cell_offset)
+                        .ignore();
+                }
+            }
+            Some("post_width") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.post_width = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "post_width: {}", self.post_width).ignore();
+                }
+            }
+            Some("wall_hysteresis") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.wall_hysteresis = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "wall_hysteresis: {}", self.wall_hysteresis)
+                        .ignore();
+                }
+            }
+            Some("wall_samples") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.wall_samples = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "wall_samples: {}", self.wall_samples)
+                        .ignore();
+                }
+            }
+            Some("snap_window") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.snap_window = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "snap_window: {}", self.snap_window).ignore();
+                }
+            }
+            Some("stall_power") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.stall_power = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "stall_power: {}", self.stall_power).ignore();
+                }
+            }
+            Some("stall_velocity") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.stall_velocity = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "stall_velocity: {}", self.stall_velocity)
+                        .ignore();
+                }
+            }
+            Some("stall_time") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.stall_time = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "stall_time: {}", self.stall_time).ignore();
+                }
+            }
+            Some("collision_distance") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.collision_distance = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "collision_distance: {}",
+                        self.collision_distance
+                    )
+                    .ignore();
+                }
+            }
+            Some("pickup_range") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.pickup_range = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "pickup_range: {}", self.pickup_range)
+                        .ignore();
+                }
+            }
+            Some("pickup_load") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.pickup_load = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "pickup_load: {}", self.pickup_load).ignore();
+                }
+            }
+            Some("pickup_time") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.pickup_time = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "pickup_time: {}", self.pickup_time).ignore();
+                }
+            }
+            Some("wave_distance") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.wave_distance = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "wave_distance: {}", self.wave_distance)
+                        .ignore();
+                }
+            }
+            Some("wave_countdown") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.wave_countdown = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "wave_countdown: {}", self.wave_countdown)
+                        .ignore();
+                }
+            }
+            Some("range_period") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.range_period = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "range_period: {}", self.range_period)
+                        .ignore();
+                }
+            }
+            Some("interleaved") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.interleaved = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "interleaved: {}", self.interleaved).ignore();
+                }
+            }
+            Some("front_scaling") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.front_scaling = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "front_scaling: {}", self.front_scaling)
+                        .ignore();
+                }
+            }
+            Some("filter_median") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.filter_median = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "filter_median: {}", self.filter_median)
+                        .ignore();
+                }
+            }
+            Some("filter_smoothing") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.filter_smoothing = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "filter_smoothing: {}",
+                        self.filter_smoothing
+                    )
+                    .ignore();
+                }
+            }
+            Some("filter_outlier") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.filter_outlier = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "filter_outlier: {}", self.filter_outlier)
+                        .ignore();
+                }
+            }
+            Some("filter_outlier_count") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.filter_outlier_count = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(
+                        out,
+                        "filter_outlier_count: {}",
+                        self.filter_outlier_count
+                    )
+                    .ignore();
+                }
+            }
+            Some("filter_max_rate") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.filter_max_rate = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
+                    }
+                } else {
+                    writeln!(out, "filter_max_rate: {}", self.filter_max_rate)
+                        .ignore();
+                }
+            }
+            Some("side_sensor_offset") => {
+                if let Some(arg) = args.next() {
+                    if let Ok(v) = arg.parse() {
+                        self.side_sensor_offset = v;
+                    } else {
+                        writeln!(out, "invalid value").ignore();
                     }
                 } else {
                     writeln!(
-                        uart,
-                        "front_wall_distance:: {}",
-                        self.cell_offset
+                        out,
+                        "side_sensor_offset: {}",
+                        self.side_sensor_offset
                     )
                     .ignore();
                 }
             }
 
-            Some(_) => writeln!(uart, "config: unknown key").ignore(),
-            None => writeln!(uart, "{:#?}", &self).ignore(),
+            Some(_) => writeln!(out, "config: unknown key").ignore(),
+            None => writeln!(out, "{:#?}", &self).ignore(),
         }
     }
 }
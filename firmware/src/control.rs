@@ -9,32 +9,39 @@ use pid_control::PIDController;
 
 use crate::bot::Bot;
 use crate::config::BotConfig;
+use crate::filter::WallEdge;
 
+use crate::time::Duration;
+use crate::time::Instant;
+
+use crate::uart;
 use crate::uart::Command;
-use crate::uart::Uart;
+use crate::uart::Output;
+use crate::uart::Subcommand;
 
 pub struct SpinMove {
     spin_pid: PIDController,
     err: f64,
-    settle: u32,
-    last_ok: u32,
-    last_update: u32,
+    settle: Duration,
+    last_ok: Instant,
+    last_update: Instant,
 }
 
 impl SpinMove {
     pub fn new(target: f64, config: &BotConfig) -> SpinMove {
         let mut spin_pid =
             PIDController::new(config.spin_p, config.spin_i, config.spin_d);
-        spin_pid.set_limits(-2.0, 2.0);
+        spin_pid
+            .set_limits(-config.spin_max_velocity, config.spin_max_velocity);
         spin_pid.d_mode = DerivativeMode::OnMeasurement;
         spin_pid.set_target(target);
 
         SpinMove {
             spin_pid,
             err: config.spin_err,
-            settle: config.spin_settle,
-            last_update: 0,
-            last_ok: 0,
+            settle: Duration::from_millis(config.spin_settle as u64),
+            last_update: Instant::START,
+            last_ok: Instant::START,
         }
     }
 
@@ -44,7 +51,7 @@ impl SpinMove {
      *  Returns true if the spin controller is done,
      *  false if it is not done.
      */
-    pub fn update(&mut self, now: u32, bot: &mut Bot) -> bool {
+    pub fn update(&mut self, now: Instant, bot: &mut Bot) -> bool {
         let spin_pos = bot.spin_pos();
 
         let error = spin_pos - self.spin_pid.target();
@@ -57,8 +64,8 @@ impl SpinMove {
             bot.change_velocity(0.0, 0.0);
             true
         } else {
-            let delta_time = now - self.last_update;
-            let spin_vel = self.spin_pid.update(spin_pos, delta_time as f64);
+            let delta_time = (now - self.last_update).as_millis_f64();
+            let spin_vel = self.spin_pid.update(spin_pos, delta_time);
             bot.change_velocity(0.0, spin_vel);
             self.last_update = now;
             false
@@ -70,23 +77,46 @@ pub struct LinearMove {
     linear_pid: PIDController,
     spin_pid: PIDController,
     linear_target: f64,
+    front_offset: f64,
+    boundary_phase: Option<f64>,
     last_linear_ok: bool,
     last_spin_ok: bool,
     err: f64,
-    settle: u32,
-    last_ok: u32,
-    last_update: u32,
+    settle: Duration,
+    last_ok: Instant,
+    last_update: Instant,
+    through_velocity: Option<f64>,
 }
 
 impl LinearMove {
-    pub fn new(target: f64, config: &BotConfig) -> LinearMove {
+    /**
+     *  Create a new linear move
+     *
+     *  `front_offset` is how much further from a front wall the move
+     *  should stop than `front_wall_distance`, in mm. It is zero when
+     *  stopping in the center of a cell.
+     *
+     *  `boundary_phase` is how far before the target the last cell
+     *  boundary is, in ticks, for moves through the maze that snap to the
+     *  posts they pass. Other moves don't know where the boundaries are,
+     *  so they leave it None.
+     */
+    pub fn new(
+        target: f64,
+        front_offset: f64,
+        boundary_phase: Option<f64>,
+        config: &BotConfig,
+    ) -> LinearMove {
         let mut linear_pid = PIDController::new(
             config.linear_p,
             config.linear_i,
             config.linear_d,
         );
 
-        linear_pid.set_limits(-2.0, 2.0);
+        linear_pid.set_limits(
+            -config.linear_max_velocity,
+            config.linear_max_velocity,
+        );
         linear_pid.d_mode = DerivativeMode::OnMeasurement;
         linear_pid.set_target(target);
 
@@ -104,12 +134,76 @@ impl LinearMove {
             linear_pid,
             spin_pid,
             linear_target: target,
+            front_offset,
+            boundary_phase,
             last_linear_ok: false,
             last_spin_ok: false,
             err: config.linear_err,
-            settle: config.linear_settle,
-            last_update: 0,
-            last_ok: 0,
+            settle: Duration::from_millis(config.linear_settle as u64),
+            last_update: Instant::START,
+            last_ok: Instant::START,
+            through_velocity: None,
+        }
+    }
+
+    /// Move the target further along, without restarting the move
+    pub fn extend(&mut self, ticks: f64) {
+        self.linear_target += ticks;
+    }
+
+    /**
+     *  End the move at the target without stopping, for a curve to pick
+     *  up from
+     *
+     *  The bot is kept going at least `velocity` all the way to the
+     *  target, and the move is done as soon as it gets there, instead of
+     *  settling.
+     */
+    pub fn run_through(&mut self, velocity: f64) {
+        self.through_velocity = Some(velocity);
+    }
+
+    pub fn is_through(&self) -> bool {
+        self.through_velocity.is_some()
+    }
+
+    /**
+     *  Snap the linear position to the cell boundary at a side wall's edge
+     *
+     *  Walls start and end at the posts on cell boundaries, so a side
+     *  wall appearing or disappearing places the sensor at the edge of a
+     *  post. The boundaries are found from the target, `boundary_phase`
+     *  after the last of them.
+     */
+    fn snap_to_post(&self, bot: &mut Bot, edge: WallEdge, boundary_phase: f64) {
+        let ticks_per_cell = bot.config.ticks_per_cell;
+        let ticks_per_mm = ticks_per_cell / bot.config.cell_width;
+
+        let post_edge = if edge.appeared {
+            -bot.config.post_width / 2.0
+        } else {
+            bot.config.post_width / 2.0
+        };
+
+        let boundary = self.linear_target - boundary_phase
+            + (post_edge - bot.config.side_sensor_offset) * ticks_per_mm;
+
+        let cells = (edge.linear_pos - boundary) / ticks_per_cell;
+        let nearest = if cells >= 0.0 {
+            (cells + 0.5) as i32
+        } else {
+            (cells - 0.5) as i32
+        };
+
+        let error =
+            edge.linear_pos - (boundary + nearest as f64 * ticks_per_cell);
+
+        // Anything further off is more likely to be a bad reading, or a
+        // gap in a wall, than a bad position
+        let window = bot.config.snap_window * ticks_per_mm;
+
+        if error < window && error > -window {
+            bot.shift_linear(-error);
         }
     }
 
@@ -119,14 +213,17 @@ impl LinearMove {
      *  Returns true if the linear controller is done,
      *  false if it is not done.
      */
-    pub fn update(&mut self, now: u32, bot: &mut Bot) -> bool {
-        let front_distance = bot.front_distance();
+    pub fn update(&mut self, now: Instant, bot: &mut Bot) -> bool {
+        // Invalid readings are treated as nothing being in range
+        let front_distance = bot.front_distance().unwrap_or(f64::INFINITY);
+        let front_wall_distance =
+            bot.config.front_wall_distance + self.front_offset;
 
         let (linear_pos, linear_target, linear_err) =
             if front_distance <= bot.config.cell_width {
                 (
                     -front_distance * 9.0,
-                    -bot.config.front_wall_distance * 9.0,
+                    -front_wall_distance * 9.0,
                     bot.config.linear_front_err * 9.0
                 )
             } else {
@@ -148,8 +245,17 @@ impl LinearMove {
 
         self.last_linear_ok = linear_ok;
 
-        let left_distance = bot.left_distance();
-        let right_distance = bot.right_distance();
+        let left_distance = bot.left_distance().unwrap_or(f64::INFINITY);
+        let right_distance = bot.right_distance().unwrap_or(f64::INFINITY);
+
+        let left_edge = bot.take_left_edge();
+        let right_edge = bot.take_right_edge();
+
+        if let Some(boundary_phase) = self.boundary_phase {
+            for &edge in left_edge.iter().chain(right_edge.iter()) {
+                self.snap_to_post(bot, edge, boundary_phase);
+            }
+        }
 
         let width = left_distance + right_distance;
 
@@ -182,16 +288,22 @@ impl LinearMove {
             self.last_ok = now;
         }
 
-        if now - self.last_ok > self.settle {
+        if self.through_velocity.is_some() && linear_error >= 0.0 {
+            // Left moving for the curve
+            true
+        } else if now - self.last_ok > self.settle {
             bot.change_velocity(0.0, 0.0);
             true
         } else {
-            let delta_time = now - self.last_update;
+            let delta_time = (now - self.last_update).as_millis_f64();
 
-            let linear_vel =
-                self.linear_pid.update(linear_pos, delta_time as f64);
+            let linear_vel = self.linear_pid.update(linear_pos, delta_time);
+            let linear_vel = match self.through_velocity {
+                Some(velocity) => linear_vel.max(velocity),
+                None => linear_vel,
+            };
 
-            let spin_vel = self.spin_pid.update(spin_pos, delta_time as f64);
+            let spin_vel = self.spin_pid.update(spin_pos, delta_time);
 
             bot.change_velocity(linear_vel, spin_vel);
             self.last_update = now;
@@ -200,10 +312,85 @@ impl LinearMove {
     }
 }
 
+pub struct CurveMove {
+    spin_pid: PIDController,
+    linear_target: f64,
+    spin_target: f64,
+    velocity: f64,
+    last_update: Instant,
+}
+
+impl CurveMove {
+    /// Create a new curve, starting at `start`
+    pub fn new(
+        linear_target: f64,
+        spin_target: f64,
+        start: Instant,
+        config: &BotConfig,
+    ) -> CurveMove {
+        let mut spin_pid = PIDController::new(config.curve_spin_p, 0.0, 0.0);
+        spin_pid.set_limits(-2.0, 2.0);
+        spin_pid.d_mode = DerivativeMode::OnMeasurement;
+        spin_pid.set_target(0.0);
+
+        CurveMove {
+            spin_pid,
+            linear_target,
+            spin_target,
+            velocity: config.curve_velocity,
+            last_update: start,
+        }
+    }
+
+    /**
+     *  Update the curve controller
+     *
+     *  Drives an arc at a constant linear velocity, with the spin
+     *  position following the linear position so the turn is finished
+     *  when the arc is.
+     *
+     *  Returns true once the arc has been driven. The bot is left
+     *  moving so the next move can pick up without stopping.
+     */
+    pub fn update(&mut self, now: Instant, bot: &mut Bot) -> bool {
+        let linear_pos = bot.linear_pos();
+
+        if linear_pos >= self.linear_target {
+            true
+        } else {
+            let progress = linear_pos / self.linear_target;
+            self.spin_pid.set_target(self.spin_target * progress);
+
+            let delta_time = (now - self.last_update).as_millis_f64();
+            let spin_correction =
+                self.spin_pid.update(bot.spin_pos(), delta_time);
+
+            let spin_vel = 2.0 * self.velocity * self.spin_target
+                / self.linear_target
+                + spin_correction;
+
+            bot.change_velocity(self.velocity, spin_vel);
+            self.last_update = now;
+            false
+        }
+    }
+}
+
+/// Why a move was cut short
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// A wheel was driven hard without turning
+    Stall,
+
+    /// Something turned up in front, closer than a wall should ever get
+    Collision,
+}
+
 enum CurrentMove {
     Idle,
     SpinMove(SpinMove),
     LinearMove(LinearMove),
+    CurveMove(CurveMove),
 }
 
 impl CurrentMove {
@@ -218,8 +405,14 @@ impl CurrentMove {
 pub struct Control {
     bot: Bot,
     current_move: CurrentMove,
+    stall_start: Option<Instant>,
+    fault: Option<(Fault, f64)>,
+
+    /// When the moves were last updated, which a new move starts from
+    last_update: Instant,
 
-    last_update: u32,
+    /// Whether the last move ran through its target for a curve
+    ran_through: bool,
 }
 
 impl Control {
@@ -227,7 +420,10 @@ impl Control {
         Control {
             bot,
             current_move: CurrentMove::Idle,
-            last_update: 0,
+            stall_start: None,
+            fault: None,
+            last_update: Instant::START,
+            ran_through: false,
         }
     }
 
@@ -240,34 +436,201 @@ impl Control {
 
     pub fn linear(&mut self, linear_target: f64) {
         if self.current_move.is_idle() {
-            let linear_move = LinearMove::new(linear_target, &self.bot.config);
+            let linear_move =
+                LinearMove::new(linear_target, 0.0, None, &self.bot.config);
             self.current_move = CurrentMove::LinearMove(linear_move);
         }
     }
 
-    pub fn update(&mut self, now: u32) {
-        let delta_time = now - self.last_update;
+    /**
+     *  Move linearly from the center of a cell to the center of another,
+     *  snapping to the posts on the way
+     */
+    pub fn linear_cells(&mut self, linear_target: f64) {
+        if self.current_move.is_idle() {
+            let linear_move = LinearMove::new(
+                linear_target,
+                0.0,
+                Some(self.bot.config.ticks_per_cell / 2.0),
+                &self.bot.config,
+            );
+            self.current_move = CurrentMove::LinearMove(linear_move);
+        }
+    }
 
-        if delta_time >= 10 {
-            let is_done = match self.current_move {
-                CurrentMove::SpinMove(ref mut spin_move) => {
-                    spin_move.update(now, &mut self.bot)
-                }
-                CurrentMove::LinearMove(ref mut linear_move) => {
-                    linear_move.update(now, &mut self.bot)
+    /**
+     *  Move linearly, ending on the edge of a cell instead of the center,
+     *  snapping to the posts on the way
+     *
+     *  A front wall will stop the move half a cell further back than a
+     *  normal linear move.
+     */
+    pub fn linear_to_edge(&mut self, linear_target: f64) {
+        if self.current_move.is_idle() {
+            let linear_move = LinearMove::new(
+                linear_target,
+                self.bot.config.cell_width / 2.0,
+                Some(0.0),
+                &self.bot.config,
+            );
+            self.current_move = CurrentMove::LinearMove(linear_move);
+        }
+    }
+
+    /**
+     *  Extend the current linear move so the bot keeps going
+     *
+     *  Returns false if the current move is not a linear move.
+     */
+    pub fn extend_linear(&mut self, ticks: f64) -> bool {
+        match self.current_move {
+            CurrentMove::LinearMove(ref mut linear_move) => {
+                linear_move.extend(ticks);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     *  Have the current linear move run through its target at speed, for
+     *  a curve that follows
+     *
+     *  If nothing is started as soon as it ends, the bot is stopped.
+     *  Returns false if the current move is not a linear move.
+     */
+    pub fn run_through(&mut self) -> bool {
+        let velocity = self.bot.config.curve_velocity;
+
+        match self.current_move {
+            CurrentMove::LinearMove(ref mut linear_move) => {
+                linear_move.run_through(velocity);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     *  Turn while moving, along a quarter circle from the edge of a cell
+     *  to the adjacent edge
+     *
+     *  The radius of the arc is half a cell, so it passes through the
+     *  corner of the cell instead of the center.
+     */
+    pub fn curve(&mut self, spin_target: f64) {
+        if self.current_move.is_idle() {
+            let linear_target =
+                self.bot.config.ticks_per_cell * f64::consts::FRAC_PI_4;
+            let curve_move = CurveMove::new(
+                linear_target,
+                spin_target,
+                self.last_update,
+                &self.bot.config,
+            );
+            self.current_move = CurrentMove::CurveMove(curve_move);
+        }
+    }
+
+    /**
+     *  Update the current move
+     *
+     *  This is the slower position loop, run every few updates of the
+     *  wheel velocities, which the scheduler does separately.
+     */
+    pub fn update(&mut self, now: Instant) {
+        if let Some(fault) = self.check_fault(now) {
+            self.fault = Some((fault, self.bot.linear_pos()));
+            self.stop();
+            return;
+        }
+
+        let is_done = match self.current_move {
+            CurrentMove::SpinMove(ref mut spin_move) => {
+                spin_move.update(now, &mut self.bot)
+            }
+            CurrentMove::LinearMove(ref mut linear_move) => {
+                linear_move.update(now, &mut self.bot)
+            }
+            CurrentMove::CurveMove(ref mut curve_move) => {
+                curve_move.update(now, &mut self.bot)
+            }
+            CurrentMove::Idle => {
+                // Nothing picked up from a move that ran through
+                if self.ran_through {
+                    self.bot.change_velocity(0.0, 0.0);
                 }
-                CurrentMove::Idle => false,
-            };
+                false
+            }
+        };
+
+        self.ran_through = false;
+        self.last_update = now;
 
-            if is_done {
-                self.current_move = CurrentMove::Idle;
-                self.bot.reset();
+        if is_done {
+            if let CurrentMove::LinearMove(ref linear) = self.current_move {
+                self.ran_through = linear.is_through();
             }
 
-            self.last_update = now;
+            self.current_move = CurrentMove::Idle;
+            self.bot.reset();
+        }
+    }
+
+    /**
+     *  Check the current move for a wheel that is stalled, or a collision
+     *  in front
+     *
+     *  Either one means the move is never going to reach its target, so
+     *  carrying on would just wind up the controllers against the motors.
+     */
+    fn check_fault(&mut self, now: Instant) -> Option<Fault> {
+        if self.current_move.is_idle() {
+            self.stall_start = None;
+            return None;
+        }
+
+        let config = &self.bot.config;
+
+        let stalled = |power: f64, velocity: f64| {
+            power.abs() > config.stall_power
+                && velocity.abs() < config.stall_velocity
+        };
+
+        if stalled(self.bot.left_power(), self.bot.left_velocity())
+            || stalled(self.bot.right_power(), self.bot.right_velocity())
+        {
+            let stall_start = *self.stall_start.get_or_insert(now);
+            let stall_time = Duration::from_millis(config.stall_time as u64);
+
+            if now - stall_start > stall_time {
+                self.stall_start = None;
+                return Some(Fault::Stall);
+            }
+        } else {
+            self.stall_start = None;
         }
 
-        self.bot.update(now);
+        // Moves stop well short of a front wall, so only something that
+        // wasn't seen coming gets this close while driving forward
+        let forward = self.bot.left_target() + self.bot.right_target() > 0.0;
+
+        match self.bot.front_distance() {
+            Some(distance)
+                if forward && distance < config.collision_distance =>
+            {
+                Some(Fault::Collision)
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     *  The fault that last cut a move short, with the linear position the
+     *  move had got to, if it hasn't been taken already
+     */
+    pub fn take_fault(&mut self) -> Option<(Fault, f64)> {
+        self.fault.take()
     }
 
     pub fn is_idle(&self) -> bool {
@@ -278,10 +641,15 @@ impl Control {
         &self.bot
     }
 
-    pub fn current_move_name(&self) -> &str {
+    pub fn bot_mut(&mut self) -> &mut Bot {
+        &mut self.bot
+    }
+
+    pub fn current_move_name(&self) -> &'static str {
         match self.current_move {
             CurrentMove::SpinMove(_) => "spin",
             CurrentMove::LinearMove(_) => "linear",
+            CurrentMove::CurveMove(_) => "curve",
             CurrentMove::Idle => "idle",
         }
     }
@@ -290,23 +658,75 @@ impl Control {
         self.bot.change_velocity(0.0, 0.0);
         self.bot.reset();
         self.current_move = CurrentMove::Idle;
+        self.ran_through = false;
     }
 }
 
+const CONTROL_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "bot",
+        args: "...",
+        help: "commands for the motors and sensors",
+    },
+    Subcommand {
+        name: "stop",
+        args: "",
+        help: "stop the motors and end the move",
+    },
+    Subcommand {
+        name: "spin",
+        args: "<ticks>",
+        help: "spin in place by the encoder ticks",
+    },
+    Subcommand {
+        name: "linear",
+        args: "<ticks>",
+        help: "move straight by the encoder ticks",
+    },
+    Subcommand {
+        name: "turn",
+        args: "<left|right|around>",
+        help: "spin in place by a quarter or half turn",
+    },
+    Subcommand {
+        name: "curve",
+        args: "<left|right>",
+        help: "curve a quarter turn while moving",
+    },
+];
+
 impl Command for Control {
     fn keyword_command(&self) -> &str {
         "control"
     }
 
+    fn subcommands(&self) -> &[Subcommand] {
+        CONTROL_SUBCOMMANDS
+    }
+
+    fn help_command<'a, I: Iterator<Item = &'a str>>(
+        &self,
+        out: &mut Output,
+        mut path: I,
+    ) {
+        let command = path.next();
+
+        if command == Some(self.bot.keyword_command()) {
+            self.bot.help_command(out, path);
+        } else {
+            uart::write_help(out, "control", CONTROL_SUBCOMMANDS, command);
+        }
+    }
+
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         mut args: I,
     ) {
         let command = args.next();
 
         if command == Some(self.bot.keyword_command()) {
-            self.bot.handle_command(uart, args);
+            self.bot.handle_command(out, args);
         } else {
             match command {
                 Some("stop") => {
@@ -321,7 +741,7 @@ impl Command for Control {
                     {
                         self.spin(spin_pos);
                     } else {
-                        writeln!(uart, "No target!").ignore();
+                        writeln!(out, "No target!").ignore();
                     }
                 }
 
@@ -331,7 +751,7 @@ impl Command for Control {
                     {
                         self.linear(linear_pos);
                     } else {
-                        writeln!(uart, "No target!").ignore();
+                        writeln!(out, "No target!").ignore();
                     }
                 }
 
@@ -345,10 +765,20 @@ impl Command for Control {
                     Some("around") => {
                         self.spin(self.bot.config.ticks_per_spin / 2.0)
                     }
-                    _ => writeln!(uart, "control: unknown turn!").ignore(),
+                    _ => writeln!(out, "control: unknown turn!").ignore(),
+                },
+
+                Some("curve") => match args.next() {
+                    Some("left") => {
+                        self.curve(-self.bot.config.ticks_per_spin / 4.0)
+                    }
+                    Some("right") => {
+                        self.curve(self.bot.config.ticks_per_spin / 4.0)
+                    }
+                    _ => writeln!(out, "control: unknown curve!").ignore(),
                 },
 
-                _ => writeln!(uart, "control: unknown command").ignore(),
+                _ => writeln!(out, "control: unknown command").ignore(),
             }
         }
     }
@@ -0,0 +1,258 @@
+use crate::config::BotConfig;
+use crate::time::Instant;
+
+/// The most readings the median can be taken over
+pub const MAX_MEDIAN: usize = 9;
+
+/**
+ *  Filters the readings from one distance sensor
+ *
+ *  Readings are checked against the current value for outliers, then the
+ *  median of the last `filter_median` is taken, limited to change by
+ *  `filter_max_rate` mm/ms and smoothed by `filter_smoothing`. A run of
+ *  `filter_outlier_count` rejected readings is taken as a real change, so
+ *  a wall appearing or disappearing still gets through, just later.
+ */
+pub struct DistanceFilter {
+    // Most recent first
+    history: [f64; MAX_MEDIAN],
+    history_len: usize,
+    rejected: u32,
+    value: Option<f64>,
+    last_time: Instant,
+}
+
+impl DistanceFilter {
+    pub fn new() -> DistanceFilter {
+        DistanceFilter {
+            history: [0.0; MAX_MEDIAN],
+            history_len: 0,
+            rejected: 0,
+            value: None,
+            last_time: Instant::START,
+        }
+    }
+
+    /// Add a measurement taken at `time`, None if it was not valid
+    pub fn add(
+        &mut self,
+        reading: Option<f64>,
+        time: Instant,
+        config: &BotConfig,
+    ) {
+        let value = match self.value {
+            Some(value) => value,
+            None => {
+                match reading {
+                    Some(reading) => {
+                        self.rejected += 1;
+
+                        if self.rejected >= config.filter_outlier_count {
+                            self.restart(reading, time);
+                        }
+                    }
+                    None => self.rejected = 0,
+                }
+
+                return;
+            }
+        };
+
+        let reading = match reading {
+            Some(reading) if !is_outlier(reading, value, config) => reading,
+            _ => {
+                self.rejected += 1;
+
+                if self.rejected >= config.filter_outlier_count {
+                    match reading {
+                        Some(reading) => self.restart(reading, time),
+                        None => self.clear(),
+                    }
+                }
+
+                return;
+            }
+        };
+
+        self.rejected = 0;
+
+        for i in (1..MAX_MEDIAN).rev() {
+            self.history[i] = self.history[i - 1];
+        }
+        self.history[0] = reading;
+
+        if self.history_len < MAX_MEDIAN {
+            self.history_len += 1;
+        }
+
+        let median = self.median(config.filter_median);
+
+        let change = if config.filter_max_rate > 0.0 {
+            let max_change = config.filter_max_rate
+                * (time - self.last_time).as_millis_f64();
+            let change = median - value;
+
+            if change > max_change {
+                max_change
+            } else if change < -max_change {
+                -max_change
+            } else {
+                change
+            }
+        } else {
+            median - value
+        };
+
+        self.value = Some(value + config.filter_smoothing * change);
+        self.last_time = time;
+    }
+
+    /// The filtered distance in mm, or None if the sensor sees nothing
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    pub fn clear(&mut self) {
+        self.history_len = 0;
+        self.rejected = 0;
+        self.value = None;
+    }
+
+    fn restart(&mut self, reading: f64, time: Instant) {
+        self.history[0] = reading;
+        self.history_len = 1;
+        self.rejected = 0;
+        self.value = Some(reading);
+        self.last_time = time;
+    }
+
+    fn median(&self, count: usize) -> f64 {
+        let count = if count < 1 {
+            1
+        } else if count > self.history_len {
+            self.history_len
+        } else {
+            count
+        };
+
+        let mut sorted = self.history;
+        let sorted = &mut sorted[..count];
+
+        for i in 1..count {
+            let mut j = i;
+            while j > 0 && sorted[j - 1] > sorted[j] {
+                sorted.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        sorted[count / 2]
+    }
+}
+
+fn is_outlier(reading: f64, value: f64, config: &BotConfig) -> bool {
+    let diff = reading - value;
+
+    config.filter_outlier > 0.0
+        && (diff > config.filter_outlier || diff < -config.filter_outlier)
+}
+
+/// A side wall starting or ending beside the bot, and where it did
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WallEdge {
+    /// True if the wall appeared, false if it went away
+    pub appeared: bool,
+
+    /// The linear position at the first reading past the edge
+    pub linear_pos: f64,
+}
+
+/**
+ *  Finds where a side wall starts or ends, from the raw readings of a
+ *  side sensor
+ *
+ *  The filtered distances lag too far behind to place a post. A reading
+ *  closer than `wall_threshold` less `wall_hysteresis` is a wall, and one
+ *  further than `wall_threshold` plus `wall_hysteresis`, or out of range,
+ *  is not. Readings in between change nothing. It takes `wall_samples`
+ *  readings in a row to change sides, and the edge is placed at the first
+ *  of them.
+ */
+pub struct EdgeDetector {
+    wall: Option<bool>,
+    run: u32,
+    run_start: f64,
+    edge: Option<WallEdge>,
+}
+
+impl EdgeDetector {
+    pub fn new() -> EdgeDetector {
+        EdgeDetector {
+            wall: None,
+            run: 0,
+            run_start: 0.0,
+            edge: None,
+        }
+    }
+
+    /// Add a measurement, None if nothing was in range, taken at
+    /// `linear_pos`
+    pub fn add(
+        &mut self,
+        reading: Option<f64>,
+        linear_pos: f64,
+        config: &BotConfig,
+    ) {
+        let near = config.wall_threshold - config.wall_hysteresis;
+        let far = config.wall_threshold + config.wall_hysteresis;
+
+        let seen = match reading {
+            Some(reading) if reading < near => true,
+            Some(reading) if reading <= far => return,
+            _ => false,
+        };
+
+        let wall = match self.wall {
+            Some(wall) => wall,
+            None => {
+                self.wall = Some(seen);
+                return;
+            }
+        };
+
+        if seen == wall {
+            self.run = 0;
+            return;
+        }
+
+        if self.run == 0 {
+            self.run_start = linear_pos;
+        }
+
+        self.run += 1;
+
+        if self.run >= config.wall_samples {
+            self.wall = Some(seen);
+            self.run = 0;
+            self.edge = Some(WallEdge {
+                appeared: seen,
+                linear_pos: self.run_start,
+            });
+        }
+    }
+
+    /// The last edge found, if it hasn't been taken yet
+    pub fn take(&mut self) -> Option<WallEdge> {
+        self.edge.take()
+    }
+
+    /**
+     *  Forget any edge on the way, for when the linear position is reset
+     *
+     *  Whether there is a wall is kept, since the bot hasn't moved.
+     */
+    pub fn reset(&mut self) {
+        self.run = 0;
+        self.edge = None;
+    }
+}
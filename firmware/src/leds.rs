@@ -0,0 +1,71 @@
+use stm32f4xx_hal::gpio::{gpiob, Output, PushPull};
+use stm32f4xx_hal::prelude::*;
+
+use crate::time::Duration;
+
+/// Which of the LEDs are lit, in the order red, green, blue, orange
+pub type Pattern = [bool; 4];
+
+/// As many LEDs lit as there are quarters of `total` still `remaining`
+pub fn countdown(remaining: Duration, total: Duration) -> Pattern {
+    let quarter = total.as_micros() / 4;
+    let lit = if quarter > 0 {
+        (remaining.as_micros() + quarter - 1) / quarter
+    } else {
+        0
+    };
+
+    [lit >= 4, lit >= 3, lit >= 2, lit >= 1]
+}
+
+/// The four LEDs along the top of the board
+pub struct Leds {
+    red: gpiob::PB12<Output<PushPull>>,
+    green: gpiob::PB13<Output<PushPull>>,
+    blue: gpiob::PB14<Output<PushPull>>,
+    orange: gpiob::PB15<Output<PushPull>>,
+}
+
+impl Leds {
+    pub fn new(
+        red: gpiob::PB12<Output<PushPull>>,
+        green: gpiob::PB13<Output<PushPull>>,
+        blue: gpiob::PB14<Output<PushPull>>,
+        orange: gpiob::PB15<Output<PushPull>>,
+    ) -> Leds {
+        Leds {
+            red,
+            green,
+            blue,
+            orange,
+        }
+    }
+
+    pub fn show(&mut self, pattern: Pattern) {
+        let [red, green, blue, orange] = pattern;
+
+        if red {
+            self.red.set_high();
+        } else {
+            self.red.set_low();
+        }
+
+        if green {
+            self.green.set_high();
+        } else {
+            self.green.set_low();
+        }
+
+        if blue {
+            self.blue.set_high();
+        } else {
+            self.blue.set_low();
+        }
+
+        if orange {
+            self.orange.set_high();
+        } else {
+            self.orange.set_low();
+        }
+    }
+}
@@ -25,11 +25,21 @@ extern crate panic_halt;
 
 pub mod battery;
 pub mod bot;
+pub mod buttons;
+pub mod characterize;
 pub mod config;
 pub mod control;
+pub mod filter;
+pub mod leds;
+pub mod menu;
 pub mod motors;
 pub mod navigate;
 pub mod plan;
+pub mod range_ready;
+pub mod report;
+pub mod scheduler;
+pub mod sensors;
+pub mod supervisor;
 pub mod time;
 pub mod uart;
 pub mod vl6180x;
@@ -43,25 +53,175 @@ use stm32f4xx_hal::stm32 as stm32f405;
 
 use ignore_result::Ignore;
 
+use telemetry::Message;
+
 use crate::battery::Battery;
+use crate::time::Duration;
+use crate::time::Instant;
 use crate::time::Time;
 
+use crate::uart;
 use crate::uart::Command;
+use crate::uart::Output;
+use crate::uart::Subcommand;
 use crate::uart::Uart;
 
+use crate::motors;
 use crate::motors::left::{LeftEncoder, LeftMotor};
 use crate::motors::right::{RightEncoder, RightMotor};
 
 use crate::bot::Bot;
+use crate::buttons::Buttons;
 use crate::config::BotConfig;
+use crate::leds;
+use crate::leds::Leds;
+use crate::menu::Action;
+use crate::menu::Menu;
 
 use crate::control::Control;
 
 use crate::plan::Plan;
+use crate::plan::Trigger;
+
+use crate::range_ready::RangeReady;
+
+use crate::report::Report;
+
+use crate::scheduler::Scheduler;
+
+use crate::sensors::SensorConfig;
+use crate::sensors::Sensors;
+
+use crate::supervisor::PowerSupervisor;
+use crate::supervisor::SensorSupervisor;
 
 use crate::navigate::RandomNavigate;
 use crate::navigate::LessRandomNavigate;
 
+/// How often the telemetry is sent, which `uart::BAUD` has room for
+const TELEMETRY_PERIOD: Duration = Duration::from_millis(10);
+
+/// The commands at the start of a line
+#[derive(Copy, Clone)]
+enum TopCommand {
+    Plan,
+    Report,
+    Sensors,
+    Bot,
+    Loop,
+    Battery,
+    Uart,
+    Help,
+    StartReport,
+    StartTelemetry,
+    StopReport,
+}
+
+/**
+ *  The commands at the start of a line, listed by `help`
+ *
+ *  Lines are dispatched on the `TopCommand` of the name they start with,
+ *  so every command listed here has to be handled.
+ */
+const COMMANDS: &[(TopCommand, Subcommand)] = &[
+    (
+        TopCommand::Plan,
+        Subcommand {
+            name: "plan",
+            args: "...",
+            help: "commands for the moves through the maze",
+        },
+    ),
+    (
+        TopCommand::Report,
+        Subcommand {
+            name: "report",
+            args: "...",
+            help: "choose what the report prints",
+        },
+    ),
+    (
+        TopCommand::Sensors,
+        Subcommand {
+            name: "sensors",
+            args: "...",
+            help: "commands for the distance sensors",
+        },
+    ),
+    (
+        TopCommand::Bot,
+        Subcommand {
+            name: "bot",
+            args: "...",
+            help: "the same as plan control bot",
+        },
+    ),
+    (
+        TopCommand::Loop,
+        Subcommand {
+            name: "loop",
+            args: "...",
+            help: "show how steady the control loop is",
+        },
+    ),
+    (
+        TopCommand::Battery,
+        Subcommand {
+            name: "battery",
+            args: "...",
+            help: "commands for the battery",
+        },
+    ),
+    (
+        TopCommand::Uart,
+        Subcommand {
+            name: "uart",
+            args: "...",
+            help: "commands for the uart itself",
+        },
+    ),
+    (
+        TopCommand::Help,
+        Subcommand {
+            name: "help",
+            args: "[command...]",
+            help: "show the subcommands of a command",
+        },
+    ),
+    (
+        TopCommand::StartReport,
+        Subcommand {
+            name: "@",
+            args: "",
+            help: "start the report",
+        },
+    ),
+    (
+        TopCommand::StartTelemetry,
+        Subcommand {
+            name: "$",
+            args: "",
+            help: "start the binary telemetry",
+        },
+    ),
+    (
+        TopCommand::StopReport,
+        Subcommand {
+            name: "!",
+            args: "",
+            help: "stop the report and telemetry",
+        },
+    ),
+];
+
+/// The command named `name`, if there is one
+fn top_command(name: &str) -> Option<TopCommand> {
+    COMMANDS
+        .iter()
+        .find(|(_, subcommand)| subcommand.name == name)
+        .map(|&(command, _)| command)
+}
+
 // Setup the master clock out
 pub fn mco2_setup(rcc: &stm32f405::RCC, gpioc: &stm32f405::GPIOC) {
     rcc.ahb1enr.write(|w| w.gpiocen().set_bit());
@@ -76,13 +236,17 @@ fn main() -> ! {
     let mut cp = stm32f405::CorePeripherals::take().unwrap();
 
     // Init non-hal things
-    let mut time = Time::setup(&p.RCC, p.TIM1);
+    let time = Time::setup(&p.RCC, &mut cp.NVIC, p.TIM1);
 
-    while time.now() < 10000 {}
+    time.delay(Duration::from_millis(10000));
 
     let mut battery = Battery::setup(&p.RCC, &p.GPIOB, p.ADC1);
 
-    let mut uart = Uart::setup(&p.RCC, &mut cp.NVIC, p.USART1, &p.GPIOA);
+    let mut uart =
+        Uart::setup(&p.RCC, &mut cp.NVIC, p.USART1, p.DMA2, &p.GPIOA);
+
+    let range_ready =
+        RangeReady::setup(&p.RCC, &mut cp.NVIC, &p.SYSCFG, &p.EXTI);
 
     let left_motor = LeftMotor::setup(&p.RCC, p.TIM3, &p.GPIOA);
 
@@ -91,6 +255,8 @@ fn main() -> ! {
     let right_motor = RightMotor::setup(&p.RCC, p.TIM4, &p.GPIOB);
     let right_encoder = RightEncoder::setup(&p.RCC, &p.GPIOA, p.TIM5);
 
+    let mut scheduler = Scheduler::setup(&p.RCC, p.TIM7);
+
     // Init the hal things
     let rcc = p.RCC.constrain();
     let clocks = rcc.cfgr.freeze();
@@ -112,88 +278,109 @@ fn main() -> ! {
     blue_led.set_low();
 
     writeln!(uart, "Initializing").ignore();
-    uart.flush_tx(&mut time, 50);
+    uart.wait_tx(&time, Duration::from_millis(50));
 
-    let mut front_distance = {
+    let (mut front_distance, front_enable) = {
         let scl = gpiob.pb8.into_open_drain_output().into_alternate_af4();
         let sda = gpiob.pb9.into_open_drain_output().into_alternate_af4();
 
         let mut gpio0 = gpioc.pc0.into_open_drain_output();
         gpio0.set_high();
 
-        let mut gpio1 = gpioc.pc1.into_open_drain_output();
-        gpio1.set_high();
+        // Read through the range_ready interrupt, the pin just needs a pull-up
+        let _gpio1 = gpioc.pc1.into_pull_up_input();
 
         let i2c =
             stm32f4::i2c::I2c::i2c1(p.I2C1, (scl, sda), 100.khz(), clocks);
 
-        time.delay(10000);
+        time.delay(Duration::from_micros(10000));
 
         let mut distance = vl6180x::VL6180x::new(i2c, 0x29);
-        distance.init_private_registers();
-        distance.init_default();
-        distance
+
+        if let Err(e) = distance
+            .init_private_registers()
+            .and_then(|_| distance.init_default())
+        {
+            writeln!(uart, "front distance: {:?}", e).ignore();
+            uart.wait_tx(&time, Duration::from_millis(50));
+        }
+
+        (distance, gpio0)
     };
 
     orange_led.set_low();
     blue_led.set_high();
 
-    let mut left_distance = {
+    let (mut left_distance, left_enable) = {
         let scl = gpiob.pb10.into_open_drain_output().into_alternate_af4();
         let sda = gpiob.pb11.into_open_drain_output().into_alternate_af4();
 
         let mut gpio0 = gpioc.pc2.into_open_drain_output();
         gpio0.set_high();
 
-        let mut gpio1 = gpioc.pc3.into_open_drain_output();
-        gpio1.set_high();
+        // Read through the range_ready interrupt, the pin just needs a pull-up
+        let _gpio1 = gpioc.pc3.into_pull_up_input();
 
         let i2c =
             stm32f4::i2c::I2c::i2c2(p.I2C2, (scl, sda), 100.khz(), clocks);
 
-        time.delay(1000);
+        time.delay(Duration::from_micros(1000));
 
         let mut distance = vl6180x::VL6180x::new(i2c, 0x29);
-        distance.init_private_registers();
-        distance.init_default();
-        distance
+
+        if let Err(e) = distance
+            .init_private_registers()
+            .and_then(|_| distance.init_default())
+        {
+            writeln!(uart, "left distance: {:?}", e).ignore();
+            uart.wait_tx(&time, Duration::from_millis(50));
+        }
+
+        (distance, gpio0)
     };
 
     orange_led.set_high();
     blue_led.set_high();
 
-    let mut right_distance = {
+    let (mut right_distance, right_enable) = {
         let scl = gpioa.pa8.into_open_drain_output().into_alternate_af4();
         let sda = gpioc.pc9.into_open_drain_output().into_alternate_af4();
 
         let mut gpio0 = gpioc.pc4.into_open_drain_output();
         gpio0.set_high();
 
-        let mut gpio1 = gpioc.pc5.into_open_drain_output();
-        gpio1.set_high();
+        // Read through the range_ready interrupt, the pin just needs a pull-up
+        let _gpio1 = gpioc.pc5.into_pull_up_input();
 
         let i2c =
             stm32f4::i2c::I2c::i2c3(p.I2C3, (scl, sda), 100.khz(), clocks);
 
-        time.delay(1000);
+        time.delay(Duration::from_micros(1000));
 
         let mut distance = vl6180x::VL6180x::new(i2c, 0x29);
-        distance.init_private_registers();
-        distance.init_default();
-        distance
+
+        if let Err(e) = distance
+            .init_private_registers()
+            .and_then(|_| distance.init_default())
+        {
+            writeln!(uart, "right distance: {:?}", e).ignore();
+            uart.wait_tx(&time, Duration::from_millis(50));
+        }
+
+        (distance, gpio0)
     };
 
     blue_led.set_low();
     orange_led.set_low();
 
     writeln!(uart, "Reading id registers").ignore();
-    uart.flush_tx(&mut time, 50);
+    uart.wait_tx(&time, Duration::from_millis(50));
 
     for _ in 0..2 {
         let buf = front_distance.get_id_bytes();
 
         writeln!(uart, "{:x?}", buf).ignore();
-        uart.flush_tx(&mut time, 50);
+        uart.wait_tx(&time, Duration::from_millis(50));
 
         orange_led.toggle();
     }
@@ -202,7 +389,7 @@ fn main() -> ! {
         let buf = left_distance.get_id_bytes();
 
         writeln!(uart, "{:x?}", buf).ignore();
-        uart.flush_tx(&mut time, 50);
+        uart.wait_tx(&time, Duration::from_millis(50));
 
         orange_led.toggle();
     }
@@ -211,7 +398,7 @@ fn main() -> ! {
         let buf = right_distance.get_id_bytes();
 
         writeln!(uart, "{:x?}", buf).ignore();
-        uart.flush_tx(&mut time, 50);
+        uart.wait_tx(&time, Duration::from_millis(50));
 
         orange_led.toggle();
     }
@@ -223,11 +410,24 @@ fn main() -> ! {
         right_p: 2000.0,
         right_i: 4.0,
         right_d: 15000.0,
+        // Fit with bot characterize, then pasted in from the output of
+        // bot model defaults, as the fit is lost at a reset
+        left_kv: 0.0,
+        left_forward_deadband: 0.0,
+        left_backward_deadband: 0.0,
+        left_tau: 0.0,
+        right_kv: 0.0,
+        right_forward_deadband: 0.0,
+        right_backward_deadband: 0.0,
+        right_tau: 0.0,
+        pwm_frequency: 20000,
+        brake: false,
         spin_p: 0.01,
         spin_i: 0.0,
         spin_d: 0.0,
         spin_err: 15.0,
         spin_settle: 50,
+        spin_max_velocity: 2.0,
         linear_p: 0.0185,
         linear_i: 0.0,
         linear_d: 0.1,
@@ -238,27 +438,59 @@ fn main() -> ! {
         linear_err: 10.0,
         linear_front_err: 5.0,
         linear_settle: 50,
+        linear_max_velocity: 2.0,
+        curve_velocity: 1.0,
+        curve_spin_p: 0.01,
         ticks_per_spin: 2064.03,
         ticks_per_cell: 1620.0,
         cell_width: 180.0,
         cell_offset: 53.0,
         wall_threshold: 120.0,
-        front_wall_distance: 35.0
+        front_wall_distance: 35.0,
+        side_sensor_offset: 20.0,
+        post_width: 12.0,
+        wall_hysteresis: 10.0,
+        wall_samples: 2,
+        snap_window: 8.0,
+        stall_power: 4000.0,
+        stall_velocity: 0.1,
+        stall_time: 200,
+        collision_distance: 15.0,
+        pickup_range: 255.0,
+        pickup_load: 1000.0,
+        pickup_time: 300,
+        wave_distance: 50.0,
+        wave_countdown: 1000,
+        range_period: 0,
+        interleaved: false,
+        front_scaling: 1,
+        filter_median: 3,
+        filter_smoothing: 0.7,
+        filter_outlier: 60.0,
+        filter_outlier_count: 2,
+        filter_max_rate: 0.0,
     };
 
+    let mut sensors = Sensors::new(
+        front_distance,
+        left_distance,
+        right_distance,
+        range_ready,
+        SensorConfig::new(&config),
+    );
+
     let bot = Bot::new(
         left_motor,
         left_encoder,
         right_motor,
         right_encoder,
-        front_distance,
-        left_distance,
-        right_distance,
+        motors::timer_clock(&clocks),
         config,
     );
 
     let control = Control::new(bot);
 
+    // The navigator also has to match scheduler::Navigator
     /*
     let navigate = RandomNavigate::new([
         15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
@@ -269,105 +501,250 @@ fn main() -> ! {
         0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
     ]);
 
-    let mut plan = Plan::new(control, navigate);
+    let plan = Plan::new(control, navigate);
+
+    let mut supervisor =
+        SensorSupervisor::new(front_enable, left_enable, right_enable);
+
+    let mut power_supervisor = PowerSupervisor::new();
 
     writeln!(uart, "\n\nstart").ignore();
-    uart.flush_tx(&mut time, 1000);
+    uart.wait_tx(&time, Duration::from_millis(1000));
+
+    // From here on the control loops run from the scheduler's interrupt,
+    // and this loop only does the background work
+    scheduler.start(&mut cp.NVIC, plan);
+
+    let mut last_time = Instant::START;
+
+    let mut buttons = Buttons::new(left_button, middle_button, right_button);
+    let mut leds = Leds::new(red_led, green_led, blue_led, orange_led);
+    let mut menu = Menu::new();
+    let mut heartbeat = false;
+
+    let mut report = Report::new();
 
-    let mut last_time: u32 = 0;
+    let mut telemetry = false;
+    let mut last_telemetry = Instant::START;
 
-    let mut report = false;
+    let mut output = Output::new();
 
     loop {
-        let now: u32 = time.now();
+        let now = time.now();
 
         if let Some(line) = uart.read_line() {
             if let Ok(string) = str::from_utf8(&line) {
                 let string = string.trim_matches(|c| c as u8 == 0).trim();
                 writeln!(uart, ">> {}", string).ignore();
-                if string.starts_with('!') {
-                    writeln!(uart, "Stopping report").ignore();
-                    report = false;
-                } else if string.starts_with('@') {
-                    writeln!(uart, "Starting report").ignore();
-                    report = true;
-                } else {
-                    let mut args = string.split_whitespace();
-
-                    let command = args.next();
-
-                    if command == Some(plan.keyword_command()) {
-                        plan.handle_command(&mut uart, args);
-                    } else {
-                        writeln!(uart, "Invalid Command!").ignore();
+
+                let mut args = string.split_whitespace();
+
+                // The report keys work with anything after them
+                let key = string.starts_with(|c: char| "!@$".contains(c));
+                let command =
+                    if key { Some(&string[..1]) } else { args.next() };
+
+                // Commands write into the output, which is only sent once
+                // the plan has been let go
+                output.clear();
+
+                match command.and_then(top_command) {
+                    Some(TopCommand::Plan) => {
+                        scheduler.with_plan(|plan| {
+                            plan.handle_command(&mut output, args)
+                        });
+                    }
+                    Some(TopCommand::Report) => {
+                        report.handle_command(&mut output, args);
+                    }
+                    Some(TopCommand::Sensors) => {
+                        // The same as bot calibrate sensors, done below
+                        if args.clone().next() == Some("calibrate") {
+                            scheduler.with_plan(|plan| {
+                                plan.control().bot_mut().calibrate_sensors()
+                            });
+                        } else {
+                            sensors.handle_command(&mut output, args);
+                        }
+                    }
+                    Some(TopCommand::Bot) => {
+                        scheduler.with_plan(|plan| {
+                            let bot = plan.control().bot_mut();
+                            bot.handle_command(&mut output, args)
+                        });
+                    }
+                    Some(TopCommand::Loop) => {
+                        scheduler.handle_command(&mut output, args);
+                    }
+                    Some(TopCommand::Battery) => {
+                        battery.handle_command(&mut output, args);
+                    }
+                    Some(TopCommand::Uart) => {
+                        uart.handle_command(&mut output, args);
+                    }
+                    Some(TopCommand::Help) => {
+                        let topic = args.next();
+
+                        match topic.and_then(top_command) {
+                            Some(TopCommand::Plan) => {
+                                scheduler.with_plan(|plan| {
+                                    plan.help_command(&mut output, args)
+                                });
+                            }
+                            Some(TopCommand::Report) => {
+                                report.help_command(&mut output, args);
+                            }
+                            Some(TopCommand::Sensors) => {
+                                sensors.help_command(&mut output, args);
+                            }
+                            Some(TopCommand::Bot) => {
+                                scheduler.with_plan(|plan| {
+                                    let bot = plan.control().bot();
+                                    bot.help_command(&mut output, args)
+                                });
+                            }
+                            Some(TopCommand::Loop) => {
+                                scheduler.help_command(&mut output, args);
+                            }
+                            Some(TopCommand::Battery) => {
+                                battery.help_command(&mut output, args);
+                            }
+                            Some(TopCommand::Uart) => {
+                                uart.help_command(&mut output, args);
+                            }
+                            _ => {
+                                let commands = COMMANDS.iter().map(|(_, s)| s);
+                                uart::write_help(
+                                    &mut output,
+                                    "",
+                                    commands,
+                                    topic,
+                                );
+                            }
+                        }
+                    }
+                    Some(TopCommand::StartReport) => {
+                        writeln!(output, "Starting report").ignore();
+                        report.start();
+                    }
+                    Some(TopCommand::StartTelemetry) => {
+                        writeln!(output, "Starting telemetry").ignore();
+                        telemetry = true;
+                    }
+                    Some(TopCommand::StopReport) => {
+                        writeln!(output, "Stopping report").ignore();
+                        report.stop();
+                        telemetry = false;
+                    }
+                    None => {
+                        writeln!(output, "Invalid Command! Try help").ignore();
                     }
                 }
-            }
-        }
-
-        if now - last_time >= 20u32 {
-            if report {
-                writeln!(
-                    uart,
-                    "{}\t{}\t{:?}",
-                    //now,
-                    plan.x_pos(),
-                    plan.y_pos(),
-                    plan.direction(),
-                    //control.bot().left_pos(),
-                    //control.bot().right_pos(),
-                    //control.bot().right_target(),
-                    //control.bot().spin_pos(),
-                    //control.bot().left_velocity(),
-                    //control.bot().right_velocity(),
-                    //control.bot().left_power(),
-                    //control.bot().right_power(),
-                    //plan.control().bot().linear_pos(),
-                    //plan.control().bot().spin_pos(),
-                    //plan.control().bot().linear_velocity(),
-                    //plan.control().bot().spin_velocity(),
-                    //control.bot().linear_pos(),
-                    //control.currnt_move_name(),
-                    //plan.control().bot().left_distance(),
-                    //plan.control().bot().front_distance(),
-                    //plan.control().bot().right_distance(),
-                )
-                .ignore();
-            }
 
-            green_led.toggle();
+                // The bot asks for the sensors to be calibrated, since they
+                // are run from here. This blocks for a while, so the plan
+                // is stopped first.
+                let calibrate = scheduler.with_plan(|plan| {
+                    let calibrate =
+                        plan.control().bot_mut().take_calibrate_sensors();
+                    if calibrate {
+                        plan.stop();
+                    }
+                    calibrate
+                });
+                if calibrate {
+                    sensors.calibrate(&mut output);
+                }
 
-            if plan.control().is_idle() {
-                orange_led.set_low();
-            } else {
-                orange_led.set_high();
+                uart.add_str_wait(&output);
             }
+        }
 
-            if plan.is_win() {
-                blue_led.set_high();
-            } else {
-                blue_led.set_low();
-            }
+        // The sensors follow changes to their config, which lives with the
+        // bot, and their readings go back to the bot's filters
+        let config = scheduler
+            .with_plan(|plan| SensorConfig::new(&plan.control().bot().config));
+        let readings = sensors.update(now, config);
+        scheduler.with_plan(|plan| {
+            plan.control().bot_mut().add_readings(&readings)
+        });
+
+        if telemetry && now - last_telemetry >= TELEMETRY_PERIOD {
+            let state = scheduler
+                .with_plan(|plan| report::bot_state(plan.control().bot()));
+            report::send(&mut uart, now, Message::BotState(state));
+            last_telemetry = now;
+        }
 
-            if battery.is_dead() {
-                red_led.set_high();
-            } else {
-                red_led.set_low();
+        match menu.update(buttons.update(now)) {
+            // A refused run shows on the LEDs, or in the supervisor log
+            Some(Action::Go) => scheduler.with_plan(|plan| plan.go()).ignore(),
+            Some(Action::Arm) => {
+                scheduler.with_plan(|plan| plan.arm()).ignore()
             }
-
-            if left_button.is_low() {
-                plan.go();
+            Some(Action::Stop) => scheduler.with_plan(|plan| plan.stop()),
+            Some(Action::SetMode(mode)) => {
+                scheduler.with_plan(|plan| plan.set_mode(mode))
             }
+            Some(Action::SetSpeed(speed)) => scheduler.with_plan(|plan| {
+                plan.control().bot_mut().config.set_speed(speed)
+            }),
+            None => {}
+        }
 
-            if right_button.is_low() {
-                plan.stop();
-            }
+        if now - last_time >= Duration::from_millis(20) {
+            heartbeat = !heartbeat;
+
+            let (is_idle, is_win, is_picked_up, trigger, countdown) = scheduler
+                .with_plan(|plan| {
+                    (
+                        plan.control().is_idle(),
+                        plan.is_win(),
+                        plan.is_picked_up(),
+                        plan.trigger(),
+                        plan.control().bot().config.wave_countdown,
+                    )
+                });
+
+            // Orange blinks until the bot is put back in the start cell and
+            // started
+            let status = [
+                battery.is_dead() || power_supervisor.is_low(),
+                heartbeat,
+                is_win,
+                if is_picked_up { heartbeat } else { !is_idle },
+            ];
+
+            // All the LEDs blink while waiting for a hand wave, stay on
+            // while the hand is there, then count down to the start
+            let status = match trigger {
+                Some(Trigger::Waiting) => {
+                    let on = (now.as_millis() / 250) % 2 == 0;
+                    [on; 4]
+                }
+                Some(Trigger::Covered) => [true; 4],
+                Some(Trigger::Countdown(start)) => leds::countdown(
+                    start - now,
+                    Duration::from_millis(countdown as u64),
+                ),
+                None => status,
+            };
+
+            leds.show(menu.pattern(now).unwrap_or(status));
+
+            // A dead battery reads too low to scale the power by
+            let voltage = battery.voltage().filter(|_| !battery.is_dead());
+            scheduler.with_plan(|plan| {
+                plan.control().bot_mut().set_battery_voltage(voltage)
+            });
 
             last_time = now;
         }
 
-        plan.update(now);
+        report.update(now, &mut uart, &scheduler);
+        supervisor.update(now, &mut sensors, &scheduler, &mut uart);
         battery.update(now);
-        uart.flush_tx(&mut time, 50);
+        power_supervisor.update(now, &battery, &scheduler, &mut uart);
+        uart.flush_tx();
     }
 }
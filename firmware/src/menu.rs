@@ -0,0 +1,139 @@
+use crate::buttons::Press;
+use crate::buttons::Presses;
+use crate::config::Speed;
+use crate::leds::Pattern;
+use crate::plan::Mode;
+use crate::time::Instant;
+
+/// How long the page LED stays on or off while blinking, in ms
+const BLINK_TIME: u64 = 250;
+
+const MODES: [Mode; 3] = [Mode::Explore, Mode::SpeedRun, Mode::Return];
+const SPEEDS: [Speed; 3] = [Speed::Slow, Speed::Normal, Speed::Fast];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Page {
+    Mode,
+    Speed,
+}
+
+/// Something the buttons asked for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    Go,
+    Arm,
+    Stop,
+    SetMode(Mode),
+    SetSpeed(Speed),
+}
+
+/**
+ *  Picks the run mode and speed with the buttons, showing them on the
+ *  LEDs, so nothing needs the uart at the contest table
+ *
+ *  With the menu closed, the left button starts a run, or arms a
+ *  hands-free start if it is held, the right button stops it and the
+ *  middle one opens the menu. In the menu, the left and right buttons
+ *  step through the choices on the page, which take effect straight away,
+ *  and the middle one goes to the next page. Holding the middle button
+ *  closes the menu, and holding the left one closes it and arms the start.
+ *
+ *  The red LED shows the page, steady for the mode and blinking for the
+ *  speed, and one of the green, blue and orange LEDs shows the choice.
+ */
+pub struct Menu {
+    open: bool,
+    page: Page,
+    mode: usize,
+    speed: usize,
+}
+
+impl Menu {
+    /// The choices start out at exploring at normal speed
+    pub fn new() -> Menu {
+        Menu {
+            open: false,
+            page: Page::Mode,
+            mode: 0,
+            speed: 1,
+        }
+    }
+
+    /// Take the latest button presses, returning what they asked for
+    pub fn update(&mut self, presses: Presses) -> Option<Action> {
+        if !self.open {
+            if presses.left == Some(Press::Long) {
+                return Some(Action::Arm);
+            } else if presses.left.is_some() {
+                return Some(Action::Go);
+            } else if presses.right.is_some() {
+                return Some(Action::Stop);
+            } else if presses.middle.is_some() {
+                self.open = true;
+                self.page = Page::Mode;
+            }
+
+            return None;
+        }
+
+        match (presses.left, presses.middle, presses.right) {
+            (Some(Press::Long), _, _) => {
+                self.open = false;
+                Some(Action::Arm)
+            }
+            (_, _, Some(Press::Long)) => Some(Action::Stop),
+            (_, Some(Press::Long), _) => {
+                self.open = false;
+                None
+            }
+            (_, Some(Press::Short), _) => {
+                self.page = match self.page {
+                    Page::Mode => Page::Speed,
+                    Page::Speed => Page::Mode,
+                };
+                None
+            }
+            (Some(Press::Short), _, _) => Some(self.step(false)),
+            (_, _, Some(Press::Short)) => Some(self.step(true)),
+            _ => None,
+        }
+    }
+
+    /// Move to the next or previous choice on the page, wrapping around
+    fn step(&mut self, forward: bool) -> Action {
+        let step = |choice: usize, len: usize| {
+            if forward {
+                (choice + 1) % len
+            } else {
+                (choice + len - 1) % len
+            }
+        };
+
+        match self.page {
+            Page::Mode => {
+                self.mode = step(self.mode, MODES.len());
+                Action::SetMode(MODES[self.mode])
+            }
+            Page::Speed => {
+                self.speed = step(self.speed, SPEEDS.len());
+                Action::SetSpeed(SPEEDS[self.speed])
+            }
+        }
+    }
+
+    /// What to show on the LEDs, or None when the menu is closed
+    pub fn pattern(&self, now: Instant) -> Option<Pattern> {
+        if !self.open {
+            return None;
+        }
+
+        let (page, choice) = match self.page {
+            Page::Mode => (true, self.mode),
+            Page::Speed => {
+                ((now.as_millis() / BLINK_TIME) % 2 == 0, self.speed)
+            }
+        };
+
+        Some([page, choice == 0, choice == 1, choice == 2])
+    }
+}
@@ -1,8 +1,131 @@
 pub mod left;
 pub mod right;
 
+use stm32f4xx_hal::rcc::Clocks;
+
+/// The battery voltage the motor gains were tuned at
+pub const NOMINAL_VOLTAGE: f64 = 7.4;
+
+/**
+ *  The lowest voltage compensated for, a little under where the supervisor
+ *  cuts the battery off, so a sagging pack scales the power by at most
+ *  about 1.3
+ */
+const MIN_VOLTAGE: f64 = 5.6;
+
+/// The power at full duty cycle
+const MAX_POWER: i32 = 10000;
+
+/// The PWM frequency the timers are set up with, until it is changed
+const SETUP_FREQUENCY: u32 = 20000;
+
+/// The slowest PWM frequency, in Hz, below which the motors whine and the
+/// current ripples
+pub const MIN_PWM_FREQUENCY: u32 = 1000;
+
+/// The fastest PWM frequency, in Hz, past what the DRV8848 switches cleanly
+pub const MAX_PWM_FREQUENCY: u32 = 100_000;
+
+/// The clock the timers count before they are told the real one, which is
+/// the internal oscillator the chip starts on
+const SETUP_CLOCK: u32 = 16_000_000;
+
+/**
+ *  What the DRV8848 does with the motor between PWM pulses
+ *
+ *  With fast decay both inputs are low, so the motor coasts and the
+ *  current dies away quickly. With slow decay both are high, shorting the
+ *  motor so it brakes.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Decay {
+    Fast,
+    Slow,
+}
+
 pub trait Motor {
+    /**
+     *  Drive the motor with a power from -10000 to 10000
+     *
+     *  The power is scaled up as the battery drops below
+     *  `NOMINAL_VOLTAGE`, so the same power gives the same speed, down to
+     *  `MIN_VOLTAGE`.
+     */
     fn change_power(&mut self, power: i32);
+
+    /// Tell the motor what the battery is at, for `change_power`
+    fn set_voltage(&mut self, voltage: f64);
+
+    /**
+     *  Change the PWM frequency, in Hz, given the clock the motor timers
+     *  count
+     *
+     *  The power keeps the same range, it is just spread over a different
+     *  number of timer counts.
+     */
+    fn set_frequency(&mut self, timer_clock: u32, frequency: u32);
+
+    fn set_decay(&mut self, decay: Decay);
+}
+
+/// The clock the motor timers count, which is twice the APB1 clock unless
+/// that isn't divided down
+pub fn timer_clock(clocks: &Clocks) -> u32 {
+    if clocks.ppre1() == 1 {
+        clocks.pclk1().0
+    } else {
+        clocks.pclk1().0 * 2
+    }
+}
+
+/// The prescaler and reload values for a PWM frequency, with as fine a
+/// duty cycle as the 16 bit timers allow, the frequency being clamped to
+/// the range the motors take
+fn pwm_timing(timer_clock: u32, frequency: u32) -> (u16, u32) {
+    let frequency = frequency.max(MIN_PWM_FREQUENCY).min(MAX_PWM_FREQUENCY);
+    let counts = timer_clock / frequency;
+
+    // The reload is kept below the 16 bit limit so a compare value past it,
+    // for a pin held high, still fits
+    let prescaler = counts / 0xffff;
+    let reload = (counts / (prescaler + 1)).saturating_sub(1);
+
+    (prescaler as u16, reload.max(1))
+}
+
+/**
+ *  The compare values of the backward and forward channels for a power,
+ *  and whether each is enabled
+ *
+ *  A channel is high while the count is below its compare value, so one
+ *  past the reload holds it high.
+ */
+fn compare_values(
+    power: i32,
+    reload: u32,
+    decay: Decay,
+) -> (u32, u32, bool, bool) {
+    let duty = power.abs().min(MAX_POWER) as u32 * reload / MAX_POWER as u32;
+    let high = reload + 1;
+
+    match decay {
+        // Drive one input, leaving the other low
+        Decay::Fast if power > 0 => (duty, duty, false, true),
+        Decay::Fast => (duty, duty, true, false),
+
+        // Hold one input high, and drop the other for the drive part of
+        // each period, leaving both high to brake the rest
+        Decay::Slow if power > 0 => (reload - duty, high, true, true),
+        Decay::Slow if power < 0 => (high, reload - duty, true, true),
+        Decay::Slow => (high, high, true, true),
+    }
+}
+
+/// The duty cycle that gives `power` at `voltage` as it would be at the
+/// nominal voltage
+fn compensate(power: i32, voltage: f64) -> i32 {
+    let power = power as f64 * NOMINAL_VOLTAGE / voltage.max(MIN_VOLTAGE);
+    (power as i32).max(-MAX_POWER).min(MAX_POWER)
 }
 
 pub trait Encoder {
@@ -2,12 +2,14 @@ use core::i32;
 
 use stm32f4xx_hal::stm32 as stm32f405;
 
-use crate::motors::{Encoder, Motor};
-
-const FORWARD_DEADBAND: i32 = 0;
-const BACKWARD_DEADBAND: i32 = 0;
+use crate::motors::{compare_values, compensate, pwm_timing};
+use crate::motors::{Decay, Encoder, Motor};
+use crate::motors::{NOMINAL_VOLTAGE, SETUP_CLOCK, SETUP_FREQUENCY};
 
 pub struct LeftMotor {
+    voltage: f64,
+    reload: u32,
+    decay: Decay,
     timer: stm32f405::TIM3,
 }
 
@@ -31,9 +33,10 @@ impl LeftMotor {
         gpio.afrl.modify(|_, w| w.afrl6().af2().afrl7().af2());
 
         // setup the timer
-        timer.psc.write(|w| unsafe { w.psc().bits(10u16) });
+        let (prescaler, reload) = pwm_timing(SETUP_CLOCK, SETUP_FREQUENCY);
+        timer.psc.write(|w| unsafe { w.psc().bits(prescaler) });
         timer.cr1.write(|w| w.arpe().set_bit());
-        timer.arr.write(|w| w.arr().bits(10000u32));
+        timer.arr.write(|w| w.arr().bits(reload));
         timer.ccr1.write(|w| w.ccr1().bits(0u32));
         timer.ccr2.write(|w| w.ccr2().bits(0u32));
         timer.ccmr1_output.write(|w| unsafe {
@@ -50,31 +53,44 @@ impl LeftMotor {
         timer.ccer.write(|w| w.cc1e().clear_bit().cc2e().set_bit());
         timer.cr1.modify(|_, w| w.cen().set_bit());
 
-        LeftMotor { timer }
+        LeftMotor {
+            voltage: NOMINAL_VOLTAGE,
+            reload,
+            decay: Decay::Fast,
+            timer,
+        }
     }
 }
 
 impl Motor for LeftMotor {
     fn change_power(&mut self, power: i32) {
+        let power = compensate(power, self.voltage);
+
+        let (backward, forward, backward_enable, forward_enable) =
+            compare_values(power, self.reload, self.decay);
+
+        self.timer.ccr1.write(|w| w.ccr1().bits(backward));
+        self.timer.ccr2.write(|w| w.ccr2().bits(forward));
         self.timer.ccer.write(|w| {
-            if power > 0 {
-                let speed = (power.abs() + FORWARD_DEADBAND) as u32;
-                self.timer.ccr1.write(|w| w.ccr1().bits(speed));
-                self.timer.ccr2.write(|w| w.ccr2().bits(speed));
-                w.cc1e().clear_bit().cc2e().set_bit()
-            } else if power < 0 {
-                let speed = (power.abs() + BACKWARD_DEADBAND) as u32;
-                self.timer.ccr1.write(|w| w.ccr1().bits(speed));
-                self.timer.ccr2.write(|w| w.ccr2().bits(speed));
-                w.cc1e().set_bit().cc2e().clear_bit()
-            } else {
-                let speed = 0;
-                self.timer.ccr1.write(|w| w.ccr1().bits(speed));
-                self.timer.ccr2.write(|w| w.ccr2().bits(speed));
-                w.cc1e().set_bit().cc2e().clear_bit()
-            }
+            w.cc1e().bit(backward_enable).cc2e().bit(forward_enable)
         });
     }
+
+    fn set_voltage(&mut self, voltage: f64) {
+        self.voltage = voltage;
+    }
+
+    fn set_frequency(&mut self, timer_clock: u32, frequency: u32) {
+        let (prescaler, reload) = pwm_timing(timer_clock, frequency);
+
+        self.timer.psc.write(|w| unsafe { w.psc().bits(prescaler) });
+        self.timer.arr.write(|w| w.arr().bits(reload));
+        self.reload = reload;
+    }
+
+    fn set_decay(&mut self, decay: Decay) {
+        self.decay = decay;
+    }
 }
 
 pub struct LeftEncoder {
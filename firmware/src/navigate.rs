@@ -11,8 +11,9 @@ use crate::plan::Direction;
 use crate::plan::Move;
 use crate::plan::MoveOptions;
 
-use crate::uart::Uart;
 use crate::uart::Command;
+use crate::uart::Output;
+use crate::uart::Subcommand;
 
 const MAZE_SIZE: usize = 3;
 const MAZE_LIMIT: i32 = MAZE_SIZE as i32 - 1;
@@ -97,41 +98,53 @@ impl Navigate for LessRandomNavigate {
                 if move_options.left { front_cell <= left_cell } else { true } &&
                 if move_options.right { front_cell <= right_cell } else { true }
             {
-                [Some(Move::Forward), None]
+                [Some(Move::Forward(1)), None]
             } else if
                 move_options.left &&
                 if move_options.forward { left_cell <= front_cell } else { true } &&
                 if move_options.right { left_cell <= right_cell } else { true }
             {
-                [Some(Move::TurnLeft), Some(Move::Forward)]
+                [Some(Move::TurnLeft), Some(Move::Forward(1))]
             } else if
                 move_options.right &&
                 if move_options.forward { right_cell <= front_cell } else { true } &&
                 if move_options.left { right_cell <= left_cell } else { true }
             {
-                [Some(Move::TurnRight), Some(Move::Forward)]
+                [Some(Move::TurnRight), Some(Move::Forward(1))]
             } else {
-                [Some(Move::TurnAround), Some(Move::Forward)]
+                [Some(Move::TurnAround), Some(Move::Forward(1))]
             }
         }
     }
 }
 
+const LESS_RANDOM_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "cells",
+        args: "",
+        help: "show how many times each cell has been visited",
+    },
+];
+
 impl Command for LessRandomNavigate {
     fn keyword_command(&self) -> &str {
         "nav"
     }
 
+    fn subcommands(&self) -> &[Subcommand] {
+        LESS_RANDOM_SUBCOMMANDS
+    }
+
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         mut args: I,
     ) {
         let command = args.next();
 
         match command {
-            Some("cells") => writeln!(uart, "{:?}", self.cells).ignore(),
-            c => writeln!(uart, "lrn: unknown command: {:?}", c).ignore(),
+            Some("cells") => writeln!(out, "{:?}", self.cells).ignore(),
+            c => writeln!(out, "lrn: unknown command: {:?}", c).ignore(),
         }
     }
 }
@@ -152,36 +165,36 @@ impl Navigate for RandomNavigate {
     fn navigate(&mut self, _x: i32, _y: i32, _d: Direction, move_options: MoveOptions) -> [Option<Move>; 2] {
         match (move_options.left, move_options.forward, move_options.right) {
             (true, true, true) => match self.rng.gen_range(0, 3) {
-                0 => [Some(Move::TurnLeft), Some(Move::Forward)],
-                1 => [Some(Move::TurnRight), Some(Move::Forward)],
-                _ => [Some(Move::Forward), None],
+                0 => [Some(Move::TurnLeft), Some(Move::Forward(1))],
+                1 => [Some(Move::TurnRight), Some(Move::Forward(1))],
+                _ => [Some(Move::Forward(1)), None],
             },
 
             (true, false, true) => match self.rng.gen_range(0, 2) {
-                0 => [Some(Move::TurnLeft), Some(Move::Forward)],
-                _ => [Some(Move::TurnRight), Some(Move::Forward)],
+                0 => [Some(Move::TurnLeft), Some(Move::Forward(1))],
+                _ => [Some(Move::TurnRight), Some(Move::Forward(1))],
             },
 
             (false, true, true) => match self.rng.gen_range(0, 2) {
-                0 => [Some(Move::TurnRight), Some(Move::Forward)],
-                _ => [Some(Move::Forward), None],
+                0 => [Some(Move::TurnRight), Some(Move::Forward(1))],
+                _ => [Some(Move::Forward(1)), None],
             },
 
             (true, true, false) => match self.rng.gen_range(0, 2) {
-                0 => [Some(Move::TurnLeft), Some(Move::Forward)],
-                _ => [Some(Move::Forward), None],
+                0 => [Some(Move::TurnLeft), Some(Move::Forward(1))],
+                _ => [Some(Move::Forward(1)), None],
             },
 
-            (false, true, false) => [Some(Move::Forward), None],
+            (false, true, false) => [Some(Move::Forward(1)), None],
 
-            (true, false, false) => [Some(Move::TurnLeft), Some(Move::Forward)],
+            (true, false, false) => [Some(Move::TurnLeft), Some(Move::Forward(1))],
 
             (false, false, true) => {
-                [Some(Move::TurnRight), Some(Move::Forward)]
+                [Some(Move::TurnRight), Some(Move::Forward(1))]
             }
 
             (false, false, false) => {
-                [Some(Move::TurnAround), Some(Move::Forward)]
+                [Some(Move::TurnAround), Some(Move::Forward(1))]
             }
         }
     }
@@ -192,15 +205,19 @@ impl Command for RandomNavigate {
         "nav"
     }
 
+    fn subcommands(&self) -> &[Subcommand] {
+        &[]
+    }
+
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         mut args: I,
     ) {
         let command = args.next();
 
         match command {
-            _ => writeln!(uart, "rn: unknown command").ignore(),
+            _ => writeln!(out, "rn: unknown command").ignore(),
         }
     }
 }
@@ -5,18 +5,24 @@ use ignore_result::Ignore;
 use arrayvec::ArrayVec;
 
 use crate::control::Control;
+use crate::control::Fault;
 
 use crate::navigate::Navigate;
 
+use crate::time::Duration;
+use crate::time::Instant;
+
+use crate::uart;
 use crate::uart::Command;
-use crate::uart::Uart;
+use crate::uart::Output;
+use crate::uart::Subcommand;
 
 #[derive(Copy, Clone)]
 pub enum Move {
     TurnLeft,
     TurnRight,
     TurnAround,
-    Forward,
+    Forward(u32),
 }
 
 #[derive(Copy, Clone)]
@@ -26,6 +32,58 @@ pub struct MoveOptions {
     pub right: bool,
 }
 
+/**
+ *  How far past a cell boundary the walls of the cell are looked at, as a
+ *  fraction of a cell, so that the side sensors see walls instead of the
+ *  posts on the boundary
+ */
+const SENSE_OFFSET: f64 = 0.25;
+
+/// Progress of a forward move through the cell boundaries along it
+#[derive(Copy, Clone)]
+struct Crossing {
+    cells: u32,
+    crossed: u32,
+    offset: f64,
+
+    /// Whether the move stops on the last boundary instead of past it
+    to_edge: bool,
+
+    /// Whether the moves after the last cell have been planned
+    planned: bool,
+}
+
+/**
+ *  What a run is for, which decides where it ends
+ *
+ *  The navigators don't plan paths, so speed runs and returns wander the
+ *  same way exploring does, and only differ in where they stop.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    /// Keep going until stopped, learning the maze
+    Explore,
+
+    /// Stop in the goal
+    SpeedRun,
+
+    /// Stop back in the start cell
+    Return,
+}
+
+/// How far a hands-free start has got
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Trigger {
+    /// Waiting for a hand to come up in front of the bot
+    Waiting,
+
+    /// Waiting for the hand to be taken away again
+    Covered,
+
+    /// Counting down to the start of the run, at the given time
+    Countdown(Instant),
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum Direction {
     Up,
@@ -62,10 +120,22 @@ where
     control: Control,
     move_buffer: ArrayVec<[Move; 32]>,
     going: bool,
+    go_refused: Option<&'static str>,
+    paused: bool,
+    mode: Mode,
     navigate: N,
     x_pos: i32,
     y_pos: i32,
     direction: Direction,
+    smooth: bool,
+    at_edge: bool,
+    crossing: Option<Crossing>,
+    recovering: bool,
+    last_fault: Option<Fault>,
+    faults: u32,
+    picked_up: bool,
+    pickup_start: Option<Instant>,
+    trigger: Option<Trigger>,
 }
 
 impl<N> Plan<N>
@@ -77,19 +147,97 @@ where
             control,
             move_buffer: ArrayVec::new(),
             going: false,
+            go_refused: None,
+            paused: false,
+            mode: Mode::Explore,
             navigate,
             x_pos: 0,
             y_pos: 0,
             direction: Direction::Up,
+            smooth: false,
+            at_edge: false,
+            crossing: None,
+            recovering: false,
+            last_fault: None,
+            faults: 0,
+            picked_up: false,
+            pickup_start: None,
+            trigger: None,
         }
     }
 
-    pub fn update(&mut self, now: u32) {
+    /**
+     *  Update the plan
+     *
+     *  With smooth turns enabled, the bot drives between the edges of
+     *  cells instead of their centers, so that a turn followed by a
+     *  forward move can be driven as a single curve. `x_pos` and `y_pos`
+     *  are then the cell that the bot is entering.
+     *
+     *  Forward moves can cover several cells, and the position is kept
+     *  up as each cell boundary is crossed. The walls of the last cell
+     *  are sampled on the way into it, so the next move is known before
+     *  the bot gets to the end of the move, and a move that would just
+     *  continue forward extends the current one instead.
+     */
+    pub fn update(&mut self, now: Instant) {
+        self.check_pickup(now);
+        self.check_trigger(now);
+
+        // Nothing moves again until the bot is put back and started
+        if self.picked_up {
+            return;
+        }
+
         if self.control.is_idle() {
-            if let Some(next_move) = self.move_buffer.pop_at(0) {
+            self.recovering = false;
+
+            if let Some(crossing) = self.crossing.take() {
+                // The move may have been cut short by a front wall
+                for _ in crossing.crossed..crossing.cells {
+                    self.move_forward();
+                }
+            }
+
+            let next_move = if self.paused {
+                None
+            } else {
+                self.move_buffer.pop_at(0)
+            };
+
+            if let Some(next_move) = next_move {
                 let ticks_per_spin = self.control.bot().config.ticks_per_spin;
                 let ticks_per_cell = self.control.bot().config.ticks_per_cell;
+
+                let curve = self.at_edge
+                    && match self.move_buffer.get(0) {
+                        Some(Move::Forward(_)) => true,
+                        _ => false,
+                    };
+
                 match next_move {
+                    Move::TurnLeft if curve => {
+                        self.curve_forward();
+                        self.control.curve(-ticks_per_spin / 4.0);
+                        self.direction = self.direction.turn_left();
+                        self.move_forward();
+                    }
+
+                    Move::TurnRight if curve => {
+                        self.curve_forward();
+                        self.control.curve(ticks_per_spin / 4.0);
+                        self.direction = self.direction.turn_right();
+                        self.move_forward();
+                    }
+
+                    Move::TurnLeft | Move::TurnRight | Move::TurnAround
+                        if self.at_edge =>
+                    {
+                        // Pull into the center of the cell to turn in place
+                        self.move_buffer.insert(0, next_move);
+                        self.control.linear(ticks_per_cell / 2.0);
+                        self.at_edge = false;
+                    }
 
                     Move::TurnLeft => {
                         self.control.spin(-ticks_per_spin / 4.0);
@@ -106,42 +254,355 @@ where
                         self.direction = self.direction().turn_right().turn_right();
                     }
 
-                    Move::Forward => {
-                        self.control.linear(ticks_per_cell);
-                        let (dx, dy) = match self.direction {
-                            Direction::Up => (0, 1),
-                            Direction::Down => (0, -1),
-                            Direction::Left => (-1, 0),
-                            Direction::Right => (1, 0),
-                        };
-                        self.x_pos += dx;
-                        self.y_pos += dy;
+                    Move::Forward(cells) => {
+                        let distance = cells as f64 * ticks_per_cell;
+
+                        // Boundaries are half a cell closer when starting
+                        // from the center of a cell
+                        let offset = if self.at_edge { 0.0 } else { 0.5 };
+
+                        if self.at_edge {
+                            self.control.linear_to_edge(distance);
+                        } else if self.smooth {
+                            self.control.linear_to_edge(
+                                distance - ticks_per_cell / 2.0,
+                            );
+                            self.at_edge = true;
+                        } else {
+                            self.control.linear_cells(distance);
+                        }
+
+                        self.crossing = Some(Crossing {
+                            cells,
+                            crossed: 0,
+                            offset,
+                            to_edge: self.at_edge,
+                            planned: false,
+                        });
                     }
                 }
             } else {
-                if self.going {
-                    let threshold = self.control.bot().config.wall_threshold;
-                    let move_options = MoveOptions {
-                        left: self.control.bot().left_distance() > threshold,
-                        forward: self.control.bot().front_distance()
-                            > threshold,
-                        right: self.control.bot().right_distance() > threshold,
-                    };
+                if self.going && !self.paused {
+                    if self.at_goal() {
+                        self.finish();
+                    } else {
+                        let ahead = if self.at_edge { 0.5 } else { 0.0 };
+                        let move_options = self.move_options(ahead);
+
+                        let next_moves = self.navigate.navigate(
+                            self.x_pos,
+                            self.y_pos,
+                            self.direction,
+                            move_options,
+                        );
+
+                        self.add_moves(&next_moves);
+                    }
+                }
+            }
+        } else {
+            self.update_crossing();
 
-                    let next_moves = self.navigate.navigate(self.x_pos, self.y_pos, self.direction, move_options);
+            // The move to the edge runs straight into a curve that follows,
+            // instead of stopping at the corner
+            if self.at_edge && !self.paused && self.curve_next() {
+                self.control.run_through();
+            }
+        }
+
+        self.control.update(now);
+
+        if let Some((fault, linear_pos)) = self.control.take_fault() {
+            self.recover(fault, linear_pos);
+        }
+    }
+
+    /**
+     *  Back off after a move was cut short by a fault
+     *
+     *  The moves still waiting are dropped, and a forward move backs up
+     *  into the center of the cell it got to, where the navigator can
+     *  pick another way. Anything else can't be placed in the maze, so
+     *  the run is stopped, as it is if backing up fails too.
+     */
+    fn recover(&mut self, fault: Fault, linear_pos: f64) {
+        self.last_fault = Some(fault);
+        self.faults += 1;
+        self.move_buffer.clear();
+
+        match self.crossing.take() {
+            Some(crossing) if !self.recovering => {
+                let ticks_per_cell = self.control.bot().config.ticks_per_cell;
+                let center = (crossing.crossed as f64 + 0.5 - crossing.offset)
+                    * ticks_per_cell;
+
+                self.control.linear(center - linear_pos);
+                self.at_edge = false;
+                self.recovering = true;
+            }
+            _ => self.stop(),
+        }
+    }
+
+    /**
+     *  Check whether the bot has been picked up
+     *
+     *  A bot in the air sees nothing on any of its sensors, and its wheels
+     *  turn with next to no power. Wheels that sit still prove nothing, as
+     *  an idle bot in an open maze looks the same, so both have to be
+     *  turning faster than `stall_velocity`. Once that has lasted
+     *  `pickup_time`, the run and the moves are dropped, since the bot is
+     *  going to be put back somewhere else.
+     */
+    fn check_pickup(&mut self, now: Instant) {
+        // The wheels are meant to be off the ground for this
+        if self.picked_up || self.control.bot().is_characterizing() {
+            self.pickup_start = None;
+            return;
+        }
+
+        let bot = self.control.bot();
+        let config = &bot.config;
+
+        let unloaded = |power: f64, velocity: f64| {
+            velocity.abs() > config.stall_velocity
+                && power.abs() < config.pickup_load * velocity.abs()
+        };
+
+        let held_up = self.is_held_up()
+            && unloaded(bot.left_power(), bot.left_velocity())
+            && unloaded(bot.right_power(), bot.right_velocity());
+
+        if !held_up {
+            self.pickup_start = None;
+            return;
+        }
+
+        let pickup_start = *self.pickup_start.get_or_insert(now);
+        let pickup_time = Duration::from_millis(config.pickup_time as u64);
+
+        if now - pickup_start > pickup_time {
+            self.stop();
+            self.move_buffer.clear();
+            self.picked_up = true;
+            self.pickup_start = None;
+        }
+    }
+
+    /**
+     *  Follow a hands-free start along
+     *
+     *  Once armed, a hand brought within `wave_distance` of the front
+     *  sensor and taken away again starts the countdown, and the run
+     *  starts when it runs out. This needs the cell in front of the start
+     *  to be open, or the wall would look like a hand that never leaves.
+     */
+    fn check_trigger(&mut self, now: Instant) {
+        let trigger = match self.trigger {
+            Some(trigger) => trigger,
+            None => return,
+        };
+
+        let config = &self.control.bot().config;
+        let covered = self
+            .control
+            .bot()
+            .front_distance()
+            .map_or(false, |d| d < config.wave_distance);
+        let countdown = Duration::from_millis(config.wave_countdown as u64);
+
+        self.trigger = match trigger {
+            Trigger::Waiting if covered => Some(Trigger::Covered),
+            Trigger::Covered if !covered => {
+                Some(Trigger::Countdown(now + countdown))
+            }
+            Trigger::Countdown(start) if now >= start => {
+                // Anything that refuses the run has its own way of showing
+                self.go().ignore();
+                None
+            }
+            trigger => Some(trigger),
+        };
+    }
 
-                    self.add_moves(&next_moves);
+    /// Whether all the distance sensors are out of range
+    fn is_held_up(&self) -> bool {
+        let bot = self.control.bot();
+        let saturated = |distance: Option<f64>| {
+            distance.map_or(true, |d| d >= bot.config.pickup_range)
+        };
+
+        saturated(bot.front_distance())
+            && saturated(bot.left_distance())
+            && saturated(bot.right_distance())
+    }
+
+    /**
+     *  Put the bot back in the start cell, after it was picked up
+     *
+     *  Only the position is reset, so the navigator keeps what it has
+     *  learned about the maze.
+     */
+    fn restart(&mut self) {
+        self.stop();
+        self.move_buffer.clear();
+        self.x_pos = 0;
+        self.y_pos = 0;
+        self.direction = Direction::Up;
+        self.at_edge = false;
+        self.picked_up = false;
+    }
+
+    /**
+     *  Follow a forward move across the cell boundaries, and plan the
+     *  moves after it
+     *
+     *  The navigator is only asked about the last cell, once the bot is
+     *  `SENSE_OFFSET` into it, and a move straight on extends this one. A
+     *  move that stops on the boundary is asked as it gets there, as it
+     *  never gets any further.
+     */
+    fn update_crossing(&mut self) {
+        if let Some(mut crossing) = self.crossing {
+            let ticks_per_cell = self.control.bot().config.ticks_per_cell;
+
+            // A move ending on a boundary may settle just short of it
+            let linear_pos = self.control.bot().linear_pos()
+                + self.control.bot().config.linear_err;
+
+            let boundary = |crossed: u32, offset: f64| {
+                (crossed as f64 + 1.0 - offset) * ticks_per_cell
+            };
+
+            while crossing.crossed < crossing.cells
+                && linear_pos >= boundary(crossing.crossed, crossing.offset)
+            {
+                crossing.crossed += 1;
+                self.move_forward();
+            }
+
+            let sense_offset =
+                if crossing.to_edge { 0.0 } else { SENSE_OFFSET };
+            let sense_pos = boundary(crossing.cells - 1, crossing.offset)
+                + sense_offset * ticks_per_cell;
+
+            if crossing.crossed == crossing.cells
+                && !crossing.planned
+                && linear_pos >= sense_pos
+            {
+                crossing.planned = true;
+
+                if self.going
+                    && !self.paused
+                    && !self.at_goal()
+                    && self.move_buffer.is_empty()
+                {
+                    let move_options = self.move_options(0.5 - sense_offset);
+
+                    let next_moves = self.navigate.navigate(
+                        self.x_pos,
+                        self.y_pos,
+                        self.direction,
+                        move_options,
+                    );
+
+                    match next_moves {
+                        [Some(Move::Forward(cells)), None]
+                            if self.control.extend_linear(
+                                cells as f64 * ticks_per_cell,
+                            ) =>
+                        {
+                            crossing.cells += cells;
+                            crossing.planned = false;
+                        }
+                        _ => self.add_moves(&next_moves),
+                    }
                 }
             }
+
+            self.crossing = Some(crossing);
+        }
+    }
+
+    /// Whether the bot is where a run in the current mode ends
+    fn at_goal(&self) -> bool {
+        match self.mode {
+            Mode::Explore => false,
+            Mode::SpeedRun => self.is_win(),
+            Mode::Return => self.x_pos == 0 && self.y_pos == 0,
+        }
+    }
+
+    /// End the run, pulling into the center of the cell if on its edge
+    fn finish(&mut self) {
+        if self.at_edge {
+            let ticks_per_cell = self.control.bot().config.ticks_per_cell;
+            self.control.linear(ticks_per_cell / 2.0);
+            self.at_edge = false;
+        }
+
+        self.going = false;
+    }
+
+    /**
+     *  Check which ways out of the current cell are open, with the center
+     *  of the cell `ahead` of the bot, as a fraction of a cell
+     *
+     *  The front wall is that much further away than from the center.
+     */
+    fn move_options(&self, ahead: f64) -> MoveOptions {
+        let bot = self.control.bot();
+        let threshold = bot.config.wall_threshold;
+        let front_threshold = threshold + bot.config.cell_width * ahead;
+
+        // A sensor without a valid reading does not see a wall
+        MoveOptions {
+            left: bot.left_distance().map_or(true, |d| d > threshold),
+            forward: bot.front_distance().map_or(true, |d| d > front_threshold),
+            right: bot.right_distance().map_or(true, |d| d > threshold),
+        }
+    }
+
+    /// Whether the moves waiting start with a turn to be driven as a curve
+    fn curve_next(&self) -> bool {
+        match (self.move_buffer.get(0), self.move_buffer.get(1)) {
+            (Some(Move::TurnLeft), Some(Move::Forward(_)))
+            | (Some(Move::TurnRight), Some(Move::Forward(_))) => true,
+            _ => false,
+        }
+    }
+
+    /// Take the first cell of the forward move that a curve replaces
+    fn curve_forward(&mut self) {
+        if let Some(Move::Forward(cells)) = self.move_buffer.pop_at(0) {
+            if cells > 1 {
+                self.move_buffer.insert(0, Move::Forward(cells - 1));
+            }
         }
+    }
 
-        self.control.update(now);
+    fn move_forward(&mut self) {
+        let (dx, dy) = match self.direction {
+            Direction::Up => (0, 1),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        };
+        self.x_pos += dx;
+        self.y_pos += dy;
     }
 
+    /// Queue up moves, joining moves forward into one longer move
     pub fn add_moves(&mut self, next_moves: &[Option<Move>]) {
         for &next_move in next_moves {
-            if let Some(m) = next_move {
-                self.move_buffer.try_push(m);
+            match (self.move_buffer.last_mut(), next_move) {
+                (Some(Move::Forward(cells)), Some(Move::Forward(more))) => {
+                    *cells += more;
+                }
+                (_, None) | (_, Some(Move::Forward(0))) => {}
+                (_, Some(m)) => {
+                    self.move_buffer.try_push(m).ignore();
+                }
             }
         }
     }
@@ -150,15 +611,99 @@ where
         &mut self.control
     }
 
-    pub fn go(&mut self) {
+    /**
+     *  Start a run with a hand wave in front of the bot, instead of with a
+     *  button that would nudge it, unless runs are being refused
+     */
+    pub fn arm(&mut self) -> Result<(), &'static str> {
+        if let Some(reason) = self.go_refused {
+            return Err(reason);
+        }
+
+        self.trigger = Some(Trigger::Waiting);
+        Ok(())
+    }
+
+    /// How far a hands-free start has got, if one is armed
+    pub fn trigger(&self) -> Option<Trigger> {
+        self.trigger
+    }
+
+    /**
+     *  Start exploring, unless runs are being refused, in which case why
+     *
+     *  A bot that was picked up starts again from the start cell, once it
+     *  has been put down.
+     */
+    pub fn go(&mut self) -> Result<(), &'static str> {
+        if let Some(reason) = self.go_refused {
+            return Err(reason);
+        }
+
+        if self.picked_up {
+            if self.is_held_up() {
+                return Err("picked up");
+            }
+
+            self.restart();
+        }
+
         self.going = true;
+        Ok(())
+    }
+
+    /// Refuse to start runs because of `reason`, or allow them with None
+    pub fn refuse_go(&mut self, reason: Option<&'static str>) {
+        self.go_refused = reason;
     }
 
     pub fn stop(&mut self) {
         self.going = false;
+        self.trigger = None;
+        self.crossing = None;
+        self.recovering = false;
         self.control.stop();
     }
 
+    /**
+     *  Hold the plan after the current move, without forgetting where it
+     *  was going
+     */
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /**
+     *  Enable or disable smooth turns
+     *
+     *  This can only be changed while the bot is sitting in the center of
+     *  a cell, since it changes where the bot stops between moves.
+     */
+    pub fn set_smooth(&mut self, smooth: bool) -> bool {
+        if !self.at_edge && self.control.is_idle() {
+            self.smooth = smooth;
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn x_pos(&self) -> i32 {
         self.x_pos
     }
@@ -171,11 +716,84 @@ where
         self.direction
     }
 
+    /// The fault that last cut a move short, and how many there have been
+    pub fn last_fault(&self) -> (Option<Fault>, u32) {
+        (self.last_fault, self.faults)
+    }
+
+    /// Whether the bot was picked up, and hasn't been started again
+    pub fn is_picked_up(&self) -> bool {
+        self.picked_up
+    }
+
     pub fn is_win(&self) -> bool {
         self.x_pos == 1 && self.y_pos == 1
     }
 }
 
+const PLAN_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "control",
+        args: "...",
+        help: "commands for the current move",
+    },
+    Subcommand {
+        name: "nav",
+        args: "...",
+        help: "commands for the navigator",
+    },
+    Subcommand {
+        name: "left",
+        args: "",
+        help: "add a left turn",
+    },
+    Subcommand {
+        name: "right",
+        args: "",
+        help: "add a right turn",
+    },
+    Subcommand {
+        name: "around",
+        args: "",
+        help: "add a turn around",
+    },
+    Subcommand {
+        name: "forward",
+        args: "[cells]",
+        help: "add a move forward, 1 cell if not given",
+    },
+    Subcommand {
+        name: "smooth",
+        args: "[on|off]",
+        help: "turn smooth moves on or off, or show if they are",
+    },
+    Subcommand {
+        name: "mode",
+        args: "[explore|speed|return]",
+        help: "set where runs end, or show it",
+    },
+    Subcommand {
+        name: "go",
+        args: "",
+        help: "start exploring the maze",
+    },
+    Subcommand {
+        name: "arm",
+        args: "",
+        help: "start exploring after a hand wave in front of the bot",
+    },
+    Subcommand {
+        name: "fault",
+        args: "",
+        help: "show the last stall or collision, and how many there were",
+    },
+    Subcommand {
+        name: "stop",
+        args: "",
+        help: "stop and clear the moves",
+    },
+];
+
 impl<N> Command for Plan<N>
 where
     N: Navigate,
@@ -184,26 +802,91 @@ where
         "plan"
     }
 
+    fn subcommands(&self) -> &[Subcommand] {
+        PLAN_SUBCOMMANDS
+    }
+
+    fn help_command<'a, I: Iterator<Item = &'a str>>(
+        &self,
+        out: &mut Output,
+        mut path: I,
+    ) {
+        let command = path.next();
+
+        if command == Some(self.control.keyword_command()) {
+            self.control.help_command(out, path);
+        } else if command == Some(self.navigate.keyword_command()) {
+            self.navigate.help_command(out, path);
+        } else {
+            uart::write_help(out, "plan", PLAN_SUBCOMMANDS, command);
+        }
+    }
+
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         mut args: I,
     ) {
         let command = args.next();
 
         if command == Some(self.control.keyword_command()) {
-            self.control.handle_command(uart, args);
+            self.control.handle_command(out, args);
         } else if command == Some(self.navigate.keyword_command()) {
-            self.navigate.handle_command(uart, args);
+            self.navigate.handle_command(out, args);
         } else {
             match command {
                 Some("left") => self.add_moves(&[Some(Move::TurnLeft)]),
                 Some("right") => self.add_moves(&[Some(Move::TurnRight)]),
                 Some("around") => self.add_moves(&[Some(Move::TurnAround)]),
-                Some("forward") => self.add_moves(&[Some(Move::Forward)]),
-                Some("go") => self.go(),
+                Some("forward") => {
+                    let cells = args.next().and_then(|s| s.parse().ok());
+                    self.add_moves(&[Some(Move::Forward(cells.unwrap_or(1)))])
+                }
+                Some("smooth") => match args.next() {
+                    Some("on") => {
+                        if !self.set_smooth(true) {
+                            writeln!(out, "plan: not in a cell center")
+                                .ignore();
+                        }
+                    }
+                    Some("off") => {
+                        if !self.set_smooth(false) {
+                            writeln!(out, "plan: not in a cell center")
+                                .ignore();
+                        }
+                    }
+                    _ => writeln!(out, "smooth: {}", self.smooth).ignore(),
+                },
+                Some("mode") => match args.next() {
+                    Some("explore") => self.set_mode(Mode::Explore),
+                    Some("speed") => self.set_mode(Mode::SpeedRun),
+                    Some("return") => self.set_mode(Mode::Return),
+                    Some(m) => {
+                        writeln!(out, "plan: unknown mode: {}", m).ignore()
+                    }
+                    None => writeln!(out, "mode: {:?}", self.mode).ignore(),
+                },
+                Some("go") => {
+                    if let Err(reason) = self.go() {
+                        writeln!(out, "plan: can't go: {}", reason).ignore();
+                    }
+                }
+                Some("fault") => match self.last_fault {
+                    Some(fault) => writeln!(
+                        out,
+                        "plan: last fault: {:?} faults: {}",
+                        fault, self.faults
+                    )
+                    .ignore(),
+                    None => writeln!(out, "plan: no faults").ignore(),
+                },
+                Some("arm") => {
+                    if let Err(reason) = self.arm() {
+                        writeln!(out, "plan: can't arm: {}", reason).ignore();
+                    }
+                }
                 Some("stop") => self.stop(),
-                _ => writeln!(uart, "plan: unknown command").ignore(),
+                _ => writeln!(out, "plan: unknown command").ignore(),
             }
         }
     }
@@ -0,0 +1,114 @@
+use core::cell::Cell;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt_macros::interrupt as isr;
+
+use stm32f4xx_hal::stm32 as stm32f405;
+use stm32f4xx_hal::stm32::Interrupt as interrupt;
+
+use crate::sensors::DistanceSensor;
+use crate::time;
+use crate::time::Instant;
+
+/// When each sensor last signalled a result, in the order front, left, right
+static READY: Mutex<Cell<[Option<Instant>; 3]>> =
+    Mutex::new(Cell::new([None, None, None]));
+
+/// The EXTI lines of the GPIO1 pins, PC1, PC3 and PC5
+const FRONT_LINE: u32 = 1;
+const LEFT_LINE: u32 = 3;
+const RIGHT_LINE: u32 = 5;
+
+/// EXTICR value selecting port C
+const PORT_C: u32 = 0b0010;
+
+fn index(sensor: DistanceSensor) -> usize {
+    match sensor {
+        DistanceSensor::Front => 0,
+        DistanceSensor::Left => 1,
+        DistanceSensor::Right => 2,
+    }
+}
+
+/**
+ *  The GPIO1 lines of the distance sensors, which go low when a new
+ *  range is ready
+ *
+ *  The falling edges are caught with EXTI interrupts, which only note the
+ *  time so that the i2c transfer can be done from the main loop.
+ */
+pub struct RangeReady {}
+
+impl RangeReady {
+    pub fn setup(
+        rcc: &stm32f405::RCC,
+        nvic: &mut stm32f405::NVIC,
+        syscfg: &stm32f405::SYSCFG,
+        exti: &stm32f405::EXTI,
+    ) -> RangeReady {
+        // enable clock for syscfg, which routes the pins to exti
+        rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+
+        syscfg.exticr1.modify(|r, w| unsafe {
+            w.bits(
+                r.bits() & !(0xf << 4 | 0xf << 12) | PORT_C << 4 | PORT_C << 12,
+            )
+        });
+        syscfg.exticr2.modify(|r, w| unsafe {
+            w.bits(r.bits() & !(0xf << 4) | PORT_C << 4)
+        });
+
+        let lines = 1 << FRONT_LINE | 1 << LEFT_LINE | 1 << RIGHT_LINE;
+
+        exti.ftsr.modify(|r, w| unsafe { w.bits(r.bits() | lines) });
+        exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | lines) });
+
+        nvic.enable(interrupt::EXTI1);
+        nvic.enable(interrupt::EXTI3);
+        nvic.enable(interrupt::EXTI9_5);
+
+        RangeReady {}
+    }
+
+    /// When the sensor last signalled a new range, if it has since the
+    /// last call
+    pub fn take(&self, sensor: DistanceSensor) -> Option<Instant> {
+        cortex_m::interrupt::free(|cs| {
+            let cell = READY.borrow(cs);
+            let mut ready = cell.get();
+            let time = ready[index(sensor)].take();
+            cell.set(ready);
+            time
+        })
+    }
+}
+
+fn mark_ready(sensor: DistanceSensor, line: u32) {
+    let exti = unsafe { &*stm32f405::EXTI::ptr() };
+
+    if exti.pr.read().bits() & (1 << line) != 0 {
+        exti.pr.write(|w| unsafe { w.bits(1 << line) });
+
+        cortex_m::interrupt::free(|cs| {
+            let cell = READY.borrow(cs);
+            let mut ready = cell.get();
+            ready[index(sensor)] = Some(time::now());
+            cell.set(ready);
+        });
+    }
+}
+
+#[isr]
+fn EXTI1() {
+    mark_ready(DistanceSensor::Front, FRONT_LINE);
+}
+
+#[isr]
+fn EXTI3() {
+    mark_ready(DistanceSensor::Left, LEFT_LINE);
+}
+
+#[isr]
+fn EXTI9_5() {
+    mark_ready(DistanceSensor::Right, RIGHT_LINE);
+}
@@ -0,0 +1,370 @@
+use core::fmt::Write;
+
+use arrayvec::ArrayVec;
+
+use ignore_result::Ignore;
+
+use telemetry::BotState;
+use telemetry::Frame;
+use telemetry::Message;
+
+use crate::bot::Bot;
+use crate::navigate::Navigate;
+use crate::plan::Direction;
+use crate::plan::Plan;
+use crate::scheduler::Scheduler;
+use crate::time::Duration;
+use crate::time::Instant;
+use crate::uart::Command;
+use crate::uart::Output;
+use crate::uart::Subcommand;
+use crate::uart::Uart;
+
+pub fn bot_state(bot: &Bot) -> BotState {
+    BotState {
+        left_pos: bot.left_pos() as f32,
+        right_pos: bot.right_pos() as f32,
+        left_velocity: bot.left_velocity() as f32,
+        right_velocity: bot.right_velocity() as f32,
+        left_power: bot.left_power() as f32,
+        right_power: bot.right_power() as f32,
+        left_target: bot.left_target() as f32,
+        right_target: bot.right_target() as f32,
+        linear_pos: bot.linear_pos() as f32,
+        spin_pos: bot.spin_pos() as f32,
+        front_distance: bot.front_distance().map(|d| d as f32),
+        left_distance: bot.left_distance().map(|d| d as f32),
+        right_distance: bot.right_distance().map(|d| d as f32),
+    }
+}
+
+/**
+ *  Send a binary telemetry frame
+ *
+ *  The frame is dropped whole if the uart buffer is too full for it.
+ */
+pub fn send(uart: &mut Uart, now: Instant, message: Message) {
+    let mut buf = [0; telemetry::MAX_FRAME_LEN];
+
+    let frame = Frame {
+        time: now.as_millis() as u32,
+        message,
+    };
+
+    if let Ok(len) = telemetry::encode(&frame, &mut buf) {
+        uart.add_bytes(&buf[..len]).ignore();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Signal {
+    Time,
+    XPos,
+    YPos,
+    Direction,
+    CurrentMove,
+    LeftPos,
+    RightPos,
+    LeftVelocity,
+    RightVelocity,
+    LeftPower,
+    RightPower,
+    LeftTarget,
+    RightTarget,
+    LinearPos,
+    SpinPos,
+    LinearVelocity,
+    SpinVelocity,
+    FrontDistance,
+    LeftDistance,
+    RightDistance,
+    BatteryVoltage,
+}
+
+const SIGNALS: [(&str, Signal); 21] = [
+    ("time", Signal::Time),
+    ("x_pos", Signal::XPos),
+    ("y_pos", Signal::YPos),
+    ("direction", Signal::Direction),
+    ("current_move", Signal::CurrentMove),
+    ("left_pos", Signal::LeftPos),
+    ("right_pos", Signal::RightPos),
+    ("left_velocity", Signal::LeftVelocity),
+    ("right_velocity", Signal::RightVelocity),
+    ("left_power", Signal::LeftPower),
+    ("right_power", Signal::RightPower),
+    ("left_target", Signal::LeftTarget),
+    ("right_target", Signal::RightTarget),
+    ("linear_pos", Signal::LinearPos),
+    ("spin_pos", Signal::SpinPos),
+    ("linear_velocity", Signal::LinearVelocity),
+    ("spin_velocity", Signal::SpinVelocity),
+    ("front_distance", Signal::FrontDistance),
+    ("left_distance", Signal::LeftDistance),
+    ("right_distance", Signal::RightDistance),
+    ("battery_voltage", Signal::BatteryVoltage),
+];
+
+impl Signal {
+    pub fn from_name(name: &str) -> Option<Signal> {
+        SIGNALS.iter().find(|(n, _)| *n == name).map(|&(_, s)| s)
+    }
+
+    pub fn name(self) -> &'static str {
+        SIGNALS
+            .iter()
+            .find(|&&(_, s)| s == self)
+            .map(|&(n, _)| n)
+            .unwrap_or("")
+    }
+
+    fn value<N: Navigate>(self, now: Instant, plan: &mut Plan<N>) -> Value {
+        match self {
+            Signal::Time => Value::Int(now.as_millis() as i64),
+            Signal::XPos => Value::Int(plan.x_pos() as i64),
+            Signal::YPos => Value::Int(plan.y_pos() as i64),
+            Signal::Direction => Value::Direction(plan.direction()),
+            Signal::CurrentMove => {
+                Value::Name(plan.control().current_move_name())
+            }
+            _ => match self.bot_value(plan.control().bot()) {
+                Some(value) => Value::Float(value),
+                None => Value::Missing,
+            },
+        }
+    }
+
+    fn bot_value(self, bot: &Bot) -> Option<f64> {
+        match self {
+            Signal::LeftPos => Some(bot.left_pos()),
+            Signal::RightPos => Some(bot.right_pos()),
+            Signal::LeftVelocity => Some(bot.left_velocity()),
+            Signal::RightVelocity => Some(bot.right_velocity()),
+            Signal::LeftPower => Some(bot.left_power()),
+            Signal::RightPower => Some(bot.right_power()),
+            Signal::LeftTarget => Some(bot.left_target()),
+            Signal::RightTarget => Some(bot.right_target()),
+            Signal::LinearPos => Some(bot.linear_pos()),
+            Signal::SpinPos => Some(bot.spin_pos()),
+            Signal::LinearVelocity => Some(bot.linear_velocity()),
+            Signal::SpinVelocity => Some(bot.spin_velocity()),
+            Signal::FrontDistance => bot.front_distance(),
+            Signal::LeftDistance => bot.left_distance(),
+            Signal::RightDistance => bot.right_distance(),
+            Signal::BatteryVoltage => bot.battery_voltage(),
+            _ => None,
+        }
+    }
+}
+
+/// A signal taken from the plan, to be printed once the plan is let go of
+#[derive(Copy, Clone)]
+enum Value {
+    Int(i64),
+    Float(f64),
+    Missing,
+    Direction(Direction),
+    Name(&'static str),
+}
+
+impl Value {
+    fn write(self, uart: &mut Uart) {
+        match self {
+            Value::Int(value) => write!(uart, "{}", value).ignore(),
+            Value::Float(value) => write!(uart, "{}", value).ignore(),
+            // Missing distances are nan so plots skip them
+            Value::Missing => write!(uart, "nan").ignore(),
+            Value::Direction(direction) => {
+                write!(uart, "{:?}", direction).ignore()
+            }
+            Value::Name(name) => write!(uart, "{}", name).ignore(),
+        }
+    }
+}
+
+/**
+ *  Prints a selected set of signals, tab separated, every `period` ms
+ *
+ *  What gets printed is picked at runtime with the `report` commands, so
+ *  that switching what to look at does not need a reflash.
+ */
+pub struct Report {
+    signals: ArrayVec<[Signal; 16]>,
+    period: u32,
+    last_report: Instant,
+    running: bool,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        let mut signals = ArrayVec::new();
+        signals.push(Signal::XPos);
+        signals.push(Signal::YPos);
+        signals.push(Signal::Direction);
+
+        Report {
+            signals,
+            period: 20,
+            last_report: Instant::START,
+            running: false,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    /**
+     *  Print the signals if it is time to
+     *
+     *  The values are all taken at once, so the control loop is only held
+     *  off for that and not while they are printed.
+     */
+    pub fn update(
+        &mut self,
+        now: Instant,
+        uart: &mut Uart,
+        scheduler: &Scheduler,
+    ) {
+        let period = Duration::from_millis(self.period as u64);
+
+        if !self.running || now - self.last_report < period {
+            return;
+        }
+
+        let signals = &self.signals;
+        let values: ArrayVec<[Value; 16]> = scheduler.with_plan(|plan| {
+            signals
+                .iter()
+                .map(|signal| signal.value(now, plan))
+                .collect()
+        });
+
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                write!(uart, "\t").ignore();
+            }
+            value.write(uart);
+        }
+        writeln!(uart).ignore();
+
+        self.last_report = now;
+    }
+}
+
+const REPORT_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "add",
+        args: "<signal>...",
+        help: "add signals to the report",
+    },
+    Subcommand {
+        name: "remove",
+        args: "<signal>...",
+        help: "remove signals from the report",
+    },
+    Subcommand {
+        name: "clear",
+        args: "",
+        help: "remove all the signals",
+    },
+    Subcommand {
+        name: "rate",
+        args: "[ms]",
+        help: "set or show the time between reports",
+    },
+    Subcommand {
+        name: "list",
+        args: "",
+        help: "show the signals reported, and all the signals",
+    },
+    Subcommand {
+        name: "start",
+        args: "",
+        help: "start the report, like @",
+    },
+    Subcommand {
+        name: "stop",
+        args: "",
+        help: "stop the report, like !",
+    },
+];
+
+impl Command for Report {
+    fn keyword_command(&self) -> &str {
+        "report"
+    }
+
+    fn subcommands(&self) -> &[Subcommand] {
+        REPORT_SUBCOMMANDS
+    }
+
+    fn handle_command<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        out: &mut Output,
+        mut args: I,
+    ) {
+        match args.next() {
+            Some("add") => {
+                for name in args {
+                    match Signal::from_name(name) {
+                        Some(signal) if self.signals.contains(&signal) => {
+                            writeln!(out, "report: already added: {}", name)
+                                .ignore()
+                        }
+                        Some(signal) => {
+                            if self.signals.try_push(signal).is_err() {
+                                writeln!(out, "report: too many signals")
+                                    .ignore();
+                            }
+                        }
+                        None => {
+                            writeln!(out, "report: unknown signal: {}", name)
+                                .ignore()
+                        }
+                    }
+                }
+            }
+            Some("remove") => {
+                for name in args {
+                    match Signal::from_name(name) {
+                        Some(signal) => self.signals.retain(|s| *s != signal),
+                        None => {
+                            writeln!(out, "report: unknown signal: {}", name)
+                                .ignore()
+                        }
+                    }
+                }
+            }
+            Some("clear") => self.signals.clear(),
+            Some("rate") => {
+                if let Some(period) = args.next().and_then(|s| s.parse().ok()) {
+                    self.period = period;
+                } else {
+                    writeln!(out, "report rate: {}", self.period).ignore();
+                }
+            }
+            Some("list") => {
+                write!(out, "report:").ignore();
+                for signal in self.signals.iter() {
+                    write!(out, " {}", signal.name()).ignore();
+                }
+                writeln!(out).ignore();
+
+                write!(out, "available:").ignore();
+                for (name, _) in SIGNALS.iter() {
+                    write!(out, " {}", name).ignore();
+                }
+                writeln!(out).ignore();
+            }
+            Some("start") => self.start(),
+            Some("stop") => self.stop(),
+            Some(c) => writeln!(out, "report: unknown command: {}", c).ignore(),
+            None => writeln!(out, "report: no command").ignore(),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt_macros::interrupt as isr;
+
+use stm32f4xx_hal::stm32 as stm32f405;
+use stm32f4xx_hal::stm32::Interrupt as interrupt;
+
+use ignore_result::Ignore;
+
+use crate::navigate::LessRandomNavigate;
+use crate::plan::Plan;
+use crate::time;
+use crate::time::Duration;
+use crate::time::Instant;
+use crate::uart::Command;
+use crate::uart::Output;
+use crate::uart::Subcommand;
+
+/// The navigator the plan is run with
+pub type Navigator = LessRandomNavigate;
+
+/// Divide the 16 MHz timer clock down to 1 MHz
+const PRESCALER: u16 = 15;
+
+/// How long between updates of the wheel velocities
+pub const PERIOD: Duration = Duration::from_millis(1);
+
+/// How many velocity updates to each update of the plan and current move
+const MOVE_DIVIDER: u32 = 10;
+
+/**
+ *  The plan, once the scheduler has been started, run from its interrupt
+ *
+ *  This isn't behind a critical section, which would hold off the uart and
+ *  everything else for as long as the plan is in use. Instead the main loop
+ *  only reaches it through `with_plan`, which masks the timer interrupt
+ *  alone while it does.
+ */
+static mut PLAN: Option<Plan<Navigator>> = None;
+
+static STATE: Mutex<RefCell<State>> = Mutex::new(RefCell::new(State {
+    ticks: 0,
+    last_start: None,
+    stats: LoopStats::new(),
+}));
+
+struct State {
+    ticks: u32,
+    last_start: Option<Instant>,
+    stats: LoopStats,
+}
+
+/// How steady the control loop has been since the stats were reset
+#[derive(Copy, Clone, Debug)]
+pub struct LoopStats {
+    /// Updates measured
+    pub count: u32,
+
+    /// The furthest an update started from a period after the last, in µs
+    pub max_jitter: u64,
+    total_jitter: u64,
+
+    /// The longest an update took, in µs
+    pub max_run: u64,
+
+    /// Updates that took longer than the period, holding up the next one
+    pub overruns: u32,
+}
+
+impl LoopStats {
+    const fn new() -> LoopStats {
+        LoopStats {
+            count: 0,
+            max_jitter: 0,
+            total_jitter: 0,
+            max_run: 0,
+            overruns: 0,
+        }
+    }
+
+    /// The average distance of an update from its period, in µs
+    pub fn mean_jitter(&self) -> u64 {
+        if self.count > 0 {
+            self.total_jitter / self.count as u64
+        } else {
+            0
+        }
+    }
+
+    fn add(&mut self, interval: Duration, run: Duration) {
+        let jitter = if interval > PERIOD {
+            interval - PERIOD
+        } else {
+            PERIOD - interval
+        };
+
+        self.count += 1;
+        self.total_jitter += jitter.as_micros();
+        self.max_jitter = self.max_jitter.max(jitter.as_micros());
+        self.max_run = self.max_run.max(run.as_micros());
+
+        if run > PERIOD {
+            self.overruns += 1;
+        }
+    }
+}
+
+/**
+ *  Runs the control loops at a fixed rate from a timer interrupt
+ *
+ *  The wheel velocities are updated every millisecond, and the plan with
+ *  its current move every `MOVE_DIVIDER` of those. Everything else, the
+ *  uart and the distance sensors included, is left to the main loop,
+ *  which reaches the plan through `with_plan`.
+ */
+pub struct Scheduler {
+    timer: stm32f405::TIM7,
+}
+
+impl Scheduler {
+    /// Set up the timer, without starting it
+    pub fn setup(rcc: &stm32f405::RCC, timer: stm32f405::TIM7) -> Scheduler {
+        // Enable clock for timer 7
+        rcc.apb1enr.modify(|_, w| w.tim7en().set_bit());
+
+        // count microseconds, overflowing once a period
+        timer.psc.write(|w| unsafe { w.psc().bits(PRESCALER) });
+        timer
+            .arr
+            .write(|w| unsafe { w.arr().bits(PERIOD.as_micros() as u16 - 1) });
+
+        // load the prescaler now, instead of at the first overflow
+        timer.egr.write(|w| w.ug().set_bit());
+        timer.sr.modify(|_, w| w.uif().clear_bit());
+
+        timer.dier.modify(|_, w| w.uie().set_bit());
+
+        Scheduler { timer }
+    }
+
+    /// Hand the plan over to the interrupt, and start running it
+    pub fn start(&mut self, nvic: &mut stm32f405::NVIC, plan: Plan<Navigator>) {
+        // The interrupt isn't enabled yet, so nothing else can see the plan
+        unsafe {
+            PLAN = Some(plan);
+        }
+
+        self.timer.cr1.modify(|_, w| w.cen().set_bit());
+
+        nvic.enable(interrupt::TIM7);
+    }
+
+    /**
+     *  Run `f` on the plan, with the control loop held off
+     *
+     *  Only the timer interrupt is masked, so the uart keeps receiving, but
+     *  this should still be kept short, since it delays the next update.
+     *  Nothing in `f` should wait on the uart, or call `with_plan` again.
+     *  It can only be used once the scheduler has been started.
+     */
+    pub fn with_plan<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Plan<Navigator>) -> T,
+    {
+        self.timer.dier.modify(|_, w| w.uie().clear_bit());
+        // Make sure the interrupt sees the mask before the plan is touched
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+
+        // With the interrupt masked, this is the only borrow of the plan
+        let result =
+            f(unsafe { PLAN.as_mut() }.expect("scheduler not started"));
+
+        // An update that came due meanwhile runs as soon as this is set
+        self.timer.dier.modify(|_, w| w.uie().set_bit());
+
+        result
+    }
+
+    pub fn stats(&self) -> LoopStats {
+        cortex_m::interrupt::free(|cs| STATE.borrow(cs).borrow().stats)
+    }
+
+    pub fn reset_stats(&self) {
+        cortex_m::interrupt::free(|cs| {
+            STATE.borrow(cs).borrow_mut().stats = LoopStats::new();
+        });
+    }
+}
+
+const LOOP_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "stats",
+        args: "",
+        help: "show the jitter and run time of the control loop",
+    },
+    Subcommand {
+        name: "reset",
+        args: "",
+        help: "start measuring the control loop again",
+    },
+];
+
+impl Command for Scheduler {
+    fn keyword_command(&self) -> &str {
+        "loop"
+    }
+
+    fn subcommands(&self) -> &[Subcommand] {
+        LOOP_SUBCOMMANDS
+    }
+
+    fn handle_command<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        out: &mut Output,
+        mut args: I,
+    ) {
+        match args.next() {
+            Some("stats") => {
+                let stats = self.stats();
+
+                writeln!(
+                    out,
+                    "loop: updates: {} jitter: mean {} max {} us \
+                     run: max {} us overruns: {}",
+                    stats.count,
+                    stats.mean_jitter(),
+                    stats.max_jitter,
+                    stats.max_run,
+                    stats.overruns
+                )
+                .ignore();
+            }
+            Some("reset") => self.reset_stats(),
+            Some(c) => writeln!(out, "loop: unknown command: {}", c).ignore(),
+            None => writeln!(out, "loop: no command").ignore(),
+        }
+    }
+}
+
+#[isr]
+fn TIM7() {
+    let timer = unsafe { &*stm32f405::TIM7::ptr() };
+
+    // Masked by `with_plan`, the flag is left set so that the update runs
+    // once the plan is let go
+    if timer.dier.read().uie().bit_is_clear()
+        || timer.sr.read().uif().bit_is_clear()
+    {
+        return;
+    }
+
+    timer.sr.modify(|_, w| w.uif().clear_bit());
+
+    let start = time::now();
+    let ticks = cortex_m::interrupt::free(|cs| STATE.borrow(cs).borrow().ticks);
+
+    // The main loop only borrows the plan with this interrupt masked
+    if let Some(plan) = unsafe { PLAN.as_mut() } {
+        // The move sets the velocities the wheels are then driven at
+        if ticks % MOVE_DIVIDER == 0 {
+            plan.update(start);
+        }
+
+        plan.control().bot_mut().update(start);
+    }
+
+    cortex_m::interrupt::free(|cs| {
+        let mut state = STATE.borrow(cs).borrow_mut();
+
+        state.ticks = state.ticks.wrapping_add(1);
+
+        if let Some(last_start) = state.last_start {
+            let run = time::now() - start;
+            state.stats.add(start - last_start, run);
+        }
+
+        state.last_start = Some(start);
+    });
+}
@@ -0,0 +1,403 @@
+use core::fmt::Write;
+
+use stm32f4xx_hal::gpio::{gpioa, gpiob, gpioc, Alternate, AF4};
+use stm32f4xx_hal::i2c;
+use stm32f4xx_hal::i2c::I2c;
+use stm32f4xx_hal::stm32 as stm32f405;
+
+use ignore_result::Ignore;
+
+use crate::config::BotConfig;
+use crate::range_ready::RangeReady;
+
+use crate::vl6180x;
+use crate::vl6180x::VL6180x;
+
+use crate::uart::Command;
+use crate::uart::Output;
+use crate::uart::Subcommand;
+
+use crate::time::Instant;
+
+/// How many bus errors in a row before a distance sensor is failing
+const FAILURE_LIMIT: u32 = 10;
+
+/// How many measurements to average for each step of sensor calibration
+const CALIBRATION_SAMPLES: u16 = 20;
+
+/**
+ *  The part of the bot's config that the sensors follow
+ *
+ *  This is small enough to copy out of the plan on every pass of the main
+ *  loop, where the whole config isn't.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorConfig {
+    pub range_period: u32,
+    pub interleaved: bool,
+    pub front_scaling: u8,
+    pub front_wall_distance: f64,
+    pub cell_offset: f64,
+}
+
+impl SensorConfig {
+    pub fn new(config: &BotConfig) -> SensorConfig {
+        SensorConfig {
+            range_period: config.range_period,
+            interleaved: config.interleaved,
+            front_scaling: config.front_scaling,
+            front_wall_distance: config.front_wall_distance,
+            cell_offset: config.cell_offset,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DistanceSensor {
+    Front,
+    Left,
+    Right,
+}
+
+pub type FrontDistance = VL6180x<
+    I2c<
+        stm32f405::I2C1,
+        (gpiob::PB8<Alternate<AF4>>, gpiob::PB9<Alternate<AF4>>),
+    >,
+>;
+
+pub type LeftDistance = VL6180x<
+    I2c<
+        stm32f405::I2C2,
+        (gpiob::PB10<Alternate<AF4>>, gpiob::PB11<Alternate<AF4>>),
+    >,
+>;
+
+pub type RightDistance = VL6180x<
+    I2c<
+        stm32f405::I2C3,
+        (gpioa::PA8<Alternate<AF4>>, gpioc::PC9<Alternate<AF4>>),
+    >,
+>;
+
+/// A new measurement from a distance sensor, in mm if it was valid
+#[derive(Copy, Clone, Debug)]
+pub struct Reading {
+    pub range: Option<f64>,
+    pub time: Instant,
+}
+
+/// The measurements made since the last update, if any
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Readings {
+    pub front: Option<Reading>,
+    pub left: Option<Reading>,
+    pub right: Option<Reading>,
+}
+
+/**
+ *  The distance sensors
+ *
+ *  These are kept apart from the bot so that the i2c transfers happen in
+ *  the background, and never hold up the control loop. The readings are
+ *  handed over to the bot's filters afterwards.
+ */
+pub struct Sensors {
+    front_distance: FrontDistance,
+    left_distance: LeftDistance,
+    right_distance: RightDistance,
+    range_ready: RangeReady,
+    range_period: u32,
+    interleaved: bool,
+
+    front_failures: u32,
+    left_failures: u32,
+    right_failures: u32,
+
+    config: SensorConfig,
+}
+
+impl Sensors {
+    pub fn new(
+        mut front_distance: FrontDistance,
+        mut left_distance: LeftDistance,
+        mut right_distance: RightDistance,
+        range_ready: RangeReady,
+        config: SensorConfig,
+    ) -> Sensors {
+        // Continuous ranging is started by the first update if configured
+        front_distance.start_ranging().ignore();
+        left_distance.start_ranging().ignore();
+        right_distance.start_ranging().ignore();
+
+        Sensors {
+            front_distance,
+            left_distance,
+            right_distance,
+            range_ready,
+            range_period: 0,
+            interleaved: false,
+            front_failures: 0,
+            left_failures: 0,
+            right_failures: 0,
+            config,
+        }
+    }
+
+    /**
+     *  Fetch any finished measurements, following changes to the ranging
+     *  settings in `config`
+     */
+    pub fn update(&mut self, now: Instant, config: SensorConfig) -> Readings {
+        self.config = config;
+
+        if self.config.range_period != self.range_period
+            || self.config.interleaved != self.interleaved
+        {
+            self.change_ranging(
+                self.config.range_period,
+                self.config.interleaved,
+            );
+        }
+
+        if self.config.front_scaling != self.front_distance.scaling() {
+            self.front_distance
+                .set_scaling(self.config.front_scaling)
+                .ignore();
+        }
+
+        // Failed readings go to the filters as a missing range, so only bus
+        // errors need to be counted here
+        let ready = self.range_ready.take(DistanceSensor::Front);
+        let front = self.front_distance.update(now, ready);
+        self.front_failures = count_failures(self.front_failures, &front);
+        let front = reading(front, ready.unwrap_or(now));
+
+        let ready = self.range_ready.take(DistanceSensor::Left);
+        let left = self.left_distance.update(now, ready);
+        self.left_failures = count_failures(self.left_failures, &left);
+        let left = reading(left, ready.unwrap_or(now));
+
+        let ready = self.range_ready.take(DistanceSensor::Right);
+        let right = self.right_distance.update(now, ready);
+        self.right_failures = count_failures(self.right_failures, &right);
+        let right = reading(right, ready.unwrap_or(now));
+
+        Readings { front, left, right }
+    }
+
+    /**
+     *  Switch the distance sensors between single-shot ranging, with a
+     *  period of 0, and continuous ranging every `period` ms
+     *
+     *  Interleaved mode also measures ambient light before each range, and
+     *  only applies to continuous ranging. Errors are left to show up as
+     *  timeouts in `update`.
+     */
+    fn change_ranging(&mut self, period: u32, interleaved: bool) {
+        if period > 0 && interleaved {
+            let period = period as u16;
+            self.front_distance
+                .start_interleaved_continuous(period)
+                .ignore();
+            self.left_distance
+                .start_interleaved_continuous(period)
+                .ignore();
+            self.right_distance
+                .start_interleaved_continuous(period)
+                .ignore();
+        } else if period > 0 {
+            let period = period as u16;
+            self.front_distance.start_range_continuous(period).ignore();
+            self.left_distance.start_range_continuous(period).ignore();
+            self.right_distance.start_range_continuous(period).ignore();
+        } else {
+            self.front_distance.start_ranging().ignore();
+            self.left_distance.start_ranging().ignore();
+            self.right_distance.start_ranging().ignore();
+        }
+
+        self.range_period = period;
+        self.interleaved = interleaved;
+    }
+
+    /// Whether a distance sensor has stopped responding
+    pub fn is_failing(&self, sensor: DistanceSensor) -> bool {
+        let failures = match sensor {
+            DistanceSensor::Front => self.front_failures,
+            DistanceSensor::Left => self.left_failures,
+            DistanceSensor::Right => self.right_failures,
+        };
+
+        failures >= FAILURE_LIMIT
+    }
+
+    /**
+     *  Set up a distance sensor again after it has been power cycled
+     *
+     *  The failure count is cleared if the sensor responds.
+     */
+    pub fn init_distance(
+        &mut self,
+        sensor: DistanceSensor,
+    ) -> Result<(), vl6180x::Error<i2c::Error>> {
+        match sensor {
+            DistanceSensor::Front => {
+                self.front_distance.init()?;
+                self.front_failures = 0;
+            }
+            DistanceSensor::Left => {
+                self.left_distance.init()?;
+                self.left_failures = 0;
+            }
+            DistanceSensor::Right => {
+                self.right_distance.init()?;
+                self.right_failures = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     *  Calibrate the distance sensors, with the bot centred in a cell that
+     *  has walls on the front, left and right
+     *
+     *  The walls are expected at `front_wall_distance` and `cell_offset`.
+     *  This blocks while the sensors measure, so the bot should be stopped
+     *  first.
+     */
+    pub fn calibrate(&mut self, out: &mut Output) {
+        let front = self.front_distance.calibrate(
+            self.config.front_wall_distance as u16,
+            CALIBRATION_SAMPLES,
+        );
+        report_calibration(out, "front", front);
+
+        let left = self
+            .left_distance
+            .calibrate(self.config.cell_offset as u16, CALIBRATION_SAMPLES);
+        report_calibration(out, "left", left);
+
+        let right = self
+            .right_distance
+            .calibrate(self.config.cell_offset as u16, CALIBRATION_SAMPLES);
+        report_calibration(out, "right", right);
+    }
+
+    /// The last unfiltered distance in mm, or None if it was invalid
+    pub fn raw_distance(&self, sensor: DistanceSensor) -> Option<f64> {
+        match sensor {
+            DistanceSensor::Front => self.front_distance.range(),
+            DistanceSensor::Left => self.left_distance.range(),
+            DistanceSensor::Right => self.right_distance.range(),
+        }
+        .map(|r| r as f64)
+    }
+
+    /// The ambient light at a distance sensor in lux, in interleaved mode
+    pub fn ambient(&self, sensor: DistanceSensor) -> Option<f64> {
+        match sensor {
+            DistanceSensor::Front => self.front_distance.ambient(),
+            DistanceSensor::Left => self.left_distance.ambient(),
+            DistanceSensor::Right => self.right_distance.ambient(),
+        }
+    }
+
+    /// When the last valid reading from a distance sensor was measured
+    pub fn distance_time(&self, sensor: DistanceSensor) -> Option<Instant> {
+        match sensor {
+            DistanceSensor::Front => self.front_distance.range_time(),
+            DistanceSensor::Left => self.left_distance.range_time(),
+            DistanceSensor::Right => self.right_distance.range_time(),
+        }
+    }
+}
+
+fn count_failures<T, E>(
+    failures: u32,
+    result: &Result<T, vl6180x::Error<E>>,
+) -> u32 {
+    match result {
+        Err(ref e) if e.is_bus_error() => failures + 1,
+        _ => 0,
+    }
+}
+
+/// A new measurement, valid or not, or None if there wasn't one
+fn reading<E>(
+    result: Result<Option<u16>, vl6180x::Error<E>>,
+    time: Instant,
+) -> Option<Reading> {
+    match result {
+        Ok(Some(range)) => Some(Reading {
+            range: Some(range as f64),
+            time,
+        }),
+        Ok(None) => None,
+        Err(_) => Some(Reading { range: None, time }),
+    }
+}
+
+fn report_calibration<E: core::fmt::Debug>(
+    out: &mut Output,
+    name: &str,
+    result: Result<(i8, u16), vl6180x::Error<E>>,
+) {
+    match result {
+        Ok((offset, crosstalk)) => writeln!(
+            out,
+            "{}: offset: {} crosstalk: {}",
+            name, offset, crosstalk
+        )
+        .ignore(),
+        Err(e) => {
+            writeln!(out, "{}: calibration failed: {:?}", name, e).ignore()
+        }
+    }
+}
+
+const SENSORS_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "calibrate",
+        args: "",
+        help: "calibrate the distance sensors in a cell center",
+    },
+    Subcommand {
+        name: "ambient",
+        args: "",
+        help: "show the ambient light at each sensor",
+    },
+];
+
+impl Command for Sensors {
+    fn keyword_command(&self) -> &str {
+        "sensors"
+    }
+
+    fn subcommands(&self) -> &[Subcommand] {
+        SENSORS_SUBCOMMANDS
+    }
+
+    fn handle_command<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        out: &mut Output,
+        mut args: I,
+    ) {
+        match args.next() {
+            Some("calibrate") => self.calibrate(out),
+            Some("ambient") => writeln!(
+                out,
+                "front: {:?}\tleft: {:?}\tright: {:?}",
+                self.ambient(DistanceSensor::Front),
+                self.ambient(DistanceSensor::Left),
+                self.ambient(DistanceSensor::Right),
+            )
+            .ignore(),
+            Some(c) => {
+                writeln!(out, "sensors: unknown command: {}", c).ignore()
+            }
+            None => writeln!(out, "sensors: no command").ignore(),
+        }
+    }
+}
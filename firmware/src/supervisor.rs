@@ -0,0 +1,431 @@
+use core::fmt::Write;
+
+use cortex_m::asm;
+
+use stm32f4xx_hal::gpio::gpioc::{PC0, PC2, PC4};
+use stm32f4xx_hal::gpio::{OpenDrain, Output};
+use stm32f4xx_hal::prelude::*;
+use stm32f4xx_hal::stm32 as stm32f405;
+
+use ignore_result::Ignore;
+
+use crate::battery::Battery;
+use crate::scheduler::Scheduler;
+use crate::sensors::DistanceSensor;
+use crate::sensors::Sensors;
+use crate::time::Duration;
+use crate::time::Instant;
+use crate::uart::Uart;
+
+/// How long to hold a sensor off when power cycling it
+const POWER_OFF_TIME: Duration = Duration::from_millis(10);
+
+/// How long to let a sensor boot before setting it up again
+const BOOT_TIME: Duration = Duration::from_millis(10);
+
+/// How long to wait after the first failed retry, doubled after each one
+const RETRY_TIME: Duration = Duration::from_millis(50);
+
+/// How many times to power cycle a sensor before giving up on it
+const MAX_RETRIES: u32 = 5;
+
+/// The least time between two of the retry messages
+const LOG_PERIOD: Duration = Duration::from_millis(1000);
+
+/// Below this a two cell battery is getting low, at 3.5V a cell
+const LOW_VOLTAGE: f64 = 7.0;
+
+/// How far above `LOW_VOLTAGE` the battery has to get back to, after being
+/// charged or swapped
+const LOW_RECOVERY: f64 = 0.2;
+
+/// Below this the battery has to be shut off from, at 3.2V a cell
+const CUTOFF_VOLTAGE: f64 = 6.4;
+
+/// How long the voltage has to stay under a threshold, so that the motors
+/// pulling it down for a moment don't count
+const HOLD_TIME: Duration = Duration::from_millis(1000);
+
+/// How long to take turning the motors off at the cutoff
+const RAMP_TIME: Duration = Duration::from_millis(500);
+
+/// Half of a 100khz i2c clock period, in cycles of the 16MHz core clock
+const HALF_CLOCK_CYCLES: u32 = 80;
+
+const MODE_OUTPUT: u32 = 0b01;
+const MODE_ALTERNATE: u32 = 0b10;
+
+fn set_mode(moder: u32, pin: u32, mode: u32) -> u32 {
+    (moder & !(0b11 << (2 * pin))) | (mode << (2 * pin))
+}
+
+/**
+ *  Free up an i2c bus that a sensor is holding
+ *
+ *  The pins are taken from the i2c peripheral and SCL is clocked until
+ *  the sensor lets go of SDA, followed by a stop condition. The
+ *  peripheral is then reset, keeping its timing setup, since it will
+ *  usually be stuck waiting on the bus too.
+ */
+macro_rules! free_bus {
+    ($I2C:ident, $SCL_PORT:ident, $scl:expr, $SDA_PORT:ident, $sda:expr) => {{
+        let i2c = unsafe { &*stm32f405::$I2C::ptr() };
+        let scl_port = unsafe { &*stm32f405::$SCL_PORT::ptr() };
+        let sda_port = unsafe { &*stm32f405::$SDA_PORT::ptr() };
+
+        // Release both lines before switching them to outputs
+        scl_port.bsrr.write(|w| unsafe { w.bits(1 << $scl) });
+        sda_port.bsrr.write(|w| unsafe { w.bits(1 << $sda) });
+
+        scl_port.moder.modify(|r, w| unsafe {
+            w.bits(set_mode(r.bits(), $scl, MODE_OUTPUT))
+        });
+        sda_port.moder.modify(|r, w| unsafe {
+            w.bits(set_mode(r.bits(), $sda, MODE_OUTPUT))
+        });
+
+        for _ in 0..9 {
+            scl_port.bsrr.write(|w| unsafe { w.bits(1 << ($scl + 16)) });
+            asm::delay(HALF_CLOCK_CYCLES);
+            scl_port.bsrr.write(|w| unsafe { w.bits(1 << $scl) });
+            asm::delay(HALF_CLOCK_CYCLES);
+        }
+
+        // Stop condition: SDA going high while SCL is high
+        sda_port.bsrr.write(|w| unsafe { w.bits(1 << ($sda + 16)) });
+        asm::delay(HALF_CLOCK_CYCLES);
+        sda_port.bsrr.write(|w| unsafe { w.bits(1 << $sda) });
+        asm::delay(HALF_CLOCK_CYCLES);
+
+        scl_port.moder.modify(|r, w| unsafe {
+            w.bits(set_mode(r.bits(), $scl, MODE_ALTERNATE))
+        });
+        sda_port.moder.modify(|r, w| unsafe {
+            w.bits(set_mode(r.bits(), $sda, MODE_ALTERNATE))
+        });
+
+        let cr2 = i2c.cr2.read().bits();
+        let ccr = i2c.ccr.read().bits();
+        let trise = i2c.trise.read().bits();
+
+        i2c.cr1.modify(|_, w| w.swrst().set_bit());
+        i2c.cr1.modify(|_, w| w.swrst().clear_bit());
+
+        i2c.cr2.write(|w| unsafe { w.bits(cr2) });
+        i2c.ccr.write(|w| unsafe { w.bits(ccr) });
+        i2c.trise.write(|w| unsafe { w.bits(trise) });
+
+        i2c.cr1.modify(|_, w| w.pe().set_bit());
+    }};
+}
+
+fn free_bus(sensor: DistanceSensor) {
+    match sensor {
+        DistanceSensor::Front => free_bus!(I2C1, GPIOB, 8, GPIOB, 9),
+        DistanceSensor::Left => free_bus!(I2C2, GPIOB, 10, GPIOB, 11),
+        DistanceSensor::Right => free_bus!(I2C3, GPIOA, 8, GPIOC, 9),
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Recovery {
+    Idle,
+    PowerOff(DistanceSensor, Instant),
+    Boot(DistanceSensor, Instant),
+    Backoff(DistanceSensor, Instant, Duration),
+}
+
+const SENSORS: [DistanceSensor; 3] = [
+    DistanceSensor::Front,
+    DistanceSensor::Left,
+    DistanceSensor::Right,
+];
+
+pub struct SensorSupervisor {
+    front_enable: PC0<Output<OpenDrain>>,
+    left_enable: PC2<Output<OpenDrain>>,
+    right_enable: PC4<Output<OpenDrain>>,
+    recovery: Recovery,
+    retries: u32,
+    last_log: Option<Instant>,
+    given_up: [bool; 3],
+}
+
+impl SensorSupervisor {
+    /// Takes the GPIO0 pins of the sensors, which hold them in reset when low
+    pub fn new(
+        front_enable: PC0<Output<OpenDrain>>,
+        left_enable: PC2<Output<OpenDrain>>,
+        right_enable: PC4<Output<OpenDrain>>,
+    ) -> SensorSupervisor {
+        SensorSupervisor {
+            front_enable,
+            left_enable,
+            right_enable,
+            recovery: Recovery::Idle,
+            retries: 0,
+            last_log: None,
+            given_up: [false; 3],
+        }
+    }
+
+    /**
+     *  Watch for distance sensors that have stopped responding, and bring
+     *  them back
+     *
+     *  The plan is paused until the sensor has been set up again. The
+     *  power cycle is spread over several updates so that the rest of the
+     *  main loop keeps running in the meantime, and failed retries wait
+     *  longer each time. After `MAX_RETRIES` the sensor is given up on and
+     *  the plan is stopped, leaving it to be started again without it.
+     */
+    pub fn update(
+        &mut self,
+        now: Instant,
+        sensors: &mut Sensors,
+        scheduler: &Scheduler,
+        uart: &mut Uart,
+    ) {
+        match self.recovery {
+            Recovery::Idle => {
+                let given_up = self.given_up;
+                let failing = SENSORS.iter().cloned().find(|&sensor| {
+                    !given_up[sensor as usize] && sensors.is_failing(sensor)
+                });
+
+                if let Some(sensor) = failing {
+                    writeln!(uart, "supervisor: {:?} distance failed", sensor)
+                        .ignore();
+                    scheduler.with_plan(|plan| plan.pause());
+                    self.retries = 0;
+                    self.last_log = None;
+                    self.power_off(now, sensor);
+                }
+            }
+
+            Recovery::PowerOff(sensor, start) => {
+                if now - start >= POWER_OFF_TIME {
+                    self.set_enable(sensor, true);
+                    self.recovery = Recovery::Boot(sensor, now);
+                }
+            }
+
+            Recovery::Boot(sensor, start) => {
+                if now - start >= BOOT_TIME {
+                    match sensors.init_distance(sensor) {
+                        Ok(()) => {
+                            writeln!(
+                                uart,
+                                "supervisor: {:?} distance recovered",
+                                sensor
+                            )
+                            .ignore();
+                            scheduler.with_plan(|plan| plan.resume());
+                            self.recovery = Recovery::Idle;
+                        }
+                        Err(e) => {
+                            self.retry_failed(now, sensor, e, scheduler, uart)
+                        }
+                    }
+                }
+            }
+
+            Recovery::Backoff(sensor, start, delay) => {
+                if now - start >= delay {
+                    self.power_off(now, sensor);
+                }
+            }
+        }
+    }
+
+    fn retry_failed<E: core::fmt::Debug>(
+        &mut self,
+        now: Instant,
+        sensor: DistanceSensor,
+        error: E,
+        scheduler: &Scheduler,
+        uart: &mut Uart,
+    ) {
+        self.retries += 1;
+
+        if self.retries >= MAX_RETRIES {
+            writeln!(
+                uart,
+                "supervisor: {:?} distance: {:?}, giving up after {} tries",
+                sensor, error, self.retries
+            )
+            .ignore();
+
+            // Leave it held in reset, so it can't hold the bus either
+            self.set_enable(sensor, false);
+            self.given_up[sensor as usize] = true;
+
+            scheduler.with_plan(|plan| {
+                plan.stop();
+                plan.resume();
+            });
+            self.recovery = Recovery::Idle;
+            return;
+        }
+
+        let quiet = match self.last_log {
+            Some(last) => now - last < LOG_PERIOD,
+            None => false,
+        };
+        if !quiet {
+            writeln!(
+                uart,
+                "supervisor: {:?} distance: {:?}, retrying",
+                sensor, error
+            )
+            .ignore();
+            self.last_log = Some(now);
+        }
+
+        let delay =
+            Duration::from_millis(RETRY_TIME.as_millis() << (self.retries - 1));
+        self.recovery = Recovery::Backoff(sensor, now, delay);
+    }
+
+    fn power_off(&mut self, now: Instant, sensor: DistanceSensor) {
+        free_bus(sensor);
+        self.set_enable(sensor, false);
+        self.recovery = Recovery::PowerOff(sensor, now);
+    }
+
+    fn set_enable(&mut self, sensor: DistanceSensor, enable: bool) {
+        match sensor {
+            DistanceSensor::Front if enable => self.front_enable.set_high(),
+            DistanceSensor::Front => self.front_enable.set_low(),
+            DistanceSensor::Left if enable => self.left_enable.set_high(),
+            DistanceSensor::Left => self.left_enable.set_low(),
+            DistanceSensor::Right if enable => self.right_enable.set_high(),
+            DistanceSensor::Right => self.right_enable.set_low(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Power {
+    Ok,
+    Low,
+    RampDown(Instant),
+    Off,
+}
+
+/// Whether `below` has held for `HOLD_TIME`, given when it started holding
+fn held(since: &mut Option<Instant>, now: Instant, below: bool) -> bool {
+    if below {
+        now - *since.get_or_insert(now) >= HOLD_TIME
+    } else {
+        *since = None;
+        false
+    }
+}
+
+/**
+ *  Protects the battery, and keeps the bot from running on one that could
+ *  brown out
+ *
+ *  Runs are refused once the battery is low. At the cutoff the motors are
+ *  ramped down, then the plan is stopped and they are left off until a
+ *  reset.
+ */
+pub struct PowerSupervisor {
+    power: Power,
+    low_since: Option<Instant>,
+    cutoff_since: Option<Instant>,
+}
+
+impl PowerSupervisor {
+    pub fn new() -> PowerSupervisor {
+        PowerSupervisor {
+            power: Power::Ok,
+            low_since: None,
+            cutoff_since: None,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        now: Instant,
+        battery: &Battery,
+        scheduler: &Scheduler,
+        uart: &mut Uart,
+    ) {
+        // Without a battery the motors can't run anyway
+        let voltage = match battery.voltage() {
+            Some(voltage) if !battery.is_dead() => voltage,
+            _ => return,
+        };
+
+        let low = held(&mut self.low_since, now, voltage < LOW_VOLTAGE);
+        let cutoff =
+            held(&mut self.cutoff_since, now, voltage < CUTOFF_VOLTAGE);
+
+        match self.power {
+            Power::Ok | Power::Low if cutoff => {
+                writeln!(
+                    uart,
+                    "power: battery at {:.2} V, turning the motors off",
+                    voltage
+                )
+                .ignore();
+                // The plan keeps driving while the power comes down, so
+                // the motors aren't cut off mid move
+                scheduler
+                    .with_plan(|plan| plan.refuse_go(Some("battery cut off")));
+                self.power = Power::RampDown(now);
+            }
+
+            Power::Ok => {
+                if low {
+                    writeln!(
+                        uart,
+                        "power: battery low at {:.2} V, refusing runs",
+                        voltage
+                    )
+                    .ignore();
+                    scheduler
+                        .with_plan(|plan| plan.refuse_go(Some("battery low")));
+                    self.power = Power::Low;
+                }
+            }
+
+            Power::Low => {
+                if voltage > LOW_VOLTAGE + LOW_RECOVERY {
+                    writeln!(uart, "power: battery back at {:.2} V", voltage)
+                        .ignore();
+                    scheduler.with_plan(|plan| plan.refuse_go(None));
+                    self.power = Power::Ok;
+                }
+            }
+
+            Power::RampDown(start) => {
+                let ramp =
+                    (now - start).as_millis_f64() / RAMP_TIME.as_millis_f64();
+                let limit = 1.0 - ramp;
+
+                scheduler.with_plan(|plan| {
+                    plan.control().bot_mut().set_power_limit(limit)
+                });
+
+                if limit <= 0.0 {
+                    writeln!(uart, "power: motors off").ignore();
+                    scheduler.with_plan(|plan| plan.stop());
+                    self.power = Power::Off;
+                }
+            }
+
+            Power::Off => {}
+        }
+    }
+
+    /// Whether the battery is low enough that runs are being refused
+    pub fn is_low(&self) -> bool {
+        match self.power {
+            Power::Ok => false,
+            _ => true,
+        }
+    }
+}
@@ -1,46 +1,190 @@
+use core::cell::Cell;
+use core::ops::Add;
+use core::ops::AddAssign;
+use core::ops::Sub;
+
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt_macros::interrupt as isr;
+
 use stm32f4xx_hal::stm32 as stm32f405;
+use stm32f4xx_hal::stm32::Interrupt as interrupt;
+
+/// Divide the 16 MHz timer clock down to 1 MHz, so it counts microseconds
+const PRESCALER: u16 = 15;
+
+/// Times the timer has overflowed, counted by its update interrupt
+static OVERFLOWS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// A length of time, in microseconds
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Duration(u64);
+
+impl Duration {
+    pub const fn from_micros(micros: u64) -> Duration {
+        Duration(micros)
+    }
+
+    pub const fn from_millis(millis: u64) -> Duration {
+        Duration(millis * 1000)
+    }
+
+    pub fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    pub fn as_millis(self) -> u64 {
+        self.0 / 1000
+    }
+
+    /// In ms with the fraction kept, which is what the controllers use
+    pub fn as_millis_f64(self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration(self.0 + other.0)
+    }
+}
+
+/// Saturates at zero rather than wrapping
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_sub(other.0))
+    }
+}
+
+/**
+ *  A point in time, in microseconds since the clock started
+ *
+ *  At 64 bits this won't wrap around for longer than anything will run,
+ *  so times can be compared and subtracted without caring about it.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// When the clock started, for things that haven't happened yet
+    pub const START: Instant = Instant(0);
+
+    pub fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    pub fn as_millis(self) -> u64 {
+        self.0 / 1000
+    }
+
+    /// How long it has been since `earlier`, zero if it was later
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration(self.0.saturating_sub(earlier.0))
+    }
+}
 
-static OVERFLOW_VALUE: u32 = 65535;
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0 + duration.0)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        self.0 += duration.0;
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/**
+ *  The current time
+ *
+ *  This can be called from interrupts too. The overflow interrupt can't
+ *  run while this does, so an overflow it hasn't counted yet is found
+ *  from the pending flag instead.
+ */
+pub fn now() -> Instant {
+    let timer = unsafe { &*stm32f405::TIM1::ptr() };
+
+    cortex_m::interrupt::free(|cs| {
+        let mut overflows = OVERFLOWS.borrow(cs).get() as u64;
+
+        // The count has to be read before the flag, so an overflow between
+        // the two shows up as a large count with the flag set
+        let count = timer.cnt.read().cnt().bits();
+
+        if timer.sr.read().uif().bit_is_set() && count < 0x8000 {
+            overflows += 1;
+        }
+
+        Instant(overflows << 16 | count as u64)
+    })
+}
 
 pub struct Time {
-    timer: stm32f405::TIM1,
-    last_time: u32,
-    overflows: u32,
+    _timer: stm32f405::TIM1,
 }
 
 impl Time {
-    pub fn setup(rcc: &stm32f405::RCC, timer: stm32f405::TIM1) -> Time {
-        // Enable clock for timer 4
+    pub fn setup(
+        rcc: &stm32f405::RCC,
+        nvic: &mut stm32f405::NVIC,
+        timer: stm32f405::TIM1,
+    ) -> Time {
+        // Enable clock for timer 1
         rcc.apb2enr.modify(|_, w| w.tim1en().set_bit());
 
-        // setup the timer
-        timer.psc.write(|w| unsafe { w.psc().bits(16000) });
-        timer.cr1.modify(|_, w| w.cen().set_bit());
-        timer.cnt.write(|w| unsafe { w.cnt().bits(0) });
+        // setup the timer to count microseconds over the full 16 bits
+        timer.psc.write(|w| unsafe { w.psc().bits(PRESCALER) });
+        timer.arr.write(|w| unsafe { w.arr().bits(0xffff) });
 
-        Time {
-            timer,
-            last_time: 0,
-            overflows: 0,
-        }
-    }
+        // load the prescaler now, instead of at the first overflow
+        timer.egr.write(|w| w.ug().set_bit());
+        timer.sr.modify(|_, w| w.uif().clear_bit());
 
-    #[inline(always)]
-    pub fn now(&mut self) -> u32 {
-        let current_time = self.timer.cnt.read().cnt().bits() as u32;
+        timer.dier.modify(|_, w| w.uie().set_bit());
+        timer.cnt.write(|w| unsafe { w.cnt().bits(0) });
+        timer.cr1.modify(|_, w| w.cen().set_bit());
 
-        if current_time < self.last_time {
-            self.overflows += 1;
-        }
+        nvic.enable(interrupt::TIM1_UP_TIM10);
 
-        self.last_time = current_time;
+        Time { _timer: timer }
+    }
 
-        current_time + self.overflows * OVERFLOW_VALUE
+    #[inline(always)]
+    pub fn now(&self) -> Instant {
+        now()
     }
 
-    pub fn delay(&mut self, usecs: u32) {
+    pub fn delay(&self, duration: Duration) {
         let start_time = self.now();
 
-        while self.now() - start_time < usecs {}
+        while self.now() - start_time < duration {}
     }
 }
+
+#[isr]
+fn TIM1_UP_TIM10() {
+    let timer = unsafe { &*stm32f405::TIM1::ptr() };
+
+    cortex_m::interrupt::free(|cs| {
+        if timer.sr.read().uif().bit_is_set() {
+            timer.sr.modify(|_, w| w.uif().clear_bit());
+
+            let overflows = OVERFLOWS.borrow(cs);
+            overflows.set(overflows.get() + 1);
+        }
+    });
+}
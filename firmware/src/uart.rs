@@ -3,37 +3,219 @@ use core::fmt::Write;
 
 use core::cell::RefCell;
 
+use arrayvec::ArrayString;
+
 use cortex_m::interrupt::Mutex;
 use cortex_m_rt_macros::interrupt as isr;
 
 use stm32f4xx_hal::stm32 as stm32f405;
 use stm32f4xx_hal::stm32::Interrupt as interrupt;
 
+use ignore_result::Ignore;
+
+use crate::time::Duration;
 use crate::time::Time;
 
+/// How much a single command can write, help for the whole config being
+/// the longest
+const OUTPUT_LEN: usize = 8192;
+
+/**
+ *  What a command writes, collected to be sent once it has finished
+ *
+ *  Commands on the plan run with the control loop held off, so they must
+ *  not wait on the uart. Everything they write goes here instead.
+ */
+pub type Output = ArrayString<[u8; OUTPUT_LEN]>;
+
+/// A subcommand of a `Command`, as listed by `help`
+pub struct Subcommand {
+    pub name: &'static str,
+
+    /// The arguments it takes, `<required>` or `[optional]`
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
 pub trait Command {
     fn keyword_command(&self) -> &str;
+    fn subcommands(&self) -> &[Subcommand];
     fn handle_command<'a, I: Iterator<Item = &'a str>>(
         &mut self,
-        uart: &mut Uart,
+        out: &mut Output,
         args: I,
     );
+
+    /**
+     *  Write the help for the subcommand at `path`, or for all of them if
+     *  the path is empty
+     *
+     *  Commands that hold other commands override this to pass the rest
+     *  of the path on to them.
+     */
+    fn help_command<'a, I: Iterator<Item = &'a str>>(
+        &self,
+        out: &mut Output,
+        mut path: I,
+    ) {
+        write_help(
+            out,
+            self.keyword_command(),
+            self.subcommands(),
+            path.next(),
+        );
+    }
+}
+
+/**
+ *  Write the help for one of `subcommands`, or all of them if `name` is
+ *  None
+ *
+ *  Each is written as a line of the full command and a description. The
+ *  keyword is left off for the top level commands, where it is empty.
+ */
+pub fn write_help<'a, I>(
+    out: &mut Output,
+    keyword: &str,
+    subcommands: I,
+    name: Option<&str>,
+) where
+    I: IntoIterator<Item = &'a Subcommand>,
+{
+    let mut subcommands = subcommands.into_iter();
+
+    match name {
+        Some(name) => match subcommands.find(|s| s.name == name) {
+            Some(subcommand) => write_subcommand(out, keyword, subcommand),
+            None if keyword.is_empty() => {
+                writeln!(out, "unknown command: {}", name).ignore()
+            }
+            None => {
+                writeln!(out, "{}: unknown command: {}", keyword, name).ignore()
+            }
+        },
+        None => {
+            for subcommand in subcommands {
+                write_subcommand(out, keyword, subcommand);
+            }
+        }
+    }
+}
+
+fn write_subcommand(out: &mut Output, keyword: &str, subcommand: &Subcommand) {
+    let mut line = ArrayString::<[u8; 128]>::new();
+
+    if !keyword.is_empty() {
+        write!(line, "{} ", keyword).ignore();
+    }
+    write!(line, "{}", subcommand.name).ignore();
+    if !subcommand.args.is_empty() {
+        write!(line, " {}", subcommand.args).ignore();
+    }
+    writeln!(line, "\t{}", subcommand.help).ignore();
+
+    out.write_str(&line).ignore();
 }
 
 const BUFFER_LEN: usize = 1024;
 
+/// The clock of the usart, left at the 16 MHz of the internal oscillator
+const CLOCK: u32 = 16_000_000;
+
+/**
+ *  The baud rate, which the host has to match
+ *
+ *  Telemetry alone is a frame of about 65 bytes every 10 ms, or 6.5 kB/s,
+ *  which is more than 9600 baud can carry. This is about 11.5 kB/s, which
+ *  leaves room for the report and command output as well.
+ */
+pub const BAUD: u32 = 115_200;
+
+const UART_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "overflows",
+        args: "",
+        help: "bytes dropped or garbled sending and receiving",
+    },
+    Subcommand {
+        name: "reset",
+        args: "",
+        help: "reset the overflow counts",
+    },
+];
+
+/// DMA stream config for usart1 tx: channel 4, memory increment, memory to
+/// peripheral
+const TX_DMA_CONFIG: u32 = 4 << 25 | 1 << 10 | 0b01 << 6;
+const DMA_ENABLE: u32 = 1;
+
+/// All the interrupt flags for DMA stream 7 in HIFCR
+const TX_DMA_FLAGS: u32 = 0b111101 << 22;
+
+const USART_DMAT: u32 = 1 << 7;
+
+/// Bytes waiting to be sent, between `tx_tail` and `tx_head` of the Uart.
+/// The DMA reads from here directly, so it can not be moved.
+static mut TX_BUF: [u8; BUFFER_LEN] = [0; BUFFER_LEN];
+
 static UART: Mutex<RefCell<Option<stm32f405::USART1>>> =
     Mutex::new(RefCell::new(None));
-static RX_BUF: Mutex<RefCell<([u8; BUFFER_LEN], usize)>> =
-    Mutex::new(RefCell::new(([0; BUFFER_LEN], 0)));
+static RX: Mutex<RefCell<RxBuffer>> = Mutex::new(RefCell::new(RxBuffer {
+    buf: [0; BUFFER_LEN],
+    head: 0,
+    tail: 0,
+    overflows: 0,
+}));
+
+/// A ring buffer of received bytes, filled by the USART1 interrupt
+struct RxBuffer {
+    buf: [u8; BUFFER_LEN],
+    head: usize,
+    tail: usize,
+    overflows: u32,
+}
+
+impl RxBuffer {
+    fn push(&mut self, c: u8) {
+        let next = (self.head + 1) % BUFFER_LEN;
+
+        if next == self.tail {
+            self.overflows += 1;
+        } else {
+            self.buf[self.head] = c;
+            self.head = next;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.head == self.tail {
+            None
+        } else {
+            let c = self.buf[self.tail];
+            self.tail = (self.tail + 1) % BUFFER_LEN;
+            Some(c)
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        (self.head + 1) % BUFFER_LEN == self.tail
+    }
+
+    fn len(&self) -> usize {
+        (self.head + BUFFER_LEN - self.tail) % BUFFER_LEN
+    }
+}
 
 pub enum UartError {
     BufferFull,
 }
 
 pub struct Uart {
-    tx_buffer: [u8; BUFFER_LEN],
-    tx_length: usize,
+    dma: stm32f405::DMA2,
+    tx_head: usize,
+    tx_tail: usize,
+    tx_sending: usize,
+    tx_overflows: u32,
 }
 
 impl Uart {
@@ -41,13 +223,15 @@ impl Uart {
         rcc: &stm32f405::RCC,
         nvic: &mut stm32f405::NVIC,
         uart: stm32f405::USART1,
+        dma: stm32f405::DMA2,
         gpioa: &stm32f405::GPIOA,
     ) -> Uart {
         // enable clock for usart
         rcc.apb2enr.modify(|_, w| w.usart1en().set_bit());
 
-        // enable clock for gpioa
-        rcc.ahb1enr.modify(|_, w| w.gpioaen().set_bit());
+        // enable clock for gpioa and dma2
+        rcc.ahb1enr
+            .modify(|_, w| w.gpioaen().set_bit().dma2en().set_bit());
 
         // set pins to alternate function
         gpioa
@@ -57,8 +241,9 @@ impl Uart {
         // set the alternate function to usart1 rx and tx
         gpioa.afrh.modify(|_, w| w.afrh9().af7().afrh10().af7());
 
-        // set buadrate
-        uart.brr.write(|w| unsafe { w.bits(0x683) });
+        // set the baud rate, oversampling by 16, rounded to the nearest
+        uart.brr
+            .write(|w| unsafe { w.bits((CLOCK + BAUD / 2) / BAUD) });
 
         // enable rx and tx
         uart.cr1.write(|w| {
@@ -74,98 +259,220 @@ impl Uart {
             //.set_bit()
         });
 
+        // send through dma stream 7, into the data register
+        uart.cr3
+            .modify(|r, w| unsafe { w.bits(r.bits() | USART_DMAT) });
+        dma.s7par
+            .write(|w| unsafe { w.bits(&uart.dr as *const _ as u32) });
+        dma.s7cr.write(|w| unsafe { w.bits(TX_DMA_CONFIG) });
+
         cortex_m::interrupt::free(|cs| UART.borrow(cs).replace(Some(uart)));
 
         nvic.enable(interrupt::USART1);
 
         Uart {
-            tx_buffer: [0; BUFFER_LEN],
-            tx_length: 0,
+            dma,
+            tx_head: 0,
+            tx_tail: 0,
+            tx_sending: 0,
+            tx_overflows: 0,
         }
     }
 
     fn add_byte(&mut self, c: u8) -> Result<usize, UartError> {
-        if self.tx_length < BUFFER_LEN {
-            self.tx_buffer[self.tx_length] = c;
-            self.tx_length += 1;
+        let next = (self.tx_head + 1) % BUFFER_LEN;
+
+        if next != self.tx_tail {
+            unsafe { TX_BUF[self.tx_head] = c };
+            self.tx_head = next;
 
-            Ok(BUFFER_LEN - self.tx_length)
+            Ok((self.tx_tail + BUFFER_LEN - next - 1) % BUFFER_LEN)
         } else {
             Err(UartError::BufferFull)
         }
     }
 
+    fn free(&self) -> usize {
+        (self.tx_tail + BUFFER_LEN - self.tx_head - 1) % BUFFER_LEN
+    }
+
     pub fn add_str(&mut self, s: &str) -> Result<usize, UartError> {
-        for &c in s.as_bytes() {
+        for (i, &c) in s.as_bytes().iter().enumerate() {
+            if let Err(e) = self.add_byte(c) {
+                self.tx_overflows += (s.len() - i) as u32;
+                return Err(e);
+            }
+        }
+        Ok((self.tx_head + BUFFER_LEN - self.tx_tail) % BUFFER_LEN)
+    }
+
+    /**
+     *  Add bytes to be sent, all or none of them
+     *
+     *  This is for binary frames, which are no use cut short.
+     */
+    pub fn add_bytes(&mut self, bytes: &[u8]) -> Result<usize, UartError> {
+        if bytes.len() > self.free() {
+            self.tx_overflows += bytes.len() as u32;
+            return Err(UartError::BufferFull);
+        }
+
+        for &c in bytes {
             self.add_byte(c)?;
         }
-        Ok(self.tx_length)
+        Ok((self.tx_head + BUFFER_LEN - self.tx_tail) % BUFFER_LEN)
     }
 
-    pub fn flush_tx(&mut self, time: &mut Time, timeout: u32) {
-        let start_time = time.now();
-        while self.tx_length > 0 && time.now() - start_time <= timeout {
-            cortex_m::interrupt::free(|cs| {
-                if let Some(uart) = UART.borrow(cs).borrow().as_ref() {
-                    if uart.sr.read().txe().bit_is_set() {
-                        uart.dr
-                            .write(|w| w.dr().bits(self.tx_buffer[0] as u16));
-
-                        for i in 1..self.tx_length {
-                            self.tx_buffer[i - 1] = self.tx_buffer[i];
-                        }
-
-                        self.tx_length -= 1;
-                        self.tx_buffer[self.tx_length] = 0;
-                    }
-                }
-            });
+    /**
+     *  Add a string to be sent, waiting for room in the buffer as it goes
+     *
+     *  This is for the output of commands, which can be longer than the
+     *  buffer. It must never be called with the control loop held off.
+     */
+    pub fn add_str_wait(&mut self, s: &str) {
+        for chunk in s.as_bytes().chunks(BUFFER_LEN / 2) {
+            while self.free() < chunk.len() {
+                self.flush_tx();
+            }
+
+            self.add_bytes(chunk).ignore();
         }
     }
 
-    pub fn read_byte(&mut self) -> Option<u8> {
-        cortex_m::interrupt::free(|cs| {
-            if let Ok(mut buf) = RX_BUF.borrow(cs).try_borrow_mut() {
-                if buf.1 > 0 {
-                    let c = buf.0[0];
+    /**
+     *  Start sending whatever has been written, without waiting
+     *
+     *  The bytes are sent by DMA straight out of the buffer, so this only
+     *  has to start the next transfer once the last one is done.
+     */
+    pub fn flush_tx(&mut self) {
+        if self.dma.s7cr.read().bits() & DMA_ENABLE != 0 {
+            return;
+        }
 
-                    for i in 1..buf.1 {
-                        buf.0[i - 1] = buf.0[i];
-                    }
+        self.tx_tail = (self.tx_tail + self.tx_sending) % BUFFER_LEN;
+        self.tx_sending = 0;
 
-                    buf.1 -= 1;
+        if self.tx_head == self.tx_tail {
+            return;
+        }
 
-                    let len = buf.1;
-                    buf.0[len] = 0;
+        // Only send up to the end of the buffer, the rest goes next time
+        let len = if self.tx_head > self.tx_tail {
+            self.tx_head - self.tx_tail
+        } else {
+            BUFFER_LEN - self.tx_tail
+        };
 
-                    Some(c)
-                } else {
-                    None
-                }
-            } else {
-                None
+        let address = unsafe { TX_BUF.as_ptr() as u32 } + self.tx_tail as u32;
+
+        self.dma.hifcr.write(|w| unsafe { w.bits(TX_DMA_FLAGS) });
+        self.dma.s7m0ar.write(|w| unsafe { w.bits(address) });
+        self.dma.s7ndtr.write(|w| unsafe { w.bits(len as u32) });
+        self.dma
+            .s7cr
+            .write(|w| unsafe { w.bits(TX_DMA_CONFIG | DMA_ENABLE) });
+
+        self.tx_sending = len;
+    }
+
+    /// Wait up to `timeout` for everything written to be sent
+    pub fn wait_tx(&mut self, time: &Time, timeout: Duration) {
+        let start_time = time.now();
+        while time.now() - start_time <= timeout {
+            self.flush_tx();
+
+            if self.tx_head == self.tx_tail {
+                break;
             }
-        })
+        }
     }
 
+    pub fn read_byte(&mut self) -> Option<u8> {
+        cortex_m::interrupt::free(|cs| RX.borrow(cs).borrow_mut().pop())
+    }
+
+    /**
+     *  Take the next full line that has been received, padded with zeros
+     *
+     *  If the buffer fills up without a newline, it is thrown away and
+     *  counted as overflowed.
+     */
     pub fn read_line(&mut self) -> Option<[u8; BUFFER_LEN]> {
         cortex_m::interrupt::free(|cs| {
-            if let Ok(mut buf) = RX_BUF.borrow(cs).try_borrow_mut() {
-                if buf.1 > 0 && buf.0[buf.1 - 1] == 10 {
-                    let new_buf = buf.0.clone();
+            let mut rx = RX.borrow(cs).borrow_mut();
+
+            let len = rx.len();
+            let newline =
+                (0..len).find(|&i| rx.buf[(rx.tail + i) % BUFFER_LEN] == 10);
 
-                    buf.0 = [0; BUFFER_LEN];
-                    buf.1 = 0;
+            if let Some(newline) = newline {
+                let mut line = [0; BUFFER_LEN];
 
-                    Some(new_buf)
-                } else {
-                    None
+                for c in line.iter_mut().take(newline + 1) {
+                    *c = rx.pop().unwrap_or(0);
                 }
+
+                Some(line)
             } else {
+                if rx.is_full() {
+                    rx.overflows += len as u32;
+                    rx.tail = rx.head;
+                }
+
                 None
             }
         })
     }
+
+    /**
+     *  How many bytes have been dropped, sending and receiving
+     *
+     *  Received bytes count from the hardware too, when one came in before
+     *  the last was read or it arrived garbled.
+     */
+    pub fn overflows(&self) -> (u32, u32) {
+        let rx_overflows =
+            cortex_m::interrupt::free(|cs| RX.borrow(cs).borrow().overflows);
+
+        (self.tx_overflows, rx_overflows)
+    }
+}
+
+impl Command for Uart {
+    fn keyword_command(&self) -> &str {
+        "uart"
+    }
+
+    fn subcommands(&self) -> &[Subcommand] {
+        UART_SUBCOMMANDS
+    }
+
+    fn handle_command<'a, I: Iterator<Item = &'a str>>(
+        &mut self,
+        out: &mut Output,
+        mut args: I,
+    ) {
+        match args.next() {
+            Some("overflows") => {
+                let (tx_overflows, rx_overflows) = self.overflows();
+                writeln!(out, "tx: {}\trx: {}", tx_overflows, rx_overflows)
+                    .ignore();
+            }
+            Some("reset") => {
+                self.tx_overflows = 0;
+                cortex_m::interrupt::free(|cs| {
+                    RX.borrow(cs).borrow_mut().overflows = 0
+                });
+            }
+            Some(c) => {
+                writeln!(out, "uart: unknown command: {}", c).ignore();
+            }
+            None => {
+                writeln!(out, "uart: no command").ignore();
+            }
+        }
+    }
 }
 
 impl Write for Uart {
@@ -178,15 +485,22 @@ impl Write for Uart {
 fn USART1() {
     cortex_m::interrupt::free(|cs| {
         if let Some(uart) = UART.borrow(cs).borrow().as_ref() {
+            // Reading the status and then the data clears the error flags
+            let sr = uart.sr.read();
             let rx = uart.dr.read().dr().bits() as u8;
             //uart.dr.write(|w| w.dr().bits(rx as u16));
-            if let Ok(mut buf) = RX_BUF.borrow(cs).try_borrow_mut() {
-                if buf.1 < BUFFER_LEN {
-                    let len = buf.1;
-                    buf.0[len] = rx;
 
-                    buf.1 += 1;
-                }
+            let mut buffer = RX.borrow(cs).borrow_mut();
+
+            // An overrun loses the byte after the one in the data register
+            if sr.ore().bit_is_set() {
+                buffer.overflows += 1;
+            }
+
+            if sr.fe().bit_is_set() || sr.nf().bit_is_set() {
+                buffer.overflows += 1;
+            } else if sr.rxne().bit_is_set() {
+                buffer.push(rx);
             }
         }
     });
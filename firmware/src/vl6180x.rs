@@ -1,9 +1,34 @@
-use ignore_result::Ignore;
-
 use embedded_hal::blocking::i2c;
 
+use crate::time::Duration;
+use crate::time::Instant;
+
 pub const DEFAULT_ADDRESS: u8 = 0x29;
 
+/// How many times to poll for a result before giving up on a blocking read
+const READY_POLLS: u32 = 10000;
+
+/// How long to wait for a result past its period before restarting it
+const RANGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// ALS lux per count at a gain of 1 and a 100 ms integration period
+const ALS_LUX_RESOLUTION: f64 = 0.32;
+
+/// The actual ALS gain set by `init_default`, see table 14 in the datasheet
+const ALS_GAIN: f64 = 1.01;
+
+/// Convert an inter-measurement period in ms to its register value
+fn period_register(period: u16) -> u8 {
+    let period_reg = (period as i16 / 10) - 1;
+    if period_reg < 0 {
+        0
+    } else if period_reg > 254 {
+        254
+    } else {
+        period_reg as u8
+    }
+}
+
 mod registers {
     #![allow(dead_code)]
     pub const IDENTIFICATION__MODEL_ID: u16 = 0x000;
@@ -74,6 +99,71 @@ mod registers {
     pub const SCALAR_VALUES: [u16; 4] = [0, 253, 127, 84];
 }
 
+/// Error codes from RESULT__RANGE_STATUS, see table 12 in the datasheet
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RangeStatus {
+    NoError,
+    VcselContinuityTest,
+    VcselWatchdogTest,
+    VcselWatchdog,
+    Pll1Lock,
+    Pll2Lock,
+    EarlyConvergenceEstimate,
+    MaxConvergence,
+    NoTargetIgnore,
+    MaxSignalToNoiseRatio,
+    RawRangingAlgoUnderflow,
+    RawRangingAlgoOverflow,
+    RangingAlgoUnderflow,
+    RangingAlgoOverflow,
+    Reserved(u8),
+}
+
+impl RangeStatus {
+    pub fn from_code(code: u8) -> RangeStatus {
+        match code {
+            0 => RangeStatus::NoError,
+            1 => RangeStatus::VcselContinuityTest,
+            2 => RangeStatus::VcselWatchdogTest,
+            3 => RangeStatus::VcselWatchdog,
+            4 => RangeStatus::Pll1Lock,
+            5 => RangeStatus::Pll2Lock,
+            6 => RangeStatus::EarlyConvergenceEstimate,
+            7 => RangeStatus::MaxConvergence,
+            8 => RangeStatus::NoTargetIgnore,
+            11 => RangeStatus::MaxSignalToNoiseRatio,
+            12 => RangeStatus::RawRangingAlgoUnderflow,
+            13 => RangeStatus::RawRangingAlgoOverflow,
+            14 => RangeStatus::RangingAlgoUnderflow,
+            15 => RangeStatus::RangingAlgoOverflow,
+            c => RangeStatus::Reserved(c),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error<E> {
+    /// The i2c bus returned an error
+    I2c(E),
+
+    /// The sensor did not finish a measurement in time
+    Timeout,
+
+    /// The sensor finished a measurement, but it is not valid
+    Range(RangeStatus),
+}
+
+impl<E> Error<E> {
+    /// Whether the error means the sensor is not responding, rather than
+    /// just not seeing anything
+    pub fn is_bus_error(&self) -> bool {
+        match self {
+            Error::I2c(_) | Error::Timeout => true,
+            Error::Range(_) => false,
+        }
+    }
+}
+
 pub struct VL6180x<I2C>
 where
     I2C: i2c::Read + i2c::Write + i2c::WriteRead,
@@ -84,12 +174,22 @@ where
     scaling: u8,
     ptp_offset: u8,
 
-    range: u8,
+    range: Option<u16>,
+    range_status: RangeStatus,
+    range_time: Option<Instant>,
+    ambient: Option<u16>,
+    last_start: Option<Instant>,
+    period: Option<u16>,
+    interleaved: bool,
+
+    calibration: Option<(i8, u16)>,
 }
 
-impl<I2C> VL6180x<I2C>
+impl<I2C, E> VL6180x<I2C>
 where
-    I2C: i2c::Read + i2c::Write + i2c::WriteRead,
+    I2C: i2c::Read<Error = E>
+        + i2c::Write<Error = E>
+        + i2c::WriteRead<Error = E>,
 {
     pub fn new(i2c: I2C, address: u8) -> Self {
         VL6180x {
@@ -97,95 +197,106 @@ where
             address,
             scaling: 1,
             ptp_offset: 0,
-            range: 255,
+            range: None,
+            range_status: RangeStatus::NoError,
+            range_time: None,
+            ambient: None,
+            last_start: None,
+            period: None,
+            interleaved: false,
+            calibration: None,
         }
     }
 
-    fn write_u8(&mut self, reg: u16, data: u8) {
+    fn write_u8(&mut self, reg: u16, data: u8) -> Result<(), Error<E>> {
         let buf = [((reg >> 8) & 0xff) as u8, (reg & 0xff) as u8, data];
-        self.i2c.write(self.address, &buf).ignore();
+        self.i2c.write(self.address, &buf).map_err(Error::I2c)
     }
 
-    fn write_u16(&mut self, reg: u16, data: u16) {
+    fn write_u16(&mut self, reg: u16, data: u16) -> Result<(), Error<E>> {
         let buf = [
             ((reg >> 8) & 0xff) as u8,
             (reg & 0xff) as u8,
             ((data >> 8) & 0xff) as u8,
             (data & 0xff) as u8,
         ];
-        self.i2c.write(self.address, &buf).ignore();
+        self.i2c.write(self.address, &buf).map_err(Error::I2c)
     }
 
-    fn read_u8(&mut self, reg: u16) -> u8 {
+    fn read_u8(&mut self, reg: u16) -> Result<u8, Error<E>> {
         let mut buf = [0; 1];
-        self.i2c.write(self.address, &reg.to_be_bytes()).ignore();
-        self.i2c.read(self.address, &mut buf).ignore();
-        u8::from_be_bytes(buf)
+        self.i2c
+            .write(self.address, &reg.to_be_bytes())
+            .map_err(Error::I2c)?;
+        self.i2c.read(self.address, &mut buf).map_err(Error::I2c)?;
+        Ok(u8::from_be_bytes(buf))
     }
 
-    fn read_u16(&mut self, reg: u16) -> u16 {
+    fn read_u16(&mut self, reg: u16) -> Result<u16, Error<E>> {
         let mut buf = [0; 2];
-        self.i2c.write(self.address, &reg.to_be_bytes()).ignore();
-        self.i2c.read(self.address, &mut buf).ignore();
-        u16::from_be_bytes(buf)
+        self.i2c
+            .write(self.address, &reg.to_be_bytes())
+            .map_err(Error::I2c)?;
+        self.i2c.read(self.address, &mut buf).map_err(Error::I2c)?;
+        Ok(u16::from_be_bytes(buf))
     }
 
-    pub fn get_id_bytes(&mut self) -> [u8; 8] {
+    pub fn get_id_bytes(&mut self) -> Result<[u8; 8], Error<E>> {
         let mut buf = [0xde; 8];
 
         for (reg, byte) in buf.iter_mut().enumerate() {
-            *byte = self.read_u8(reg as u16);
+            *byte = self.read_u8(reg as u16)?;
         }
 
-        buf
+        Ok(buf)
     }
 
-    pub fn init_private_registers(&mut self) {
+    pub fn init_private_registers(&mut self) -> Result<(), Error<E>> {
         // Store part-to-part range offset so it can be adjusted if scaling is changed
         self.ptp_offset =
-            self.read_u8(registers::SYSRANGE__PART_TO_PART_RANGE_OFFSET);
+            self.read_u8(registers::SYSRANGE__PART_TO_PART_RANGE_OFFSET)?;
 
-        if self.read_u8(registers::SYSTEM__FRESH_OUT_OF_RESET) == 1 {
+        if self.read_u8(registers::SYSTEM__FRESH_OUT_OF_RESET)? == 1 {
             self.scaling = 1;
 
-            self.write_u8(0x207, 0x01);
-            self.write_u8(0x208, 0x01);
-            self.write_u8(0x096, 0x00);
-            self.write_u8(0x097, 0xFD); // RANGE_SCALER = 253
-            self.write_u8(0x0E3, 0x00);
-            self.write_u8(0x0E4, 0x04);
-            self.write_u8(0x0E5, 0x02);
-            self.write_u8(0x0E6, 0x01);
-            self.write_u8(0x0E7, 0x03);
-            self.write_u8(0x0F5, 0x02);
-            self.write_u8(0x0D9, 0x05);
-            self.write_u8(0x0DB, 0xCE);
-            self.write_u8(0x0DC, 0x03);
-            self.write_u8(0x0DD, 0xF8);
-            self.write_u8(0x09F, 0x00);
-            self.write_u8(0x0A3, 0x3C);
-            self.write_u8(0x0B7, 0x00);
-            self.write_u8(0x0BB, 0x3C);
-            self.write_u8(0x0B2, 0x09);
-            self.write_u8(0x0CA, 0x09);
-            self.write_u8(0x198, 0x01);
-            self.write_u8(0x1B0, 0x17);
-            self.write_u8(0x1AD, 0x00);
-            self.write_u8(0x0FF, 0x05);
-            self.write_u8(0x100, 0x05);
-            self.write_u8(0x199, 0x05);
-            self.write_u8(0x1A6, 0x1B);
-            self.write_u8(0x1AC, 0x3E);
-            self.write_u8(0x1A7, 0x1F);
-            self.write_u8(0x030, 0x00);
-
-            self.write_u8(registers::SYSTEM__FRESH_OUT_OF_RESET, 0);
+            self.write_u8(0x207, 0x01)?;
+            self.write_u8(0x208, 0x01)?;
+            self.write_u8(0x096, 0x00)?;
+            self.write_u8(0x097, 0xFD)?; // RANGE_SCALER = 253
+            self.write_u8(0x0E3, 0x00)?;
+            self.write_u8(0x0E4, 0x04)?;
+            self.write_u8(0x0E5, 0x02)?;
+            self.write_u8(0x0E6, 0x01)?;
+            self.write_u8(0x0E7, 0x03)?;
+            self.write_u8(0x0F5, 0x02)?;
+            self.write_u8(0x0D9, 0x05)?;
+            self.write_u8(0x0DB, 0xCE)?;
+            self.write_u8(0x0DC, 0x03)?;
+            self.write_u8(0x0DD, 0xF8)?;
+            self.write_u8(0x09F, 0x00)?;
+            self.write_u8(0x0A3, 0x3C)?;
+            self.write_u8(0x0B7, 0x00)?;
+            self.write_u8(0x0BB, 0x3C)?;
+            self.write_u8(0x0B2, 0x09)?;
+            self.write_u8(0x0CA, 0x09)?;
+            self.write_u8(0x198, 0x01)?;
+            self.write_u8(0x1B0, 0x17)?;
+            self.write_u8(0x1AD, 0x00)?;
+            self.write_u8(0x0FF, 0x05)?;
+            self.write_u8(0x100, 0x05)?;
+            self.write_u8(0x199, 0x05)?;
+            self.write_u8(0x1A6, 0x1B)?;
+            self.write_u8(0x1AC, 0x3E)?;
+            self.write_u8(0x1A7, 0x1F)?;
+            self.write_u8(0x030, 0x00)?;
+
+            self.write_u8(registers::SYSTEM__FRESH_OUT_OF_RESET, 0)?;
         } else {
             // Sensor has already been initialized, so try to get scaling settings by
             // reading registers.
 
             self.scaling = {
-                let s = self.read_u16(registers::RANGE_SCALER);
+                let s = self.read_u16(registers::RANGE_SCALER)?;
 
                 if s == registers::SCALAR_VALUES[3] {
                     3
@@ -202,68 +313,102 @@ where
             // be resolved by resetting the sensor and Arduino again.
             self.ptp_offset *= self.scaling;
         }
+
+        Ok(())
+    }
+
+    /// Run the full setup of a sensor that has just been powered on, and
+    /// start ranging
+    ///
+    /// The scaling, calibration and ranging mode from before are kept.
+    pub fn init(&mut self) -> Result<(), Error<E>> {
+        let scaling = self.scaling;
+
+        self.init_private_registers()?;
+        self.init_default()?;
+        if let Some((offset, crosstalk)) = self.calibration {
+            self.ptp_offset = offset as u8;
+            self.write_u16(
+                registers::SYSRANGE__CROSSTALK_COMPENSATION_RATE,
+                crosstalk,
+            )?;
+        }
+        self.set_scaling(scaling)?;
+        self.range = None;
+        self.range_time = None;
+        self.ambient = None;
+        self.last_start = None;
+        self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x07)?;
+        self.restart()
     }
 
-    pub fn read_range_status(&mut self) -> u8 {
-        self.read_u8(registers::RESULT__RANGE_STATUS) >> 4
+    pub fn read_range_status(&mut self) -> Result<RangeStatus, Error<E>> {
+        let status = self.read_u8(registers::RESULT__RANGE_STATUS)?;
+        Ok(RangeStatus::from_code(status >> 4))
     }
 
-    pub fn init_default(&mut self) {
+    pub fn init_default(&mut self) -> Result<(), Error<E>> {
         // "Recommended : Public registers"
 
         // readout__averaging_sample_period = 48
-        self.write_u8(registers::READOUT__AVERAGING_SAMPLE_PERIOD, 0x30);
+        self.write_u8(registers::READOUT__AVERAGING_SAMPLE_PERIOD, 0x30)?;
 
         // sysals__analogue_gain_light = 6
         // (ALS gain = 1 nominal, actually 1.01 according to Table 14 in datasheet)
-        self.write_u8(registers::SYSALS__ANALOGUE_GAIN, 0x46);
+        self.write_u8(registers::SYSALS__ANALOGUE_GAIN, 0x46)?;
 
         // sysrange__vhv_repeat_rate = 255
         // (auto Very High Voltage temperature recalibration
         // after every 255 range measurements)
-        self.write_u8(registers::SYSRANGE__VHV_REPEAT_RATE, 0xFF);
+        self.write_u8(registers::SYSRANGE__VHV_REPEAT_RATE, 0xFF)?;
 
         // sysals__integration_period = 99 (100 ms)
         // AN4545 incorrectly recommends writing to register 0x040;
         // 0x63 should go in the lower byte, which is register 0x041.
-        self.write_u16(registers::SYSALS__INTEGRATION_PERIOD, 0x0063);
+        self.write_u16(registers::SYSALS__INTEGRATION_PERIOD, 0x0063)?;
 
         // sysrange__vhv_recalibrate = 1 (manually trigger a VHV recalibration)
-        self.write_u8(registers::SYSRANGE__VHV_RECALIBRATE, 0x01);
+        self.write_u8(registers::SYSRANGE__VHV_RECALIBRATE, 0x01)?;
 
         // "Optional: Public registers"
 
         // sysrange__intermeasurement_period = 9 (100 ms)
-        self.write_u8(registers::SYSRANGE__INTERMEASUREMENT_PERIOD, 0x09);
+        self.write_u8(registers::SYSRANGE__INTERMEASUREMENT_PERIOD, 0x09)?;
 
         // sysals__intermeasurement_period = 49 (500 ms)
-        self.write_u8(registers::SYSALS__INTERMEASUREMENT_PERIOD, 0x31);
+        self.write_u8(registers::SYSALS__INTERMEASUREMENT_PERIOD, 0x31)?;
 
         // als_int_mode = 4 (ALS new sample ready interrupt);
         // range_int_mode = 4 (range new sample ready interrupt)
-        self.write_u8(registers::SYSTEM__INTERRUPT_CONFIG_GPIO, 0x24);
+        self.write_u8(registers::SYSTEM__INTERRUPT_CONFIG_GPIO, 0x24)?;
+
+        // gpio1 = interrupt output, active low
+        self.write_u8(registers::SYSTEM__MODE_GPIO1, 0x10)?;
 
         // Reset other settings to power-on defaults
 
         // sysrange__max_convergence_time = 49 (49 ms)
-        self.write_u8(registers::SYSRANGE__MAX_CONVERGENCE_TIME, 0x31);
+        self.write_u8(registers::SYSRANGE__MAX_CONVERGENCE_TIME, 0x31)?;
 
         // disable interleaved mode
-        self.write_u8(registers::INTERLEAVED_MODE__ENABLE, 0);
+        self.write_u8(registers::INTERLEAVED_MODE__ENABLE, 0)?;
 
         // reset range scaling factor to 1x
-        self.set_scaling(1);
+        self.set_scaling(1)
     }
 
     // Implemented using ST's VL6180X API as a reference (STSW-IMG003); see
     // VL6180x_UpscaleSetScaling() in vl6180x_api.c.
-    fn set_scaling(&mut self, new_scaling: u8) {
+    //
+    // Ranges are multiplied by the scaling, so 2x and 3x reach 510 mm and
+    // 765 mm at the cost of resolution.
+    pub fn set_scaling(&mut self, new_scaling: u8) -> Result<(), Error<E>> {
         // default value of SYSRANGE__CROSSTALK_VALID_HEIGHT
         let default_crosstalk_valid_height = 20;
 
         // do nothing if scaling value is invalid
         if new_scaling < 1 || new_scaling > 3 {
-            return;
+            return Ok(());
         }
 
         self.scaling = new_scaling;
@@ -273,33 +418,37 @@ where
         self.write_u16(
             registers::RANGE_SCALER,
             registers::SCALAR_VALUES[scaling as usize],
-        );
+        )?;
 
-        // apply scaling on part-to-part offset
+        // apply scaling on part-to-part offset, which is two's complement
         self.write_u8(
             registers::SYSRANGE__PART_TO_PART_RANGE_OFFSET,
-            ptp_offset / scaling,
-        );
+            (ptp_offset as i8 / scaling as i8) as u8,
+        )?;
 
         // apply scaling on CrossTalkValidHeight
         self.write_u8(
             registers::SYSRANGE__CROSSTALK_VALID_HEIGHT,
             default_crosstalk_valid_height / scaling,
-        );
+        )?;
 
         // This function does not apply scaling to RANGE_IGNORE_VALID_HEIGHT.
 
         // enable early convergence estimate only at 1x scaling
-        let rce = self.read_u8(registers::SYSRANGE__RANGE_CHECK_ENABLES);
+        let rce = self.read_u8(registers::SYSRANGE__RANGE_CHECK_ENABLES)?;
         self.write_u8(
             registers::SYSRANGE__RANGE_CHECK_ENABLES,
             (rce & 0xFE) | (scaling == 1) as u8,
-        );
+        )
+    }
+
+    pub fn scaling(&self) -> u8 {
+        self.scaling
     }
 
     // Performs a single-shot ranging measurement
-    pub fn read_range_single(&mut self) -> u8 {
-        self.write_u8(registers::SYSRANGE__START, 0x01);
+    pub fn read_range_single(&mut self) -> Result<u16, Error<E>> {
+        self.write_u8(registers::SYSRANGE__START, 0x01)?;
         self.read_range_continuous()
     }
 
@@ -309,53 +458,298 @@ where
     // The period must be greater than the time it takes to perform a
     // measurement. See section 2.4.4 ("Continuous mode limits") in the datasheet
     // for details.
-    pub fn start_range_continuous(&mut self, period: u16) {
-        let period_reg = (period as i16 / 10) - 1;
-        let period_reg = if period_reg < 0 {
-            0
-        } else if period_reg > 254 {
-            254
-        } else {
-            period_reg
-        };
+    pub fn start_range_continuous(
+        &mut self,
+        period: u16,
+    ) -> Result<(), Error<E>> {
+        self.stop_range_continuous()?;
 
         self.write_u8(
             registers::SYSRANGE__INTERMEASUREMENT_PERIOD,
-            period_reg as u8,
-        );
-        self.write_u8(registers::SYSRANGE__START, 0x03);
+            period_register(period),
+        )?;
+        self.write_u8(registers::SYSRANGE__START, 0x03)?;
+        self.period = Some(period);
+        Ok(())
+    }
+
+    // Starts continuous interleaved measurements with the given period in ms.
+    // In this mode, each ambient light measurement is immediately followed
+    // by a range measurement.
+    //
+    // The datasheet recommends using this mode instead of running "range and
+    // ALS continuous modes simultaneously (i.e. asynchronously)".
+    //
+    // The period must be greater than the time it takes to perform both
+    // measurements. See section 2.4.4 ("Continuous mode limits") in the
+    // datasheet for details.
+    pub fn start_interleaved_continuous(
+        &mut self,
+        period: u16,
+    ) -> Result<(), Error<E>> {
+        self.stop_range_continuous()?;
+
+        self.write_u8(
+            registers::SYSALS__INTERMEASUREMENT_PERIOD,
+            period_register(period),
+        )?;
+        self.write_u8(registers::INTERLEAVED_MODE__ENABLE, 1)?;
+        self.write_u8(registers::SYSALS__START, 0x03)?;
+        self.period = Some(period);
+        self.interleaved = true;
+        Ok(())
+    }
+
+    // Stops continuous or interleaved mode. The measurement in progress still
+    // finishes.
+    pub fn stop_range_continuous(&mut self) -> Result<(), Error<E>> {
+        if self.interleaved {
+            self.write_u8(registers::SYSALS__START, 0x01)?;
+            self.write_u8(registers::INTERLEAVED_MODE__ENABLE, 0)?;
+            self.interleaved = false;
+        } else if self.period.is_some() {
+            self.write_u8(registers::SYSRANGE__START, 0x01)?;
+        }
+
+        self.period = None;
+        Ok(())
+    }
+
+    /**
+     *  Calibrate the part-to-part offset and the crosstalk compensation
+     *  against a target `distance` mm away, averaging over `samples`
+     *  measurements
+     *
+     *  This follows the procedure in AN4545: the offset is found first with
+     *  no crosstalk compensation, then the crosstalk from the return rate
+     *  and how short the corrected range still reads. Both are written to
+     *  the sensor and kept across `init`. Returns the offset in mm and the
+     *  crosstalk rate in 9.7 fixed point Mcps.
+     */
+    pub fn calibrate(
+        &mut self,
+        distance: u16,
+        samples: u16,
+    ) -> Result<(i8, u16), Error<E>> {
+        self.stop_range_continuous()?;
+
+        // Let the measurement that was running finish, it may not be valid
+        self.read_range_continuous().ok();
+
+        self.write_u16(registers::SYSRANGE__CROSSTALK_COMPENSATION_RATE, 0)?;
+        self.ptp_offset = 0;
+        let scaling = self.scaling;
+        self.set_scaling(scaling)?;
+
+        let mut total = 0u32;
+        for _ in 0..samples {
+            total += self.read_range_single()? as u32;
+        }
+
+        let offset = distance as i32 - (total / samples as u32) as i32;
+        let offset = offset.max(-128).min(127) as i8;
+
+        self.ptp_offset = offset as u8;
+        self.set_scaling(scaling)?;
+
+        let mut total = 0u32;
+        let mut total_rate = 0u32;
+        for _ in 0..samples {
+            total += self.read_range_single()? as u32;
+            total_rate +=
+                self.read_u16(registers::RESULT__RANGE_RETURN_RATE)? as u32;
+        }
+
+        let range = total as f64 / samples as f64;
+        let rate = total_rate as f64 / samples as f64;
+
+        // XTalk = return rate * (1 - measured range / actual range)
+        let crosstalk = if range < distance as f64 {
+            (rate * (1.0 - range / distance as f64)) as u16
+        } else {
+            0
+        };
+
+        self.write_u16(
+            registers::SYSRANGE__CROSSTALK_COMPENSATION_RATE,
+            crosstalk,
+        )?;
+        self.calibration = Some((offset, crosstalk));
+
+        self.last_start = None;
+        self.restart()?;
+
+        Ok((offset, crosstalk))
+    }
+
+    // Start measuring again in the same mode as before
+    fn restart(&mut self) -> Result<(), Error<E>> {
+        match self.period {
+            Some(period) if self.interleaved => {
+                self.start_interleaved_continuous(period)
+            }
+            Some(period) => self.start_range_continuous(period),
+            None => self.start_ranging(),
+        }
+    }
+
+    // Performs a single-shot ambient light measurement, returning the raw
+    // count
+    pub fn read_ambient_single(&mut self) -> Result<u16, Error<E>> {
+        self.write_u8(registers::SYSALS__START, 0x01)?;
+        self.read_ambient_continuous()
+    }
+
+    // Returns an ambient light reading when continuous or interleaved mode is
+    // activated, giving up with a timeout like read_range_continuous
+    pub fn read_ambient_continuous(&mut self) -> Result<u16, Error<E>> {
+        let mut polls = 0;
+
+        while (self.read_u8(registers::RESULT__INTERRUPT_STATUS_GPIO)? & 0x20)
+            == 0
+        {
+            polls += 1;
+            if polls > READY_POLLS {
+                return Err(Error::Timeout);
+            }
+        }
+
+        let ambient = self.read_u16(registers::RESULT__ALS_VAL)?;
+        self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x02)?;
+
+        Ok(ambient)
     }
 
     // Returns a range reading when continuous mode is activated
     // (readRangeSingle() also calls this function after starting a single-shot
     // range measurement)
-    pub fn read_range_continuous(&mut self) -> u8 {
-        while (self.read_u8(registers::RESULT__INTERRUPT_STATUS_GPIO) & 0x04)
+    //
+    // Gives up with a timeout if the result is not ready after READY_POLLS
+    // reads of the interrupt status.
+    pub fn read_range_continuous(&mut self) -> Result<u16, Error<E>> {
+        let mut polls = 0;
+
+        while (self.read_u8(registers::RESULT__INTERRUPT_STATUS_GPIO)? & 0x04)
             == 0
-        {}
+        {
+            polls += 1;
+            if polls > READY_POLLS {
+                return Err(Error::Timeout);
+            }
+        }
 
-        let range = self.read_u8(registers::RESULT__RANGE_VAL);
-        self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x01);
+        let status = self.read_range_status()?;
+        let range = self.read_u8(registers::RESULT__RANGE_VAL)?;
+        self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x01)?;
 
-        range
+        match status {
+            RangeStatus::NoError => Ok(range as u16 * self.scaling as u16),
+            status => Err(Error::Range(status)),
+        }
     }
 
-    pub fn start_ranging(&mut self) {
-        self.write_u8(registers::SYSRANGE__START, 0x01);
+    // Starts a single-shot measurement, leaving continuous mode if it was
+    // running
+    pub fn start_ranging(&mut self) -> Result<(), Error<E>> {
+        self.stop_range_continuous()?;
+        self.write_u8(registers::SYSRANGE__START, 0x01)
     }
 
-    pub fn update(&mut self) {
-        if (self.read_u8(registers::RESULT__INTERRUPT_STATUS_GPIO) * 0x04) != 0
-        {
-            let range = self.read_u8(registers::RESULT__RANGE_VAL);
-            self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x01);
-            self.start_ranging();
+    /**
+     *  Fetch a finished measurement, and start the next one
+     *
+     *  `ready` is the time the sensor pulled GPIO1 low to say a range or, in
+     *  interleaved mode, an ambient light result was ready, or None if it
+     *  has not since the last update, in which case the bus is not touched.
+     *  If the measurement was not valid, or the sensor did not respond, the
+     *  range is cleared until the next good measurement instead of keeping
+     *  the last one. A measurement that takes longer than RANGE_TIMEOUT
+     *  past its period is restarted. Returns the range if a new valid one
+     *  was measured.
+     */
+    pub fn update(
+        &mut self,
+        now: Instant,
+        ready: Option<Instant>,
+    ) -> Result<Option<u16>, Error<E>> {
+        let result = self.fetch_range(now, ready);
+
+        if result.is_err() {
+            self.range = None;
+        }
+
+        result
+    }
 
-            self.range = range;
+    fn fetch_range(
+        &mut self,
+        now: Instant,
+        ready: Option<Instant>,
+    ) -> Result<Option<u16>, Error<E>> {
+        let last_start = *self.last_start.get_or_insert(now);
+        let period = Duration::from_millis(self.period.unwrap_or(0) as u64);
+
+        if let Some(ready) = ready {
+            let interrupts =
+                self.read_u8(registers::RESULT__INTERRUPT_STATUS_GPIO)?;
+            self.last_start = Some(ready);
+
+            if interrupts & 0x20 != 0 {
+                self.ambient = Some(self.read_u16(registers::RESULT__ALS_VAL)?);
+                self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x02)?;
+            }
+
+            if interrupts & 0x04 == 0 {
+                return Ok(None);
+            }
+
+            let status = self.read_range_status()?;
+            let range = self.read_u8(registers::RESULT__RANGE_VAL)?;
+            self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x01)?;
+            if self.period.is_none() {
+                self.start_ranging()?;
+            }
+
+            self.range_status = status;
+
+            match status {
+                RangeStatus::NoError => {
+                    let range = range as u16 * self.scaling as u16;
+                    self.range = Some(range);
+                    self.range_time = Some(ready);
+                    Ok(Some(range))
+                }
+                status => Err(Error::Range(status)),
+            }
+        } else if now - last_start > period + RANGE_TIMEOUT {
+            // A missed edge leaves GPIO1 low, so clear it before restarting
+            self.write_u8(registers::SYSTEM__INTERRUPT_CLEAR, 0x07)?;
+            self.restart()?;
+            self.last_start = Some(now);
+            Err(Error::Timeout)
+        } else {
+            Ok(None)
         }
     }
 
-    pub fn range(&self) -> u8 {
+    /// The last valid range in mm, or None if the last measurement failed
+    pub fn range(&self) -> Option<u16> {
         self.range
     }
+
+    /// When the last valid range was measured
+    pub fn range_time(&self) -> Option<Instant> {
+        self.range_time
+    }
+
+    /// The last ambient light level in lux, only measured in interleaved mode
+    pub fn ambient(&self) -> Option<f64> {
+        self.ambient
+            .map(|count| ALS_LUX_RESOLUTION * count as f64 / ALS_GAIN)
+    }
+
+    /// The status of the last measurement
+    pub fn range_status(&self) -> RangeStatus {
+        self.range_status
+    }
 }
@@ -0,0 +1,345 @@
+//! An interactive console for the mouse's command shell
+//!
+//! ```text
+//! console <device> [--baud N] [--log file] [--no-color]
+//! ```
+//!
+//! Lines typed are sent as commands, with history on up and down and tab
+//! completing the command words, which are learned from the mouse's `help`
+//! when the console connects. What the mouse echoes back (`>> `) is
+//! shown apart from its responses, and binary telemetry frames are left
+//! out. With `--log`, everything sent and received is written to a file
+//! with the time since the console started.
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::process;
+use std::time::Duration;
+use std::time::Instant;
+
+use telemetry::Decoder;
+use telemetry::Event;
+
+use host::commands;
+use host::editor::Action;
+use host::editor::Editor;
+use host::editor::RawTerminal;
+use host::serial;
+use host::serial::Serial;
+
+const USAGE: &str =
+    "usage: console <device> [--baud N] [--log file] [--no-color]";
+
+const PROMPT: &str = "mouse> ";
+
+const ECHO_COLOR: &str = "\x1b[36m";
+const CONSOLE_COLOR: &str = "\x1b[33m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// How long to wait for the last responses once the input runs out
+const DRAIN_TIME: Duration = Duration::from_millis(500);
+
+/// How long the mouse has to go quiet for its answer to be finished
+const ANSWER_TIME: Duration = Duration::from_millis(200);
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+struct Console {
+    port: Serial,
+    editor: Editor,
+    log: Option<File>,
+    color: bool,
+    interactive: bool,
+    start: Instant,
+    decoder: Decoder,
+    line: Vec<u8>,
+    frames: u64,
+}
+
+impl Console {
+    fn log(&mut self, direction: &str, line: &str) -> io::Result<()> {
+        let elapsed = self.start.elapsed().as_secs_f64();
+
+        match self.log.as_mut() {
+            Some(log) => {
+                writeln!(log, "{:.3}\t{}\t{}", elapsed, direction, line)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Print a line above the prompt, in `color` if there is one
+    fn print(&mut self, line: &str, color: Option<&str>) -> io::Result<()> {
+        let mut out = io::stdout();
+
+        if self.interactive {
+            self.editor.clear(&mut out)?;
+        }
+
+        match color {
+            Some(color) if self.color => {
+                writeln!(out, "{}{}{}", color, line, RESET_COLOR)?
+            }
+            _ => writeln!(out, "{}", line)?,
+        }
+
+        if self.interactive {
+            self.editor.render(&mut out)
+        } else {
+            out.flush()
+        }
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.port, "{}", line)?;
+        self.log("tx", line)
+    }
+
+    /// The lines finished by some bytes, leaving out telemetry frames
+    fn decode(&mut self, bytes: &[u8]) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+
+        for &byte in bytes {
+            match self.decoder.push(byte) {
+                Some(Event::Text(b'\n')) => {
+                    let text = String::from_utf8_lossy(&self.line)
+                        .trim_end()
+                        .to_string();
+                    self.line.clear();
+
+                    self.log("rx", &text)?;
+                    lines.push(text);
+                }
+                Some(Event::Text(byte)) => self.line.push(byte),
+                Some(Event::Frame(_)) => self.frames += 1,
+                None => {}
+            }
+        }
+
+        Ok(lines)
+    }
+
+    fn receive(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for text in self.decode(bytes)? {
+            if text.starts_with(">> ") {
+                self.print(&text, Some(ECHO_COLOR))?;
+            } else {
+                self.print(&text, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send a command, and take the lines that come back until the mouse
+    /// goes quiet, without showing them
+    fn ask(&mut self, command: &str) -> io::Result<Vec<String>> {
+        self.send(command)?;
+
+        let mut lines = Vec::new();
+        let mut buf = [0; 256];
+        let mut last_read = Instant::now();
+
+        while last_read.elapsed() < ANSWER_TIME {
+            let len = self.port.read(&mut buf)?;
+
+            if len > 0 {
+                lines.extend(self.decode(&buf[..len])?);
+                last_read = Instant::now();
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// Handle some keys, returning false once the user has quit
+    fn keys(&mut self, bytes: &[u8]) -> io::Result<bool> {
+        for &byte in bytes {
+            match self.editor.push(byte) {
+                Action::Submit(line) => {
+                    if self.interactive {
+                        let mut out = io::stdout();
+                        self.editor.clear(&mut out)?;
+                        writeln!(out, "{}{}", PROMPT, line)?;
+                    }
+
+                    self.send(line.trim())?;
+                }
+                Action::Options(options) => {
+                    self.print(&options.join("  "), Some(CONSOLE_COLOR))?
+                }
+                Action::Quit => return Ok(false),
+                Action::None => {}
+            }
+        }
+
+        if self.interactive {
+            self.editor.render(&mut io::stdout())?;
+        }
+
+        Ok(true)
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut device = None;
+    let mut baud = serial::DEFAULT_BAUD;
+    let mut log_path = None;
+    let mut color = true;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--baud" => {
+                baud = args
+                    .next()
+                    .and_then(|b| b.parse().ok())
+                    .ok_or_else(|| USAGE.to_string())?
+            }
+            "--log" => {
+                log_path = Some(args.next().ok_or_else(|| USAGE.to_string())?)
+            }
+            "--no-color" => color = false,
+            _ if device.is_none() && !arg.starts_with("--") => {
+                device = Some(arg.as_str())
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let device = device.ok_or_else(|| USAGE.to_string())?;
+
+    let port = Serial::open(device, baud)
+        .map_err(|e| format!("console: {}: {}", device, e))?;
+
+    let log = match log_path {
+        Some(path) => Some(
+            File::create(path)
+                .map_err(|e| format!("console: {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+
+    let stdin = io::stdin().as_raw_fd();
+
+    // Only edit lines when there is someone typing them
+    let raw = RawTerminal::new(stdin).ok();
+    let interactive = raw.is_some();
+    let color = color && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+
+    let mut console = Console {
+        port,
+        editor: Editor::new(PROMPT),
+        log,
+        color,
+        interactive,
+        start: Instant::now(),
+        decoder: Decoder::new(),
+        line: Vec::new(),
+        frames: 0,
+    };
+
+    if interactive {
+        console
+            .print(
+                &format!("connected to {}, learning its commands", device),
+                Some(CONSOLE_COLOR),
+            )
+            .map_err(|e| format!("console: {}", e))?;
+    }
+
+    let commands = commands::learn(|command| console.ask(command))
+        .map_err(|e| format!("console: {}", e))?;
+    console.editor.set_commands(commands);
+
+    if interactive {
+        console
+            .print("ready, ctrl-d to quit", Some(CONSOLE_COLOR))
+            .map_err(|e| format!("console: {}", e))?;
+    }
+
+    let result = run_loop(&mut console, stdin);
+
+    drop(raw);
+
+    if interactive {
+        println!();
+    }
+
+    if console.frames > 0 {
+        eprintln!("{} telemetry frames not shown", console.frames);
+    }
+
+    result.map_err(|e| format!("console: {}", e))
+}
+
+fn run_loop(console: &mut Console, stdin: i32) -> io::Result<()> {
+    let mut buf = [0; 256];
+    let mut drain_start = None;
+
+    loop {
+        if let Some(drain_start) = drain_start {
+            if Instant::now().duration_since(drain_start) > DRAIN_TIME {
+                return Ok(());
+            }
+        }
+
+        let mut fds = [
+            libc::pollfd {
+                fd: stdin,
+                events: if drain_start.is_none() {
+                    libc::POLLIN
+                } else {
+                    0
+                },
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: console.port.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, 100) } < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+
+        if fds[1].revents & (libc::POLLIN | libc::POLLHUP) != 0 {
+            let len = console.port.read(&mut buf)?;
+            console.receive(&buf[..len])?;
+        }
+
+        // A closed stdin keeps reporting a hang up, once it has run out
+        if drain_start.is_none()
+            && fds[0].revents & (libc::POLLIN | libc::POLLHUP) != 0
+        {
+            let len = unsafe {
+                libc::read(stdin, buf.as_mut_ptr() as *mut _, buf.len())
+            };
+
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            } else if len == 0 {
+                drain_start = Some(Instant::now());
+            } else if !console.keys(&buf[..len as usize])? {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Records telemetry from the mouse to CSV, and plots the recordings
+//!
+//! ```text
+//! logger record <device> <name> [--baud N] [--duration secs] [--send CMD]...
+//! logger plot <name>.csv [--window ms]
+//! ```
+//!
+//! Recording starts the binary telemetry with `$`, writing each state to
+//! `<name>.csv` and each line of the text report to `<name>-report.csv`.
+//! Plotting writes `<name>-pid.svg`, `<name>-spin.svg` and
+//! `<name>-motion.svg` next to the recording.
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+use std::time::Duration;
+use std::time::Instant;
+
+use telemetry::Decoder;
+use telemetry::Event;
+use telemetry::Message;
+
+use host::plot::Plot;
+use host::plot::Series;
+use host::record;
+use host::record::Table;
+use host::serial;
+use host::serial::Serial;
+
+const USAGE: &str = "usage:
+    logger record <device> <name> [--baud N] [--duration secs] [--send CMD]...
+    logger plot <name>.csv [--window ms]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("record") => record(&args[1..]),
+        Some("plot") => plot(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// The positional arguments, and the `--flag value` pairs
+type Args<'a> = (Vec<&'a str>, Vec<(&'a str, &'a str)>);
+
+fn parse_args(args: &[String]) -> Result<Args<'_>, String> {
+    let mut positional = Vec::new();
+    let mut flags = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("logger: missing value for {}", arg))?;
+            flags.push((arg.as_str(), value.as_str()));
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+
+    Ok((positional, flags))
+}
+
+fn parse_flag<T: std::str::FromStr>(
+    flag: &str,
+    value: &str,
+) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("logger: invalid value for {}: {}", flag, value))
+}
+
+fn io_error(path: &str, e: io::Error) -> String {
+    format!("logger: {}: {}", path, e)
+}
+
+fn record(args: &[String]) -> Result<(), String> {
+    let (positional, flags) = parse_args(args)?;
+
+    let (device, name) = match positional.as_slice() {
+        [device, name] => (*device, *name),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut baud = serial::DEFAULT_BAUD;
+    let mut duration = None;
+    let mut commands = Vec::new();
+
+    for (flag, value) in flags {
+        match flag {
+            "--baud" => baud = parse_flag(flag, value)?,
+            "--duration" => {
+                duration =
+                    Some(Duration::from_secs_f64(parse_flag(flag, value)?))
+            }
+            "--send" => commands.push(value),
+            _ => return Err(format!("logger: unknown option: {}", flag)),
+        }
+    }
+
+    let mut port =
+        Serial::open(device, baud).map_err(|e| io_error(device, e))?;
+
+    let state_path = format!("{}.csv", name);
+    let report_path = format!("{}-report.csv", name);
+
+    let mut states = BufWriter::new(
+        File::create(&state_path).map_err(|e| io_error(&state_path, e))?,
+    );
+    let mut reports = BufWriter::new(
+        File::create(&report_path).map_err(|e| io_error(&report_path, e))?,
+    );
+
+    record::write_bot_state_header(&mut states)
+        .map_err(|e| io_error(&state_path, e))?;
+
+    for command in &commands {
+        writeln!(port, "{}", command).map_err(|e| io_error(device, e))?;
+    }
+    writeln!(port, "$").map_err(|e| io_error(device, e))?;
+
+    let start = Instant::now();
+    let mut decoder = Decoder::new();
+    let mut line = Vec::new();
+    let mut buf = [0; 256];
+
+    let mut frames = 0u64;
+    let mut bad_frames = 0u64;
+
+    while duration.map(|d| start.elapsed() < d).unwrap_or(true) {
+        let len = port.read(&mut buf).map_err(|e| io_error(device, e))?;
+        let host_ms = start.elapsed().as_millis();
+
+        for &byte in &buf[..len] {
+            match decoder.push(byte) {
+                Some(Event::Text(b'\n')) => {
+                    let text = String::from_utf8_lossy(&line);
+                    let text = text.trim_end();
+
+                    if text.starts_with(">> ") {
+                        // The mouse echoing a command back
+                        println!("{}", text);
+                    } else if text.contains('\t') {
+                        record::write_report_line(&mut reports, host_ms, text)
+                            .and_then(|_| reports.flush())
+                            .map_err(|e| io_error(&report_path, e))?;
+                    } else if !text.is_empty() {
+                        println!("{}", text);
+                    }
+
+                    line.clear();
+                }
+                Some(Event::Text(byte)) => line.push(byte),
+                Some(Event::Frame(Ok(frame))) => {
+                    frames += 1;
+
+                    match frame.message {
+                        Message::BotState(state) => {
+                            record::write_bot_state(
+                                &mut states,
+                                host_ms,
+                                frame.time,
+                                &state,
+                            )
+                            .and_then(|_| states.flush())
+                            .map_err(|e| io_error(&state_path, e))?;
+                        }
+                    }
+                }
+                Some(Event::Frame(Err(_))) => bad_frames += 1,
+                None => {}
+            }
+        }
+    }
+
+    writeln!(port, "!").map_err(|e| io_error(device, e))?;
+
+    eprintln!("{} frames recorded, {} bad", frames, bad_frames);
+
+    Ok(())
+}
+
+fn plot(args: &[String]) -> Result<(), String> {
+    let (positional, flags) = parse_args(args)?;
+
+    let path = match positional.as_slice() {
+        [path] => *path,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut window = None;
+
+    for (flag, value) in flags {
+        match flag {
+            "--window" => window = Some(parse_flag::<f64>(flag, value)?),
+            _ => return Err(format!("logger: unknown option: {}", flag)),
+        }
+    }
+
+    let table = Table::read(path).map_err(|e| io_error(path, e))?;
+
+    let column = |name: &str| {
+        table
+            .column(name)
+            .ok_or_else(|| format!("logger: {}: no column {}", path, name))
+    };
+
+    let time = column("time")?;
+    let left_velocity = column("left_velocity")?;
+    let right_velocity = column("right_velocity")?;
+
+    // Only plot the last `window` ms, like the scrolling gnuplot scripts
+    let start = match window {
+        Some(window) => {
+            let end = time.iter().cloned().fold(f64::NAN, f64::max);
+            time.iter().position(|&t| t >= end - window).unwrap_or(0)
+        }
+        None => 0,
+    };
+
+    let time = &time[start..];
+    let windowed = |values: Vec<f64>| values[start..].to_vec();
+
+    let left_velocity = windowed(left_velocity);
+    let right_velocity = windowed(right_velocity);
+
+    let mut pid = Plot::new("PID target vs velocity", "time (ms)");
+    pid.add(Series::new(
+        "left target",
+        time,
+        &windowed(column("left_target")?),
+    ));
+    pid.add(Series::new("left velocity", time, &left_velocity));
+    pid.add(Series::new(
+        "right target",
+        time,
+        &windowed(column("right_target")?),
+    ));
+    pid.add(Series::new("right velocity", time, &right_velocity));
+
+    let spin_velocity: Vec<f64> = left_velocity
+        .iter()
+        .zip(&right_velocity)
+        .map(|(l, r)| l - r)
+        .collect();
+
+    let mut spin = Plot::new("Spin", "time (ms)");
+    spin.add(Series::new(
+        "spin pos",
+        time,
+        &windowed(column("spin_pos")?),
+    ));
+    spin.add(
+        Series::new("spin velocity", time, &spin_velocity).on_right_axis(),
+    );
+
+    let linear_velocity: Vec<f64> = left_velocity
+        .iter()
+        .zip(&right_velocity)
+        .map(|(l, r)| (l + r) / 2.0)
+        .collect();
+
+    let mut motion = Plot::new("Linear motion", "time (ms)");
+    motion.add(Series::new(
+        "linear pos",
+        time,
+        &windowed(column("linear_pos")?),
+    ));
+    motion.add(
+        Series::new("linear velocity", time, &linear_velocity).on_right_axis(),
+    );
+
+    let name = path.trim_end_matches(".csv");
+
+    for (suffix, plot) in &[("pid", pid), ("spin", spin), ("motion", motion)] {
+        let svg_path = format!("{}-{}.svg", name, suffix);
+        std::fs::write(&svg_path, plot.to_svg())
+            .map_err(|e| io_error(&svg_path, e))?;
+        println!("{}", svg_path);
+    }
+
+    Ok(())
+}
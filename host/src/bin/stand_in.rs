@@ -0,0 +1,326 @@
+//! Stands in for the mouse on a pseudo-terminal, to try out the console
+//! and logger without one
+//!
+//! ```text
+//! stand_in
+//! ```
+//!
+//! Prints the path of the pseudo-terminal to connect to, then answers
+//! commands the way the firmware's main loop does: each line is echoed
+//! back after `>> `, `@` and `!` start and stop a report of made up
+//! values, and `$` starts telemetry frames of them. `help` answers from a
+//! few of the firmware's commands, so the console can learn them.
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::io::FromRawFd;
+use std::process;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use telemetry::BotState;
+use telemetry::Frame;
+use telemetry::Message;
+
+/// A command of the made up firmware, like those in its `Subcommand` tables
+struct Command {
+    name: &'static str,
+    args: &'static str,
+    help: &'static str,
+    children: &'static [Command],
+}
+
+const fn command(
+    name: &'static str,
+    args: &'static str,
+    help: &'static str,
+) -> Command {
+    Command {
+        name,
+        args,
+        help,
+        children: &[],
+    }
+}
+
+const fn group(
+    name: &'static str,
+    help: &'static str,
+    children: &'static [Command],
+) -> Command {
+    Command {
+        name,
+        args: "...",
+        help,
+        children,
+    }
+}
+
+const SIGNALS: &[&str] = &[
+    "time",
+    "left_velocity",
+    "right_velocity",
+    "linear_pos",
+    "spin_pos",
+    "front_distance",
+];
+
+const CONFIG: &[Command] = &[
+    command("left_p", "[value]", "left wheel velocity P gain"),
+    command("linear_p", "[value]", "linear move P gain"),
+    command("linear_max_velocity", "[value]", "fastest linear velocity"),
+];
+
+const BOT: &[Command] = &[
+    group("config", "show or change the config", CONFIG),
+    command("spin", "<velocity>", "spin at a velocity"),
+    command("linear", "<velocity>", "drive at a velocity"),
+    command("calibrate", "sensors", "calibrate the distance sensors"),
+];
+
+const CONTROL: &[Command] = &[
+    group("bot", "commands for the motors and sensors", BOT),
+    command("stop", "", "stop the current move"),
+    command("turn", "<left|right|around>", "turn in place"),
+];
+
+const PLAN: &[Command] = &[
+    group("control", "commands for single moves", CONTROL),
+    command("smooth", "[on|off]", "turn smooth moves on or off"),
+    command("mode", "[explore|speed|return]", "set or show the run mode"),
+    command("go", "", "start the run"),
+    command("stop", "", "stop the run"),
+];
+
+const REPORT: &[Command] = &[
+    command("add", "<signal>...", "add signals to the report"),
+    command("remove", "<signal>...", "remove signals from the report"),
+    command("rate", "[ms]", "set or show the time between reports"),
+    command("list", "", "show the signals reported, and all the signals"),
+    command("start", "", "start the report, like @"),
+    command("stop", "", "stop the report, like !"),
+];
+
+const COMMANDS: &[Command] = &[
+    group("plan", "commands for the moves through the maze", PLAN),
+    group("report", "choose what the report prints", REPORT),
+    group(
+        "sensors",
+        "commands for the distance sensors",
+        &[command("calibrate", "", "calibrate the distance sensors")],
+    ),
+    group("bot", "the same as plan control bot", BOT),
+    command("help", "[command...]", "show the subcommands of a command"),
+    command("@", "", "start the report"),
+    command("$", "", "start the binary telemetry"),
+    command("!", "", "stop the report and telemetry"),
+];
+
+fn write_command<W: Write>(
+    w: &mut W,
+    keyword: &str,
+    command: &Command,
+) -> io::Result<()> {
+    let mut line = String::new();
+
+    if !keyword.is_empty() {
+        line.push_str(keyword);
+        line.push(' ');
+    }
+    line.push_str(command.name);
+    if !command.args.is_empty() {
+        line.push(' ');
+        line.push_str(command.args);
+    }
+
+    writeln!(w, "{}\t{}", line, command.help)
+}
+
+/// Answer `help` for the commands at `path` the way the firmware does
+fn write_help<W: Write>(w: &mut W, path: &str) -> io::Result<()> {
+    let mut keyword = "";
+    let mut commands = COMMANDS;
+
+    for word in path.split_whitespace() {
+        match commands.iter().find(|c| c.name == word) {
+            Some(c) if !c.children.is_empty() => {
+                keyword = c.name;
+                commands = c.children;
+            }
+            Some(c) => return write_command(w, keyword, c),
+            None if keyword.is_empty() => {
+                return writeln!(w, "unknown command: {}", word)
+            }
+            None => {
+                return writeln!(w, "{}: unknown command: {}", keyword, word)
+            }
+        }
+    }
+
+    for command in commands {
+        write_command(w, keyword, command)?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("stand_in: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Open a pseudo-terminal, returning the master end and the slave's path
+fn open_pty() -> io::Result<(File, String)> {
+    let mut master = 0;
+    let mut slave = 0;
+
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut name = [0 as libc::c_char; 128];
+    if unsafe { libc::ttyname_r(slave, name.as_mut_ptr(), name.len()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let path = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned();
+
+    // The slave is kept open so the master doesn't hang up between clients
+    std::mem::forget(unsafe { File::from_raw_fd(slave) });
+
+    Ok((unsafe { File::from_raw_fd(master) }, path))
+}
+
+/// Whether the first words of a command are ones the firmware knows
+fn known(line: &str) -> bool {
+    let mut commands = COMMANDS;
+
+    for word in line.split_whitespace() {
+        match commands.iter().find(|c| c.name == word) {
+            Some(command) => commands = command.children,
+            None => return commands.is_empty(),
+        }
+    }
+
+    true
+}
+
+fn run() -> io::Result<()> {
+    let (mut pty, path) = open_pty()?;
+    println!("{}", path);
+
+    unsafe {
+        let fd = pty.as_raw_fd();
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+
+    let start = Instant::now();
+    let mut line = Vec::new();
+    let mut buf = [0; 256];
+
+    let mut report = false;
+    let mut telemetry = false;
+    let mut last_report = 0;
+
+    loop {
+        match pty.read(&mut buf) {
+            Ok(len) => {
+                for &byte in &buf[..len] {
+                    if byte != b'\n' && byte != b'\r' {
+                        line.push(byte);
+                        continue;
+                    }
+
+                    let command = String::from_utf8_lossy(&line).into_owned();
+                    let command = command.trim();
+                    line.clear();
+
+                    if command.is_empty() {
+                        continue;
+                    }
+
+                    writeln!(pty, ">> {}", command)?;
+
+                    if command.starts_with('!') {
+                        writeln!(pty, "Stopping report")?;
+                        report = false;
+                        telemetry = false;
+                    } else if command.starts_with('@') {
+                        writeln!(pty, "Starting report")?;
+                        report = true;
+                    } else if command.starts_with('$') {
+                        writeln!(pty, "Starting telemetry")?;
+                        telemetry = true;
+                    } else if command == "help" || command.starts_with("help ")
+                    {
+                        write_help(&mut pty, &command["help".len()..])?;
+                    } else if command == "report list" {
+                        writeln!(pty, "report:")?;
+                        writeln!(pty, "available: {}", SIGNALS.join(" "))?;
+                    } else if !known(command) {
+                        writeln!(pty, "Invalid Command! Try help")?;
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        let now = start.elapsed().as_millis() as u32;
+
+        if now - last_report >= 20 {
+            let t = now as f32 / 1000.0;
+
+            if report {
+                writeln!(pty, "{}\t{:.2}\t{:.2}", now, t.sin(), t.cos())?;
+            }
+
+            if telemetry {
+                let state = BotState {
+                    left_velocity: t.sin(),
+                    right_velocity: t.cos(),
+                    left_target: t.sin().signum(),
+                    right_target: t.cos().signum(),
+                    linear_pos: t * 100.0,
+                    spin_pos: t.sin() * 10.0,
+                    front_distance: Some(100.0),
+                    ..BotState::default()
+                };
+
+                let frame = Frame {
+                    time: now,
+                    message: Message::BotState(state),
+                };
+
+                let mut bytes = [0; telemetry::MAX_FRAME_LEN];
+                if let Ok(len) = telemetry::encode(&frame, &mut bytes) {
+                    pty.write_all(&bytes[..len])?;
+                }
+            }
+
+            last_report = now;
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,220 @@
+//! The commands the firmware understands, for completing them
+//!
+//! The tree of commands is learned from the firmware's own `help` when
+//! the console connects, so it follows the `Subcommand` tables there
+//! instead of a copy of them.
+
+use std::io;
+
+/// A word of a command, and the words that can follow it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub children: Vec<Node>,
+
+    /// Whether any number of the children can follow, like signals
+    pub repeat: bool,
+}
+
+impl Node {
+    pub fn leaf(name: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            children: Vec::new(),
+            repeat: false,
+        }
+    }
+}
+
+/// A line of `help`, split into the subcommand and the arguments it takes
+#[derive(Clone, Debug, PartialEq)]
+pub struct HelpLine {
+    pub name: String,
+    pub args: String,
+}
+
+/**
+ *  Read a line of help for the subcommands of `keyword`, or for the top
+ *  level commands if it is empty
+ *
+ *  The firmware writes each as the keyword, the name and the arguments,
+ *  then a tab and the description. Anything else, like an echo or a
+ *  report line, is None.
+ */
+pub fn parse_help(keyword: &str, line: &str) -> Option<HelpLine> {
+    let mut parts = line.splitn(2, '\t');
+    let usage = parts.next()?;
+    parts.next()?;
+
+    let mut words = usage.split_whitespace();
+
+    if !keyword.is_empty() && words.next()? != keyword {
+        return None;
+    }
+
+    // Lines of a report are numbers, which no command starts with
+    let name = words.next()?;
+    if name.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return None;
+    }
+
+    let name = name.to_string();
+    let args = words.collect::<Vec<_>>().join(" ");
+
+    Some(HelpLine { name, args })
+}
+
+/// The arguments that are a choice of words, like `<left|right>`
+fn choices(args: &str) -> Option<Vec<Node>> {
+    let inner = args
+        .strip_prefix('<')
+        .and_then(|a| a.strip_suffix('>'))
+        .or_else(|| args.strip_prefix('[').and_then(|a| a.strip_suffix(']')))?;
+
+    if inner.contains('|') {
+        Some(inner.split('|').map(Node::leaf).collect())
+    } else {
+        None
+    }
+}
+
+/**
+ *  The commands the firmware has, asking it with `ask`
+ *
+ *  `ask` sends a command and returns the lines that came back. Commands
+ *  taking `...` have their own help asked for in turn, words given as
+ *  arguments are completed as they are, and signals come from the list
+ *  the report gives.
+ */
+pub fn learn<F>(mut ask: F) -> io::Result<Commands>
+where
+    F: FnMut(&str) -> io::Result<Vec<String>>,
+{
+    let signals: Vec<Node> = ask("report list")?
+        .iter()
+        .filter_map(|line| line.strip_prefix("available:"))
+        .flat_map(|names| names.split_whitespace().map(Node::leaf))
+        .collect();
+
+    let nodes = learn_path(&mut ask, &[], &signals)?;
+
+    Ok(Commands { nodes })
+}
+
+fn learn_path<F>(
+    ask: &mut F,
+    path: &[String],
+    signals: &[Node],
+) -> io::Result<Vec<Node>>
+where
+    F: FnMut(&str) -> io::Result<Vec<String>>,
+{
+    let mut command = String::from("help");
+    for word in path {
+        command.push(' ');
+        command.push_str(word);
+    }
+
+    let keyword = path.last().map(String::as_str).unwrap_or("");
+    let mut nodes = Vec::new();
+
+    for line in ask(&command)? {
+        let help = match parse_help(keyword, &line) {
+            Some(help) => help,
+            None => continue,
+        };
+
+        // Help for a command repeated in the answer, or an echo of it
+        if nodes.iter().any(|n: &Node| n.name == help.name) {
+            continue;
+        }
+
+        let children = if help.args == "..." {
+            let mut path = path.to_vec();
+            path.push(help.name.clone());
+            learn_path(ask, &path, signals)?
+        } else if help.args.starts_with("<signal>") {
+            signals.to_vec()
+        } else if let Some(choices) = choices(&help.args) {
+            choices
+        } else if !help.args.is_empty()
+            && !help.args.contains(|c| "<[.".contains(c))
+        {
+            // Fixed words, like `calibrate sensors`
+            help.args
+                .split_whitespace()
+                .rev()
+                .fold(Vec::new(), |rest, w| {
+                    vec![Node {
+                        name: w.to_string(),
+                        children: rest,
+                        repeat: false,
+                    }]
+                })
+        } else {
+            Vec::new()
+        };
+
+        nodes.push(Node {
+            name: help.name,
+            children,
+            repeat: help.args.starts_with("<signal>"),
+        });
+    }
+
+    Ok(nodes)
+}
+
+/// The commands learned from the firmware, for completion
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Commands {
+    pub nodes: Vec<Node>,
+}
+
+impl Commands {
+    /**
+     *  The words that could finish the last word of `line`
+     *
+     *  Earlier words have to match the tree exactly. Words past the end
+     *  of the tree, like values, have nothing to complete. `help` is
+     *  followed by any of the other commands.
+     */
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+
+        // A trailing space means the last word is done, and a new one
+        // started
+        if line.is_empty() || line.ends_with(char::is_whitespace) {
+            words.push("");
+        }
+
+        if words.len() > 1 && words[0] == "help" {
+            words.remove(0);
+        }
+
+        let (partial, path) = match words.split_last() {
+            Some((partial, path)) => (*partial, path),
+            None => return Vec::new(),
+        };
+
+        let mut nodes = &self.nodes;
+        let mut repeat = false;
+
+        for word in path {
+            match nodes.iter().find(|n| n.name == *word) {
+                Some(node) if repeat && node.children.is_empty() => {}
+                Some(node) => {
+                    nodes = &node.children;
+                    repeat = node.repeat;
+                }
+                None => return Vec::new(),
+            }
+        }
+
+        nodes
+            .iter()
+            .map(|n| n.name.clone())
+            .filter(|name| name.starts_with(partial))
+            .collect()
+    }
+}
@@ -0,0 +1,311 @@
+//! A small line editor for the console, with history and completion
+
+use std::io;
+use std::io::Write;
+use std::os::unix::io::RawFd;
+
+use crate::commands::Commands;
+
+/// What the user did with the line
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    /// Nothing that needs the console to do anything
+    None,
+
+    /// Enter was pressed on this line
+    Submit(String),
+
+    /// Tab had more than one way to finish the word
+    Options(Vec<String>),
+
+    /// Ctrl-C or Ctrl-D on an empty line
+    Quit,
+}
+
+enum Escape {
+    None,
+    Started,
+    Csi(Vec<u8>),
+}
+
+/**
+ *  Edits a line a byte of input at a time
+ *
+ *  The input is fed in with `push`, so the console can wait on the mouse
+ *  and the keyboard at once. Only ASCII is kept, since that's all the
+ *  firmware reads.
+ */
+pub struct Editor {
+    prompt: String,
+    line: Vec<u8>,
+    cursor: usize,
+    history: Vec<String>,
+
+    // Where in the history up and down are, and the line from before
+    history_index: Option<usize>,
+    saved_line: Vec<u8>,
+
+    escape: Escape,
+
+    commands: Commands,
+}
+
+impl Editor {
+    /// An editor with nothing to complete, until it is given the commands
+    pub fn new(prompt: &str) -> Editor {
+        Editor {
+            prompt: prompt.to_string(),
+            line: Vec::new(),
+            cursor: 0,
+            history: Vec::new(),
+            history_index: None,
+            saved_line: Vec::new(),
+            escape: Escape::None,
+            commands: Commands::default(),
+        }
+    }
+
+    pub fn set_commands(&mut self, commands: Commands) {
+        self.commands = commands;
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if !line.is_empty()
+            && self.history.last().map(String::as_str) != Some(line)
+        {
+            self.history.push(line.to_string());
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Action {
+        match std::mem::replace(&mut self.escape, Escape::None) {
+            Escape::Started => {
+                if byte == b'[' || byte == b'O' {
+                    self.escape = Escape::Csi(Vec::new());
+                }
+                return Action::None;
+            }
+            Escape::Csi(mut params) => {
+                if byte.is_ascii_digit() || byte == b';' {
+                    params.push(byte);
+                    self.escape = Escape::Csi(params);
+                } else {
+                    self.escape_sequence(&params, byte);
+                }
+                return Action::None;
+            }
+            Escape::None => {}
+        }
+
+        match byte {
+            // Ctrl-C throws the line away, or quits if there isn't one
+            3 if self.line.is_empty() => return Action::Quit,
+            3 => self.set_line(Vec::new()),
+            4 if self.line.is_empty() => return Action::Quit,
+            4 => self.delete(),
+            1 => self.cursor = 0,
+            5 => self.cursor = self.line.len(),
+            2 => self.left(),
+            6 => self.right(),
+            // Ctrl-U and Ctrl-K cut before and after the cursor
+            21 => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            11 => self.line.truncate(self.cursor),
+            16 => self.history_up(),
+            14 => self.history_down(),
+            8 | 127 => self.backspace(),
+            b'\t' => return self.complete(),
+            b'\r' | b'\n' => {
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.add_history(line.trim());
+                self.set_line(Vec::new());
+                self.history_index = None;
+                return Action::Submit(line);
+            }
+            27 => self.escape = Escape::Started,
+            b' '..=b'~' => {
+                self.line.insert(self.cursor, byte);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+
+        Action::None
+    }
+
+    fn escape_sequence(&mut self, params: &[u8], byte: u8) {
+        match (params, byte) {
+            (_, b'A') => self.history_up(),
+            (_, b'B') => self.history_down(),
+            (_, b'C') => self.right(),
+            (_, b'D') => self.left(),
+            (_, b'H') | (b"1", b'~') | (b"7", b'~') => self.cursor = 0,
+            (_, b'F') | (b"4", b'~') | (b"8", b'~') => {
+                self.cursor = self.line.len()
+            }
+            (b"3", b'~') => self.delete(),
+            _ => {}
+        }
+    }
+
+    fn set_line(&mut self, line: Vec<u8>) {
+        self.cursor = line.len();
+        self.line = line;
+    }
+
+    fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        if self.cursor < self.line.len() {
+            self.cursor += 1;
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn history_up(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.saved_line = self.line.clone();
+                self.history.len() - 1
+            }
+        };
+
+        self.history_index = Some(index);
+        self.set_line(self.history[index].as_bytes().to_vec());
+    }
+
+    fn history_down(&mut self) {
+        match self.history_index {
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_index = Some(i + 1);
+                self.set_line(self.history[i + 1].as_bytes().to_vec());
+            }
+            Some(_) => {
+                self.history_index = None;
+                let saved_line = std::mem::take(&mut self.saved_line);
+                self.set_line(saved_line);
+            }
+            None => {}
+        }
+    }
+
+    /// Finish the word before the cursor as far as all the options agree
+    fn complete(&mut self) -> Action {
+        let before = String::from_utf8_lossy(&self.line[..self.cursor]);
+        let partial_len = before
+            .rsplit(char::is_whitespace)
+            .next()
+            .map(str::len)
+            .unwrap_or(0);
+
+        let options = self.commands.complete(&before);
+
+        let common = match options.split_first() {
+            Some((first, rest)) => {
+                rest.iter().fold(first.as_str(), |common, option| {
+                    let len = common
+                        .bytes()
+                        .zip(option.bytes())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    &common[..len]
+                })
+            }
+            None => return Action::None,
+        };
+
+        let mut insert = common.as_bytes()[partial_len..].to_vec();
+        if options.len() == 1 {
+            insert.push(b' ');
+        }
+
+        for (i, &c) in insert.iter().enumerate() {
+            self.line.insert(self.cursor + i, c);
+        }
+        self.cursor += insert.len();
+
+        if options.len() > 1 && common.len() == partial_len {
+            Action::Options(options)
+        } else {
+            Action::None
+        }
+    }
+
+    /// Redraw the prompt and line over whatever is on the current line
+    pub fn render<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "\r\x1b[K{}", self.prompt)?;
+        out.write_all(&self.line)?;
+
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            write!(out, "\x1b[{}D", back)?;
+        }
+
+        out.flush()
+    }
+
+    /// Clear the current line, to print something in place of the prompt
+    pub fn clear<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "\r\x1b[K")
+    }
+}
+
+/**
+ *  Puts a terminal into a mode where each key press can be read as it
+ *  happens, without it being echoed, until dropped
+ */
+pub struct RawTerminal {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    pub fn new(fd: RawFd) -> io::Result<RawTerminal> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(RawTerminal { fd, original })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
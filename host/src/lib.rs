@@ -0,0 +1,7 @@
+//! Tools that run on a computer connected to the mouse over its uart
+
+pub mod commands;
+pub mod editor;
+pub mod plot;
+pub mod record;
+pub mod serial;
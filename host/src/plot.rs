@@ -0,0 +1,319 @@
+//! Line plots rendered to SVG
+
+use std::fmt::Write;
+
+const WIDTH: f64 = 900.0;
+const HEIGHT: f64 = 450.0;
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_RIGHT: f64 = 70.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 50.0;
+
+const COLORS: [&str; 6] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b",
+];
+
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+
+    /// Plot against the axis on the right instead of the left
+    pub right_axis: bool,
+}
+
+impl Series {
+    /// Pair up x and y values, dropping any that are NaN
+    pub fn new(name: &str, xs: &[f64], ys: &[f64]) -> Series {
+        Series {
+            name: name.to_string(),
+            points: xs
+                .iter()
+                .zip(ys)
+                .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+                .map(|(&x, &y)| (x, y))
+                .collect(),
+            right_axis: false,
+        }
+    }
+
+    pub fn on_right_axis(mut self) -> Series {
+        self.right_axis = true;
+        self
+    }
+}
+
+pub struct Plot {
+    pub title: String,
+    pub x_label: String,
+    pub series: Vec<Series>,
+}
+
+#[derive(Copy, Clone)]
+struct Range {
+    min: f64,
+    max: f64,
+}
+
+impl Range {
+    fn of<'a, I: Iterator<Item = &'a f64>>(values: I) -> Option<Range> {
+        let mut range: Option<Range> = None;
+
+        for &v in values {
+            range = Some(match range {
+                Some(r) => Range {
+                    min: r.min.min(v),
+                    max: r.max.max(v),
+                },
+                None => Range { min: v, max: v },
+            });
+        }
+
+        range.map(|r| {
+            if r.max > r.min {
+                r
+            } else {
+                Range {
+                    min: r.min - 1.0,
+                    max: r.max + 1.0,
+                }
+            }
+        })
+    }
+
+    fn scale(self, v: f64, from: f64, to: f64) -> f64 {
+        from + (v - self.min) / (self.max - self.min) * (to - from)
+    }
+
+    /// Tick values on round numbers, about `count` of them
+    fn ticks(self, count: usize) -> Vec<f64> {
+        let rough = (self.max - self.min) / count as f64;
+        let magnitude = 10f64.powf(rough.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .iter()
+            .map(|m| m * magnitude)
+            .find(|&s| s >= rough)
+            .unwrap_or(10.0 * magnitude);
+
+        let mut ticks = Vec::new();
+        let mut tick = (self.min / step).ceil() * step;
+        while tick <= self.max + step * 1e-9 {
+            ticks.push(tick);
+            tick += step;
+        }
+        ticks
+    }
+}
+
+impl Plot {
+    pub fn new(title: &str, x_label: &str) -> Plot {
+        Plot {
+            title: title.to_string(),
+            x_label: x_label.to_string(),
+            series: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, series: Series) {
+        self.series.push(series);
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" \
+             height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">",
+            WIDTH, HEIGHT
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>",
+            WIDTH, HEIGHT
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"24\" text-anchor=\"middle\" \
+             font-size=\"16\">{}</text>",
+            WIDTH / 2.0,
+            escape(&self.title)
+        )
+        .unwrap();
+
+        let left = MARGIN_LEFT;
+        let right = WIDTH - MARGIN_RIGHT;
+        let top = MARGIN_TOP;
+        let bottom = HEIGHT - MARGIN_BOTTOM;
+
+        let points = |right_axis: bool| {
+            self.series
+                .iter()
+                .filter(move |s| s.right_axis == right_axis)
+                .flat_map(|s| s.points.iter())
+        };
+
+        let x_range = Range::of(
+            self.series
+                .iter()
+                .flat_map(|s| s.points.iter().map(|(x, _)| x)),
+        );
+        let left_range = Range::of(points(false).map(|(_, y)| y));
+        let right_range = Range::of(points(true).map(|(_, y)| y));
+
+        let x_range = match x_range {
+            Some(r) => r,
+            None => {
+                writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">\
+                     no data</text>",
+                    WIDTH / 2.0,
+                    HEIGHT / 2.0
+                )
+                .unwrap();
+                svg.push_str("</svg>\n");
+                return svg;
+            }
+        };
+
+        writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+             fill=\"none\" stroke=\"black\"/>",
+            left,
+            top,
+            right - left,
+            bottom - top
+        )
+        .unwrap();
+
+        for tick in x_range.ticks(10) {
+            let x = x_range.scale(tick, left, right);
+            writeln!(
+                svg,
+                "<line x1=\"{x:.1}\" y1=\"{}\" x2=\"{x:.1}\" y2=\"{}\" \
+                 stroke=\"#ddd\"/>\
+                 <text x=\"{x:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                top,
+                bottom,
+                bottom + 16.0,
+                format_tick(tick),
+                x = x
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            (left + right) / 2.0,
+            HEIGHT - 12.0,
+            escape(&self.x_label)
+        )
+        .unwrap();
+
+        if let Some(range) = left_range {
+            for tick in range.ticks(8) {
+                let y = range.scale(tick, bottom, top);
+                writeln!(
+                    svg,
+                    "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" \
+                     stroke=\"#ddd\"/>\
+                     <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                    left,
+                    right,
+                    left - 6.0,
+                    y + 4.0,
+                    format_tick(tick),
+                    y = y
+                )
+                .unwrap();
+            }
+        }
+
+        if let Some(range) = right_range {
+            for tick in range.ticks(8) {
+                let y = range.scale(tick, bottom, top);
+                writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{:.1}\">{}</text>",
+                    right + 6.0,
+                    y + 4.0,
+                    format_tick(tick)
+                )
+                .unwrap();
+            }
+        }
+
+        for (i, series) in self.series.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+
+            let y_range = if series.right_axis {
+                right_range
+            } else {
+                left_range
+            };
+
+            if let Some(y_range) = y_range {
+                let path: Vec<String> = series
+                    .points
+                    .iter()
+                    .map(|&(x, y)| {
+                        format!(
+                            "{:.1},{:.1}",
+                            x_range.scale(x, left, right),
+                            y_range.scale(y, bottom, top)
+                        )
+                    })
+                    .collect();
+
+                writeln!(
+                    svg,
+                    "<polyline fill=\"none\" stroke=\"{}\" \
+                     stroke-width=\"1.5\" points=\"{}\"/>",
+                    color,
+                    path.join(" ")
+                )
+                .unwrap();
+            }
+
+            let legend_y = top + 16.0 + i as f64 * 16.0;
+            let axis = if series.right_axis { " (right)" } else { "" };
+            writeln!(
+                svg,
+                "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" \
+                 stroke=\"{}\" stroke-width=\"2\"/>\
+                 <text x=\"{}\" y=\"{:.1}\">{}{}</text>",
+                left + 10.0,
+                left + 30.0,
+                color,
+                left + 36.0,
+                legend_y + 4.0,
+                escape(&series.name),
+                axis,
+                y = legend_y
+            )
+            .unwrap();
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn format_tick(v: f64) -> String {
+    if v == v.trunc() && v.abs() < 1e9 {
+        format!("{}", v as i64)
+    } else {
+        let s = format!("{:.3}", v);
+        s.trim_end_matches('0').to_string()
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
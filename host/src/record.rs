@@ -0,0 +1,117 @@
+//! Recording telemetry to CSV files, and reading them back
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use telemetry::BotState;
+
+/// The columns of a recorded `BotState`, `host_ms` being when it arrived
+pub const BOT_STATE_COLUMNS: [&str; 15] = [
+    "host_ms",
+    "time",
+    "left_pos",
+    "right_pos",
+    "left_velocity",
+    "right_velocity",
+    "left_power",
+    "right_power",
+    "left_target",
+    "right_target",
+    "linear_pos",
+    "spin_pos",
+    "front_distance",
+    "left_distance",
+    "right_distance",
+];
+
+pub fn write_bot_state_header<W: Write>(w: &mut W) -> io::Result<()> {
+    writeln!(w, "{}", BOT_STATE_COLUMNS.join(","))
+}
+
+/// Missing distances are left empty
+pub fn write_bot_state<W: Write>(
+    w: &mut W,
+    host_ms: u128,
+    time: u32,
+    state: &BotState,
+) -> io::Result<()> {
+    let distance =
+        |d: Option<f32>| d.map(|d| d.to_string()).unwrap_or_default();
+
+    writeln!(
+        w,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        host_ms,
+        time,
+        state.left_pos,
+        state.right_pos,
+        state.left_velocity,
+        state.right_velocity,
+        state.left_power,
+        state.right_power,
+        state.left_target,
+        state.right_target,
+        state.linear_pos,
+        state.spin_pos,
+        distance(state.front_distance),
+        distance(state.left_distance),
+        distance(state.right_distance),
+    )
+}
+
+/// A line of the text report, which is tab separated
+pub fn write_report_line<W: Write>(
+    w: &mut W,
+    host_ms: u128,
+    line: &str,
+) -> io::Result<()> {
+    let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+    writeln!(w, "{},{}", host_ms, fields.join(","))
+}
+
+/// A CSV file of numbers with a header row
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<f64>>,
+}
+
+impl Table {
+    /// Anything that is not a number, such as an empty field, reads as NaN
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Table> {
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines();
+
+        let columns = lines
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "missing header")
+            })?
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .collect();
+
+        let rows = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split(',')
+                    .map(|v| v.trim().parse().unwrap_or(f64::NAN))
+                    .collect()
+            })
+            .collect();
+
+        Ok(Table { columns, rows })
+    }
+
+    pub fn column(&self, name: &str) -> Option<Vec<f64>> {
+        let index = self.columns.iter().position(|c| c == name)?;
+
+        Some(
+            self.rows
+                .iter()
+                .map(|row| row.get(index).cloned().unwrap_or(f64::NAN))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::io::RawFd;
+
+/// The baud rate the firmware sets up, fast enough for its telemetry
+pub const DEFAULT_BAUD: u32 = 115_200;
+
+/**
+ *  A serial port, or a pseudo-terminal standing in for one
+ *
+ *  Reads give up after a tenth of a second with no data, returning 0
+ *  bytes, so that callers can do other things while waiting.
+ */
+pub struct Serial {
+    file: File,
+}
+
+impl Serial {
+    pub fn open(path: &str, baud: u32) -> io::Result<Serial> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+
+        let speed = match baud {
+            9600 => libc::B9600,
+            19200 => libc::B19200,
+            38400 => libc::B38400,
+            57600 => libc::B57600,
+            115_200 => libc::B115200,
+            230_400 => libc::B230400,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "unsupported baud rate",
+                ))
+            }
+        };
+
+        let fd = file.as_raw_fd();
+
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            libc::cfmakeraw(&mut termios);
+            libc::cfsetispeed(&mut termios, speed);
+            libc::cfsetospeed(&mut termios, speed);
+            termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 1;
+
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(Serial { file })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Serial> {
+        Ok(Serial {
+            file: self.file.try_clone()?,
+        })
+    }
+}
+
+impl Read for Serial {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for Serial {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl AsRawFd for Serial {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
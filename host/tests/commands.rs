@@ -0,0 +1,111 @@
+use std::io;
+
+use host::commands;
+use host::commands::Commands;
+use host::commands::HelpLine;
+
+/// Answer the way the firmware does, for a few of its commands
+fn ask(command: &str) -> io::Result<Vec<String>> {
+    let answer: &[&str] = match command {
+        "help" => &[
+            "plan ...\tcommands for the moves through the maze",
+            "report ...\tchoose what the report prints",
+            "bot calibrate sensors\tthe same as sensors calibrate",
+            "help [command...]\tshow the subcommands of a command",
+            "@\tstart the report",
+        ],
+        "help plan" => &[
+            "plan smooth [on|off]\tturn smooth moves on or off",
+            "plan nav [cells]\tshow the maze",
+            "plan go\tstart the run",
+        ],
+        "help report" => &[
+            "report add <signal>...\tadd signals to the report",
+            "report rate [ms]\tset or show the time between reports",
+        ],
+        "report list" => &["report: time", "available: time x_pos y_pos"],
+        _ => &[],
+    };
+
+    let mut lines = vec![format!(">> {}", command)];
+    lines.extend(answer.iter().map(|line| line.to_string()));
+    Ok(lines)
+}
+
+fn learned() -> Commands {
+    commands::learn(ask).unwrap()
+}
+
+#[test]
+fn help_lines_are_parsed() {
+    assert_eq!(
+        commands::parse_help("plan", "plan smooth [on|off]\tturn smooth"),
+        Some(HelpLine {
+            name: "smooth".to_string(),
+            args: "[on|off]".to_string(),
+        })
+    );
+
+    assert_eq!(
+        commands::parse_help("", "help [command...]\tshow help"),
+        Some(HelpLine {
+            name: "help".to_string(),
+            args: "[command...]".to_string(),
+        })
+    );
+}
+
+#[test]
+fn other_lines_are_not_help() {
+    assert_eq!(commands::parse_help("plan", ">> help plan"), None);
+    assert_eq!(commands::parse_help("plan", "report add x\tadd"), None);
+    assert_eq!(commands::parse_help("", "1200\t0.50\t-3"), None);
+    assert_eq!(commands::parse_help("", "no tab here"), None);
+}
+
+#[test]
+fn top_level_commands_are_learned() {
+    assert_eq!(
+        learned().complete(""),
+        vec!["plan", "report", "bot", "help", "@"]
+    );
+}
+
+#[test]
+fn nested_commands_are_asked_for() {
+    let commands = learned();
+
+    assert_eq!(commands.complete("plan "), vec!["smooth", "nav", "go"]);
+    assert_eq!(commands.complete("help plan g"), vec!["go"]);
+}
+
+#[test]
+fn choices_and_fixed_words_complete() {
+    let commands = learned();
+
+    assert_eq!(commands.complete("plan smooth o"), vec!["on", "off"]);
+    assert_eq!(commands.complete("bot "), vec!["calibrate"]);
+    assert_eq!(commands.complete("bot calibrate "), vec!["sensors"]);
+
+    // Values have nothing to complete
+    assert!(commands.complete("plan nav ").is_empty());
+    assert!(commands.complete("report rate ").is_empty());
+}
+
+#[test]
+fn signals_come_from_the_report() {
+    let commands = learned();
+
+    assert_eq!(
+        commands.complete("report add "),
+        vec!["time", "x_pos", "y_pos"]
+    );
+    assert_eq!(commands.complete("report add time x"), vec!["x_pos"]);
+}
+
+#[test]
+fn nothing_learned_completes_nothing() {
+    let commands = commands::learn(|_| Ok(Vec::new())).unwrap();
+
+    assert!(commands.complete("pl").is_empty());
+}
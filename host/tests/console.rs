@@ -0,0 +1,98 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+
+/// The stand in, killed again when dropped
+struct StandIn {
+    child: Child,
+    path: String,
+}
+
+impl StandIn {
+    fn start() -> StandIn {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_stand_in"))
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut path = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut path)
+            .unwrap();
+
+        StandIn {
+            child,
+            path: path.trim().to_string(),
+        }
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// Run the console on the stand in with `input` typed, returning what it
+/// printed
+fn run_console(input: &[u8]) -> String {
+    let stand_in = StandIn::start();
+
+    let mut console = Command::new(env!("CARGO_BIN_EXE_console"))
+        .arg(&stand_in.path)
+        .arg("--no-color")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Closing stdin lets the console finish once the answers are in
+    console.stdin.take().unwrap().write_all(input).unwrap();
+
+    let mut output = String::new();
+    console
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+
+    assert!(console.wait().unwrap().success());
+    output
+}
+
+#[test]
+fn commands_are_sent_and_answered() {
+    let output = run_console(b"help plan control\n");
+
+    assert!(output.contains(">> help plan control"));
+    assert!(output.contains("control turn <left|right|around>"));
+}
+
+#[test]
+fn tab_completes_commands_learned_from_help() {
+    // Each word only has one way to finish, so tab fills it in
+    let output = run_console(b"pl\tcon\tb\tconf\tlinear_m\t\n");
+
+    assert!(output.contains(">> plan control bot config linear_max_velocity"));
+}
+
+#[test]
+fn tab_completes_choices_and_signals() {
+    let output = run_console(b"plan mode sp\t\nreport add front\t\n");
+
+    assert!(output.contains(">> plan mode speed"));
+    assert!(output.contains(">> report add front_distance"));
+}
+
+#[test]
+fn tab_lists_the_options() {
+    let output = run_console(b"plan s\t\n");
+
+    assert!(output.contains("smooth  stop"));
+}
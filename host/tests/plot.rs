@@ -0,0 +1,66 @@
+use host::plot::Plot;
+use host::plot::Series;
+
+#[test]
+fn series_drops_nan_points() {
+    let xs = [0.0, 1.0, f64::NAN, 3.0];
+    let ys = [5.0, f64::NAN, 7.0, 8.0];
+
+    let series = Series::new("speed", &xs, &ys);
+
+    assert_eq!(series.points, vec![(0.0, 5.0), (3.0, 8.0)]);
+    assert!(!series.right_axis);
+    assert!(series.on_right_axis().right_axis);
+}
+
+#[test]
+fn empty_plot_says_so() {
+    let svg = Plot::new("nothing", "time").to_svg();
+
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert!(svg.contains("no data"));
+    assert!(!svg.contains("<polyline"));
+}
+
+#[test]
+fn each_series_gets_a_line_and_a_legend() {
+    let xs = [0.0, 10.0, 20.0];
+
+    let mut plot = Plot::new("left & right", "time (ms)");
+    plot.add(Series::new("left", &xs, &[0.0, 1.0, 2.0]));
+    plot.add(Series::new("power", &xs, &[0.0, 500.0, 1000.0]).on_right_axis());
+
+    let svg = plot.to_svg();
+
+    assert_eq!(svg.matches("<svg").count(), 1);
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert_eq!(svg.matches("<polyline").count(), 2);
+    assert!(svg.contains(">left</text>"));
+    assert!(svg.contains(">power (right)</text>"));
+    assert!(svg.contains(">time (ms)</text>"));
+
+    // Text is escaped for the XML
+    assert!(svg.contains("left &amp; right"));
+    assert!(!svg.contains("left & right"));
+}
+
+#[test]
+fn points_stay_inside_the_plot() {
+    let xs = [-5.0, 0.0, 5.0];
+
+    let mut plot = Plot::new("bounds", "x");
+    plot.add(Series::new("y", &xs, &[-100.0, 0.0, 100.0]));
+
+    let svg = plot.to_svg();
+    let start = svg.find("points=\"").unwrap() + "points=\"".len();
+    let end = start + svg[start..].find('"').unwrap();
+
+    for point in svg[start..end].split_whitespace() {
+        let mut xy = point.split(',').map(|v| v.parse::<f64>().unwrap());
+        let (x, y) = (xy.next().unwrap(), xy.next().unwrap());
+
+        assert!((0.0..=900.0).contains(&x), "x out of range: {}", x);
+        assert!((0.0..=450.0).contains(&y), "y out of range: {}", y);
+    }
+}
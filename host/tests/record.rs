@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::PathBuf;
+
+use host::record;
+use host::record::Table;
+use telemetry::BotState;
+
+fn bot_state() -> BotState {
+    BotState {
+        left_pos: 1620.0,
+        right_pos: 1618.5,
+        left_velocity: 1.25,
+        right_velocity: -0.5,
+        left_power: 3000.0,
+        right_power: -1200.0,
+        left_target: 1.0,
+        right_target: 1.0,
+        linear_pos: 1619.25,
+        spin_pos: 0.75,
+        front_distance: Some(35.0),
+        left_distance: None,
+        right_distance: Some(53.0),
+    }
+}
+
+/// A file in the temp directory, removed again when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, contents: &[u8]) -> TempFile {
+        let path = std::env::temp_dir().join(format!(
+            "host-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, contents).unwrap();
+        TempFile(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.0).ok();
+    }
+}
+
+#[test]
+fn bot_state_header_has_every_column() {
+    let mut csv = Vec::new();
+    record::write_bot_state_header(&mut csv).unwrap();
+
+    let header = String::from_utf8(csv).unwrap();
+    assert_eq!(header.trim_end(), record::BOT_STATE_COLUMNS.join(","));
+}
+
+#[test]
+fn bot_state_row_matches_header() {
+    let mut csv = Vec::new();
+    record::write_bot_state(&mut csv, 42, 123_456, &bot_state()).unwrap();
+
+    let row = String::from_utf8(csv).unwrap();
+    let fields: Vec<&str> = row.trim_end().split(',').collect();
+
+    assert_eq!(fields.len(), record::BOT_STATE_COLUMNS.len());
+    assert_eq!(fields[0], "42");
+    assert_eq!(fields[1], "123456");
+    assert_eq!(fields[6], "3000");
+    assert_eq!(fields[12], "35");
+
+    // A missing distance is left empty
+    assert_eq!(fields[13], "");
+}
+
+#[test]
+fn report_line_is_split_on_tabs() {
+    let mut csv = Vec::new();
+    record::write_report_line(&mut csv, 7, "1.5\t -2 \t3").unwrap();
+
+    assert_eq!(String::from_utf8(csv).unwrap(), "7,1.5,-2,3\n");
+}
+
+#[test]
+fn table_reads_back_what_was_recorded() {
+    let mut csv = Vec::new();
+    record::write_bot_state_header(&mut csv).unwrap();
+    record::write_bot_state(&mut csv, 10, 1000, &bot_state()).unwrap();
+    record::write_bot_state(&mut csv, 20, 2000, &bot_state()).unwrap();
+
+    let file = TempFile::new("round-trip.csv", &csv);
+    let table = Table::read(&file.0).unwrap();
+
+    assert_eq!(table.columns, record::BOT_STATE_COLUMNS.to_vec());
+    assert_eq!(table.rows.len(), 2);
+    assert_eq!(table.column("time"), Some(vec![1000.0, 2000.0]));
+    assert_eq!(table.column("spin_pos"), Some(vec![0.75, 0.75]));
+
+    let left_distance = table.column("left_distance").unwrap();
+    assert!(left_distance.iter().all(|d| d.is_nan()));
+
+    assert_eq!(table.column("nothing"), None);
+}
+
+#[test]
+fn table_without_header_is_an_error() {
+    let file = TempFile::new("empty.csv", b"");
+
+    assert!(Table::read(&file.0).is_err());
+}
+
+#[test]
+fn short_rows_read_as_nan() {
+    let file = TempFile::new("short.csv", b"a,b\n1\n\n2,3\n");
+    let table = Table::read(&file.0).unwrap();
+
+    assert_eq!(table.rows.len(), 2);
+    assert_eq!(table.column("a"), Some(vec![1.0, 2.0]));
+
+    let b = table.column("b").unwrap();
+    assert!(b[0].is_nan());
+    assert_eq!(b[1], 3.0);
+}
@@ -149,6 +149,10 @@ fn main() {
     //let nav = TwelvePartitionNavigate::new();
     let mut mouse = Mouse::new(nav, maze);
 
+    if args.next().as_ref().map(String::as_str) == Some("smooth") {
+        mouse.set_smooth(true);
+    }
+
     let start_time = std::time::Instant::now();
 
     /*
@@ -16,6 +16,7 @@ pub const LENGTH: f64 = CELL_SIZE * 0.5;
 
 const LINEAR_SPEED: f64 = 4.0 * CELL_SIZE;
 const TURN_SPEED: f64 = 8.0 * 90.0;
+const CURVE_RADIUS: f64 = CELL_SIZE / 2.0;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Direction {
@@ -53,6 +54,16 @@ impl Direction {
         }
     }
 
+    /// The unit vector pointing in this direction
+    pub fn vector(&self) -> (f64, f64) {
+        match self {
+            Direction::North => (0.0, 1.0),
+            Direction::South => (0.0, -1.0),
+            Direction::East => (1.0, 0.0),
+            Direction::West => (-1.0, 0.0),
+        }
+    }
+
     pub fn rotation(&self) -> f64 {
         match self {
             Direction::North => 0.0,
@@ -67,6 +78,7 @@ impl Direction {
 enum MouseState {
     MoveLinear(f64, f64),
     MoveTurn(f64, f64),
+    MoveCurve(f64, f64),
     Decision,
 }
 
@@ -79,6 +91,8 @@ pub struct Mouse<C: Visualize + Copy> {
     direction: Direction,
     state: MouseState,
     paused: bool,
+    smooth: bool,
+    at_edge: bool,
     moves: Vec<Move>,
     maze: Maze<C>,
     nav: Box<dyn Navigate<Cell = C>>,
@@ -101,6 +115,8 @@ impl<C: Visualize + Copy> Mouse<C> {
             direction: Direction::North,
             state: MouseState::Decision,
             paused: true,
+            smooth: false,
+            at_edge: false,
             moves: Vec::new(),
             maze,
             nav,
@@ -115,10 +131,23 @@ impl<C: Visualize + Copy> Mouse<C> {
         self.paused = true;
     }
 
+    /// Drive between the edges of cells instead of their centers, so
+    /// that turns followed by a forward move can be done as a curve
+    pub fn set_smooth(&mut self, smooth: bool) {
+        self.smooth = smooth;
+    }
+
     pub fn world_location(&self) -> (f64, f64, f64) {
+        let (edge_x, edge_y) = if self.at_edge {
+            let (dx, dy) = self.direction.vector();
+            (-dx * CELL_SIZE / 2.0, -dy * CELL_SIZE / 2.0)
+        } else {
+            (0.0, 0.0)
+        };
+
         (
-            self.cell_x as f64 * CELL_SIZE + self.local_x,
-            self.cell_y as f64 * CELL_SIZE + self.local_y,
+            self.cell_x as f64 * CELL_SIZE + edge_x + self.local_x,
+            self.cell_y as f64 * CELL_SIZE + edge_y + self.local_y,
             self.direction.rotation() + self.local_direction,
         )
     }
@@ -131,16 +160,40 @@ impl<C: Visualize + Copy> Mouse<C> {
         &self.maze
     }
 
+    fn start_move(&mut self, next_move: Move) -> MouseState {
+        let curve = self.at_edge && self.moves.last() == Some(&Move::Forward);
+
+        match next_move {
+            Move::TurnLeft if curve => {
+                self.moves.pop();
+                MouseState::MoveCurve(-90.0, 0.0)
+            }
+            Move::TurnRight if curve => {
+                self.moves.pop();
+                MouseState::MoveCurve(90.0, 0.0)
+            }
+            Move::TurnLeft | Move::TurnRight | Move::TurnAround
+                if self.at_edge =>
+            {
+                // Pull into the center of the cell to turn in place
+                self.moves.push(next_move);
+                MouseState::MoveLinear(CELL_SIZE / 2.0, 0.0)
+            }
+            Move::Forward if self.smooth && !self.at_edge => {
+                MouseState::MoveLinear(CELL_SIZE / 2.0, 0.0)
+            }
+            Move::Forward => MouseState::MoveLinear(CELL_SIZE, 0.0),
+            Move::TurnLeft => MouseState::MoveTurn(-90.0, 0.0),
+            Move::TurnRight => MouseState::MoveTurn(90.0, 0.0),
+            Move::TurnAround => MouseState::MoveTurn(180.0, 0.0),
+        }
+    }
+
     pub fn run(&mut self, dt: f64) {
         match self.state {
             MouseState::Decision => {
                 self.state = if let Some(next_move) = self.moves.pop() {
-                    match next_move {
-                        Move::Forward => MouseState::MoveLinear(CELL_SIZE, 0.0),
-                        Move::TurnLeft => MouseState::MoveTurn(-90.0, 0.0),
-                        Move::TurnRight => MouseState::MoveTurn(90.0, 0.0),
-                        Move::TurnAround => MouseState::MoveTurn(180.0, 0.0),
-                    }
+                    self.start_move(next_move)
                 } else {
                     let (_, north_edge, south_edge, east_edge, west_edge) =
                         self.maze.get(self.cell_x, self.cell_y);
@@ -193,16 +246,7 @@ impl<C: Visualize + Copy> Mouse<C> {
                     }
 
                     if let Some(next_move) = self.moves.pop() {
-                        match next_move {
-                            Move::Forward => {
-                                MouseState::MoveLinear(CELL_SIZE, 0.0)
-                            }
-                            Move::TurnLeft => MouseState::MoveTurn(-90.0, 0.0),
-                            Move::TurnRight => MouseState::MoveTurn(90.0, 0.0),
-                            Move::TurnAround => {
-                                MouseState::MoveTurn(180.0, 0.0)
-                            }
-                        }
+                        self.start_move(next_move)
                     } else {
                         MouseState::Decision
                     }
@@ -214,8 +258,14 @@ impl<C: Visualize + Copy> Mouse<C> {
                     + LINEAR_SPEED * dt * if target > 0.0 { 1.0 } else { -1.0 };
 
                 if new_value.abs() > target.abs() {
-                    let cells_moved =
-                        (target / CELL_SIZE).abs().round() as usize;
+                    // Half cell moves go between the center and edge of a
+                    // cell. Moving out to the edge enters the next cell.
+                    let cells_moved = if target.abs() < CELL_SIZE {
+                        self.at_edge = !self.at_edge;
+                        if self.at_edge { 1 } else { 0 }
+                    } else {
+                        (target / CELL_SIZE).abs().round() as usize
+                    };
                     match self.direction {
                         Direction::North => self.cell_y += cells_moved,
                         Direction::South => self.cell_y -= cells_moved,
@@ -258,6 +308,46 @@ impl<C: Visualize + Copy> Mouse<C> {
                     self.state = MouseState::MoveTurn(target, new_value);
                 }
             }
+
+            MouseState::MoveCurve(target, value) => {
+                let new_value = value
+                    + TURN_SPEED * dt * if target > 0.0 { 1.0 } else { -1.0 };
+
+                if new_value.abs() > target.abs() {
+                    if target > 0.0 {
+                        self.direction.turn_right()
+                    } else {
+                        self.direction.turn_left()
+                    };
+
+                    match self.direction {
+                        Direction::North => self.cell_y += 1,
+                        Direction::South => self.cell_y -= 1,
+                        Direction::East => self.cell_x += 1,
+                        Direction::West => self.cell_x -= 1,
+                    }
+
+                    self.local_x = 0.0;
+                    self.local_y = 0.0;
+                    self.local_direction = 0.0;
+                    self.state = MouseState::Decision;
+                } else {
+                    // Follow a quarter circle around the corner of the
+                    // cell, starting from the edge the mouse entered on
+                    let (fx, fy) = self.direction.vector();
+                    let (sx, sy) =
+                        if target > 0.0 { (fy, -fx) } else { (-fy, fx) };
+
+                    let angle = new_value.abs().to_radians();
+                    let forward = CURVE_RADIUS * angle.sin();
+                    let side = CURVE_RADIUS * (1.0 - angle.cos());
+
+                    self.local_x = fx * forward + sx * side;
+                    self.local_y = fy * forward + sy * side;
+                    self.local_direction = new_value;
+                    self.state = MouseState::MoveCurve(target, new_value);
+                }
+            }
         }
     }
 }
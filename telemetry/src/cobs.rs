@@ -0,0 +1,80 @@
+//! Consistent overhead byte stuffing
+//!
+//! Encoded data has no zero bytes, so zeros can be used to mark where
+//! frames start and end.
+
+/// The most an encoded buffer can be longer than the data in it
+pub const fn max_overhead(len: usize) -> usize {
+    len / 254 + 1
+}
+
+/// Encode `input` into `output`, returning the encoded length, or None if
+/// `output` is too small
+pub fn encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut code_index = 0;
+    let mut out_index = 1;
+    let mut code: u8 = 1;
+
+    for &byte in input {
+        if byte == 0 {
+            *output.get_mut(code_index)? = code;
+            code_index = out_index;
+            out_index += 1;
+            code = 1;
+        } else {
+            *output.get_mut(out_index)? = byte;
+            out_index += 1;
+            code += 1;
+
+            if code == 0xff {
+                *output.get_mut(code_index)? = code;
+                code_index = out_index;
+                out_index += 1;
+                code = 1;
+            }
+        }
+    }
+
+    *output.get_mut(code_index)? = code;
+
+    Some(out_index)
+}
+
+/// Decode `input` into `output`, returning the decoded length, or None if
+/// `input` is not valid or `output` is too small
+pub fn decode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut in_index = 0;
+    let mut out_index = 0;
+
+    while in_index < input.len() {
+        let code = input[in_index];
+        if code == 0 {
+            return None;
+        }
+
+        let end = in_index + code as usize;
+        if end > input.len() {
+            return None;
+        }
+
+        for &byte in &input[in_index + 1..end] {
+            if byte == 0 {
+                return None;
+            }
+
+            *output.get_mut(out_index)? = byte;
+            out_index += 1;
+        }
+
+        in_index = end;
+
+        // A zero is implied between blocks, but not after a full block or
+        // at the end
+        if code != 0xff && in_index < input.len() {
+            *output.get_mut(out_index)? = 0;
+            out_index += 1;
+        }
+    }
+
+    Some(out_index)
+}
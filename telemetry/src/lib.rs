@@ -0,0 +1,188 @@
+//! Binary telemetry sent by the micromouse over its uart
+//!
+//! Each frame is a message id, a timestamp in ms, the message payload and
+//! a CRC-16 of all of those, COBS encoded and with a zero byte on either
+//! side. The text command shell never sends zero bytes, so the two can
+//! share the uart, and `Decoder` splits them back apart.
+
+#![no_std]
+
+pub mod cobs;
+pub mod crc;
+pub mod message;
+
+pub use crate::message::BotState;
+pub use crate::message::Message;
+
+/// The id, timestamp and CRC around each payload
+const HEADER_LEN: usize = 5;
+const CRC_LEN: usize = 2;
+
+/// The longest frame before COBS encoding
+pub const MAX_MESSAGE_LEN: usize =
+    HEADER_LEN + message::MAX_PAYLOAD_LEN + CRC_LEN;
+
+/// The longest COBS encoded frame, without the delimiters
+pub const MAX_ENCODED_LEN: usize =
+    MAX_MESSAGE_LEN + cobs::max_overhead(MAX_MESSAGE_LEN);
+
+/// The most bytes `encode` can write
+pub const MAX_FRAME_LEN: usize = MAX_ENCODED_LEN + 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The output buffer is too small for the frame
+    BufferTooSmall,
+
+    /// The frame is not valid COBS
+    Cobs,
+
+    /// The frame is too short, too long, or the wrong length for its id
+    Length,
+
+    /// The CRC does not match, so the frame was corrupted
+    Crc,
+
+    /// The message id is not known
+    UnknownMessage(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// When the message was sent, in ms since the mouse started
+    pub time: u32,
+    pub message: Message,
+}
+
+/**
+ *  Encode a frame into `output`, delimiters included, returning the
+ *  number of bytes to send
+ */
+pub fn encode(frame: &Frame, output: &mut [u8]) -> Result<usize, Error> {
+    let mut raw = [0; MAX_MESSAGE_LEN];
+
+    raw[0] = frame.message.id();
+    raw[1..HEADER_LEN].copy_from_slice(&frame.time.to_le_bytes());
+
+    let payload_len = frame
+        .message
+        .write(&mut raw[HEADER_LEN..MAX_MESSAGE_LEN - CRC_LEN])?;
+
+    let crc_start = HEADER_LEN + payload_len;
+    let crc = crc::crc16(&raw[..crc_start]);
+    raw[crc_start..crc_start + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+    let raw = &raw[..crc_start + CRC_LEN];
+
+    if output.len() < 2 {
+        return Err(Error::BufferTooSmall);
+    }
+
+    let end = output.len() - 1;
+    let len =
+        cobs::encode(raw, &mut output[1..end]).ok_or(Error::BufferTooSmall)?;
+
+    output[0] = 0;
+    output[len + 1] = 0;
+
+    Ok(len + 2)
+}
+
+/// Decode a COBS encoded frame, without its delimiters
+pub fn decode(input: &[u8]) -> Result<Frame, Error> {
+    let mut raw = [0; MAX_MESSAGE_LEN];
+
+    let len = cobs::decode(input, &mut raw).ok_or(Error::Cobs)?;
+
+    if len < HEADER_LEN + CRC_LEN {
+        return Err(Error::Length);
+    }
+
+    let crc_start = len - CRC_LEN;
+    let crc = u16::from_le_bytes([raw[crc_start], raw[crc_start + 1]]);
+
+    if crc != crc::crc16(&raw[..crc_start]) {
+        return Err(Error::Crc);
+    }
+
+    let time = u32::from_le_bytes([raw[1], raw[2], raw[3], raw[4]]);
+    let message = Message::read(raw[0], &raw[HEADER_LEN..crc_start])?;
+
+    Ok(Frame { time, message })
+}
+
+/// Something received from the mouse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// A byte of the text shell output
+    Text(u8),
+
+    /// A binary frame, or why it could not be decoded
+    Frame(Result<Frame, Error>),
+}
+
+/**
+ *  Splits the bytes from the mouse into text and frames
+ *
+ *  A zero starts a frame and the next zero ends it. If a frame can't be
+ *  decoded, its closing zero is taken as the start of the next frame
+ *  instead, so that the decoder gets back in step after losing a byte.
+ */
+pub struct Decoder {
+    buf: [u8; MAX_ENCODED_LEN],
+    len: usize,
+    in_frame: bool,
+    overflowed: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder {
+            buf: [0; MAX_ENCODED_LEN],
+            len: 0,
+            in_frame: false,
+            overflowed: false,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Event> {
+        if !self.in_frame {
+            if byte == 0 {
+                self.in_frame = true;
+                None
+            } else {
+                Some(Event::Text(byte))
+            }
+        } else if byte != 0 {
+            if self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflowed = true;
+            }
+
+            None
+        } else if self.len == 0 && !self.overflowed {
+            // Two zeros in a row, so this is the start of the frame
+            None
+        } else {
+            let result = if self.overflowed {
+                Err(Error::Length)
+            } else {
+                decode(&self.buf[..self.len])
+            };
+
+            self.len = 0;
+            self.overflowed = false;
+            self.in_frame = result.is_err();
+
+            Some(Event::Frame(result))
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
@@ -0,0 +1,149 @@
+use crate::Error;
+
+pub const BOT_STATE_ID: u8 = 1;
+
+/// The longest payload of any message
+pub const MAX_PAYLOAD_LEN: usize = 13 * 4;
+
+/// The state of the bot from one control loop update
+///
+/// Positions are in encoder ticks, velocities in ticks/ms, and distances
+/// in mm, with None when the sensor sees nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BotState {
+    pub left_pos: f32,
+    pub right_pos: f32,
+    pub left_velocity: f32,
+    pub right_velocity: f32,
+    pub left_power: f32,
+    pub right_power: f32,
+    pub left_target: f32,
+    pub right_target: f32,
+    pub linear_pos: f32,
+    pub spin_pos: f32,
+    pub front_distance: Option<f32>,
+    pub left_distance: Option<f32>,
+    pub right_distance: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message {
+    BotState(BotState),
+}
+
+impl Message {
+    pub fn id(&self) -> u8 {
+        match self {
+            Message::BotState(_) => BOT_STATE_ID,
+        }
+    }
+
+    /// Write the payload into `buf`, returning its length
+    pub(crate) fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut writer = Writer { buf, len: 0 };
+
+        match self {
+            Message::BotState(state) => {
+                writer.f32(state.left_pos)?;
+                writer.f32(state.right_pos)?;
+                writer.f32(state.left_velocity)?;
+                writer.f32(state.right_velocity)?;
+                writer.f32(state.left_power)?;
+                writer.f32(state.right_power)?;
+                writer.f32(state.left_target)?;
+                writer.f32(state.right_target)?;
+                writer.f32(state.linear_pos)?;
+                writer.f32(state.spin_pos)?;
+                writer.option_f32(state.front_distance)?;
+                writer.option_f32(state.left_distance)?;
+                writer.option_f32(state.right_distance)?;
+            }
+        }
+
+        Ok(writer.len)
+    }
+
+    /// Read the payload of a message with the given id
+    pub(crate) fn read(id: u8, buf: &[u8]) -> Result<Message, Error> {
+        let mut reader = Reader { buf };
+
+        let message = match id {
+            BOT_STATE_ID => Message::BotState(BotState {
+                left_pos: reader.f32()?,
+                right_pos: reader.f32()?,
+                left_velocity: reader.f32()?,
+                right_velocity: reader.f32()?,
+                left_power: reader.f32()?,
+                right_power: reader.f32()?,
+                left_target: reader.f32()?,
+                right_target: reader.f32()?,
+                linear_pos: reader.f32()?,
+                spin_pos: reader.f32()?,
+                front_distance: reader.option_f32()?,
+                left_distance: reader.option_f32()?,
+                right_distance: reader.option_f32()?,
+            }),
+            id => return Err(Error::UnknownMessage(id)),
+        };
+
+        if reader.buf.is_empty() {
+            Ok(message)
+        } else {
+            Err(Error::Length)
+        }
+    }
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn f32(&mut self, value: f32) -> Result<(), Error> {
+        self.bytes(&value.to_bits().to_le_bytes())
+    }
+
+    /// None is sent as NaN
+    fn option_f32(&mut self, value: Option<f32>) -> Result<(), Error> {
+        self.f32(value.unwrap_or(f32::NAN))
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn f32(&mut self) -> Result<f32, Error> {
+        if self.buf.len() < 4 {
+            return Err(Error::Length);
+        }
+
+        let (bytes, rest) = self.buf.split_at(4);
+        self.buf = rest;
+
+        let bits = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        Ok(f32::from_bits(bits))
+    }
+
+    fn option_f32(&mut self) -> Result<Option<f32>, Error> {
+        let value = self.f32()?;
+
+        if value.is_nan() {
+            Ok(None)
+        } else {
+            Ok(Some(value))
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use telemetry::{cobs, crc, decode, encode};
+use telemetry::{BotState, Decoder, Error, Event, Frame, Message};
+use telemetry::{MAX_ENCODED_LEN, MAX_FRAME_LEN};
+
+fn bot_state() -> BotState {
+    BotState {
+        left_pos: 1620.0,
+        right_pos: 1618.5,
+        left_velocity: 1.25,
+        right_velocity: -0.5,
+        left_power: 3000.0,
+        right_power: -1200.0,
+        left_target: 1.0,
+        right_target: 1.0,
+        linear_pos: 1619.25,
+        spin_pos: 0.75,
+        front_distance: Some(35.0),
+        left_distance: None,
+        right_distance: Some(53.0),
+    }
+}
+
+fn frame() -> Frame {
+    Frame {
+        time: 123_456,
+        message: Message::BotState(bot_state()),
+    }
+}
+
+fn encode_frame(frame: &Frame) -> Vec<u8> {
+    let mut buf = [0; MAX_FRAME_LEN];
+    let len = encode(frame, &mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+fn push_all(decoder: &mut Decoder, bytes: &[u8]) -> Vec<Event> {
+    bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+}
+
+#[test]
+fn crc_check_value() {
+    assert_eq!(crc::crc16(b"123456789"), 0x29b1);
+}
+
+#[test]
+fn cobs_round_trip() {
+    let inputs: Vec<Vec<u8>> = vec![
+        vec![],
+        vec![0],
+        vec![0, 0],
+        vec![1, 2, 0, 3],
+        (1..=254).collect(),
+        (0..=255).collect(),
+        (0..600).map(|i| (i % 7) as u8).collect(),
+    ];
+
+    for input in inputs {
+        let mut encoded =
+            vec![0; input.len() + cobs::max_overhead(input.len())];
+        let len = cobs::encode(&input, &mut encoded).unwrap();
+        assert!(encoded[..len].iter().all(|&b| b != 0));
+
+        let mut decoded = vec![0; input.len()];
+        let len = cobs::decode(&encoded[..len], &mut decoded).unwrap();
+        assert_eq!(&decoded[..len], &input[..]);
+    }
+}
+
+#[test]
+fn frame_round_trip() {
+    let bytes = encode_frame(&frame());
+
+    assert!(bytes.len() <= MAX_FRAME_LEN);
+    assert_eq!(bytes[0], 0);
+    assert_eq!(bytes[bytes.len() - 1], 0);
+    assert!(bytes[1..bytes.len() - 1].iter().all(|&b| b != 0));
+
+    assert_eq!(decode(&bytes[1..bytes.len() - 1]), Ok(frame()));
+}
+
+#[test]
+fn corrupted_frame_fails_crc() {
+    let mut bytes = encode_frame(&frame());
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0x01;
+    if bytes[middle] == 0 {
+        bytes[middle] = 0xaa;
+    }
+
+    let result = decode(&bytes[1..bytes.len() - 1]);
+    assert!(result == Err(Error::Crc) || result == Err(Error::Cobs));
+}
+
+#[test]
+fn encode_needs_room() {
+    let mut buf = [0; 10];
+    assert_eq!(encode(&frame(), &mut buf), Err(Error::BufferTooSmall));
+}
+
+#[test]
+fn decoder_splits_text_and_frames() {
+    let mut stream = b"Initializing\n".to_vec();
+    stream.extend(encode_frame(&frame()));
+    stream.extend(b">> plan go\n");
+    stream.extend(encode_frame(&frame()));
+    stream.extend(encode_frame(&frame()));
+    stream.extend(b"done\n");
+
+    let mut decoder = Decoder::new();
+    let events = push_all(&mut decoder, &stream);
+
+    let text: Vec<u8> = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Text(c) => Some(*c),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(text, b"Initializing\n>> plan go\ndone\n".to_vec());
+
+    let frames: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Frame(f) => Some(*f),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(frames, vec![Ok(frame()); 3]);
+}
+
+#[test]
+fn decoder_recovers_from_lost_byte() {
+    let mut first = encode_frame(&frame());
+    // Lose a byte from the middle
+    first.remove(first.len() / 2);
+
+    let mut stream = first;
+    stream.extend(encode_frame(&frame()));
+    stream.extend(b"ok\n");
+
+    let mut decoder = Decoder::new();
+    let events = push_all(&mut decoder, &stream);
+
+    let frames: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::Frame(f) => Some(*f),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].is_err());
+    assert_eq!(frames[1], Ok(frame()));
+    assert_eq!(
+        events[events.len() - 3..].to_vec(),
+        vec![Event::Text(b'o'), Event::Text(b'k'), Event::Text(b'\n'),]
+    );
+}
+
+#[test]
+fn decoder_rejects_long_frames() {
+    let mut stream = vec![0];
+    stream.extend(vec![1; MAX_ENCODED_LEN + 10]);
+    stream.push(0);
+    stream.extend(encode_frame(&frame()));
+
+    let mut decoder = Decoder::new();
+    let events = push_all(&mut decoder, &stream);
+
+    assert_eq!(
+        events,
+        vec![Event::Frame(Err(Error::Length)), Event::Frame(Ok(frame())),]
+    );
+}